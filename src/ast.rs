@@ -2,7 +2,7 @@ use std::{fmt::Display, rc::Rc};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{tokens::TokenType, utils::Span};
+use crate::{tokens::TokenType, units::Units, utils::Span};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum Operator {
@@ -56,6 +56,25 @@ impl Display for Operator {
     }
 }
 
+impl Operator {
+    /// The plain operator an assignment operator applies, ie. `+` for `+=`
+    pub fn without_assign(&self) -> Self {
+        match self {
+            Self::PlusEqual => Self::Plus,
+            Self::MinusEqual => Self::Minus,
+            Self::DivideEqual => Self::Divide,
+            Self::MultiplyEqual => Self::Multiply,
+            Self::ExponentEqual => Self::Exponent,
+            Self::BitAndEqual => Self::BitAnd,
+            Self::BitOrEqual => Self::BitOr,
+            Self::BitXorEqual => Self::BitXor,
+            Self::BitLeftShiftEqual => Self::BitLeftShift,
+            Self::BitRightShiftEqual => Self::BitRightShift,
+            operator => *operator,
+        }
+    }
+}
+
 impl From<TokenType> for Operator {    
     fn from(value: TokenType) -> Self {
         match value {
@@ -80,7 +99,7 @@ impl From<TokenType> for Operator {
     } 
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct Tree<'a> {
     pub(crate) ast: AST<'a>,
     pub(crate) span: Span,
@@ -92,6 +111,91 @@ impl<'a> Tree<'a> {
             ast, span,
         }
     }
+
+    /// The tree with each node on its own line, indented under its parent, along with its span:
+    ///
+    /// ```text
+    /// BinaryOp + [0:7]
+    ///   Number 1 [0:1]
+    ///   FunctionCall sin [4:10]
+    ///     Identifier x [8:9]
+    /// ```
+    pub fn dump(&self) -> String {
+        let mut output = String::new();
+        self.dump_into(&mut output, None, 0);
+        output
+    }
+
+    fn dump_into(&self, output: &mut String, label: Option<&str>, depth: usize) {
+        let mut children: Vec<(Option<&str>, &Tree)> = vec![];
+        let node = match &self.ast {
+            AST::BinaryOp { lhs, rhs, op } => {
+                children.extend([(None, &**lhs), (None, &**rhs)]);
+                format!("BinaryOp {op}")
+            },
+            AST::UnaryOp { rhs, op } => {
+                children.push((None, rhs));
+                format!("UnaryOp {op}")
+            },
+            AST::Number { value } => format!("Number {value}"),
+            AST::Identifier { name } => format!("Identifier {name}"),
+            AST::DeclareAssign { identifier, value, .. } => {
+                children.push((None, value));
+                format!("DeclareAssign {identifier}")
+            },
+            AST::Declare { identifier, .. } => format!("Declare {identifier}"),
+            AST::Assign { identifier, value, .. } => {
+                children.push((None, value));
+                format!("Assign {identifier}")
+            },
+            AST::AssignOp { identifier, value, operator, .. } => {
+                children.push((None, value));
+                format!("AssignOp {identifier} {operator}")
+            },
+            AST::Output { value } => {
+                children.push((None, value));
+                "Output".to_owned()
+            },
+            AST::FunctionCall { name, expressions } => {
+                children.extend(expressions.iter().map(|expression| (None, &**expression)));
+                format!("FunctionCall {name}")
+            },
+            AST::FunctionDecl { name, arguments, body } => {
+                children.push((None, body));
+                format!("FunctionDecl {name}({})", arguments.join(", "))
+            },
+            AST::FunctionRef { name } => format!("FunctionRef {name}"),
+            AST::Delete { name } => format!("Delete {name}"),
+            AST::Print { expressions } => {
+                children.extend(expressions.iter().map(|expression| (None, &**expression)));
+                "Print".to_owned()
+            },
+            AST::String { contents } => format!("String {contents:?}"),
+            AST::Vector { elements } => {
+                children.extend(elements.iter().map(|element| (None, &**element)));
+                "Vector".to_owned()
+            },
+            AST::Unit { units } => format!("Unit {units}"),
+            AST::Convert { value, units } => {
+                children.push((None, value));
+                format!("Convert to {units}")
+            },
+            AST::Series { operator, index, from, to, body } => {
+                children.extend([(Some("from"), &**from), (Some("to"), &**to), (Some("body"), &**body)]);
+                let name = if *operator == Operator::Multiply { "prod" } else { "sum" };
+                format!("Series {name} {index}")
+            },
+            AST::Null => "Null".to_owned(),
+        };
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        let label = label.map_or(String::new(), |label| format!("{label}: "));
+        output.push_str(&format!("{}{label}{node} {}", "  ".repeat(depth), self.span));
+        for (label, child) in children {
+            child.dump_into(output, label, depth + 1);
+        }
+    }
 }
 
 impl Display for Tree<'_> {
@@ -100,7 +204,9 @@ impl Display for Tree<'_> {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Serialized with the name of the variant in `type`, ie. `{"type": "Number", "value": 1.0}`
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
 pub enum AST<'a> {
     BinaryOp { 
         lhs: Rc<Tree<'a>>,
@@ -160,6 +266,11 @@ pub enum AST<'a> {
         body: Rc<Tree<'a>>,
     },
 
+    /// A function passed by name rather than called, ie. the `f` in `root(f, 0, 2)`
+    FunctionRef {
+        name: &'a str,
+    },
+
     Delete {
         name: &'a str,
     },
@@ -172,6 +283,32 @@ pub enum AST<'a> {
         contents: String,
     },
 
+    /// A vector or matrix literal: `[1, 2]`, `[[1, 2], [3, 4]]`
+    Vector {
+        elements: Vec<Rc<Tree<'a>>>,
+    },
+
+    /// A unit on its own, ie. the `km` in `5 km`, which is a quantity of magnitude 1
+    Unit {
+        units: Units,
+    },
+
+    /// Conversion of a quantity to other units: `60 mph to m/s`
+    Convert {
+        value: Rc<Tree<'a>>,
+        units: Units,
+    },
+
+    /// A sum or product over a range, ie. `sum(i, 1, 10, i**2)`. `index` is bound to each integer from `from` to `to`
+    /// in turn while `body` is evaluated, and `operator` combines the terms
+    Series {
+        operator: Operator,
+        index: &'a str,
+        from: Rc<Tree<'a>>,
+        to: Rc<Tree<'a>>,
+        body: Rc<Tree<'a>>,
+    },
+
     Null,
 }
 
@@ -187,8 +324,15 @@ impl Display for AST<'_> {
             Self::Output { value } => write!(f, "*{value}*"),
             
             Self::Identifier { name } => write!(f, "{name}"),
+            Self::FunctionRef { name } => write!(f, "{name}"),
             Self::String { contents } => write!(f, "\"{contents}\""),
             Self::Delete { name } => write!(f, "(delete {name})"),
+            Self::Unit { units } => write!(f, "{units}"),
+            Self::Convert { value, units } => write!(f, "({value} to {units})"),
+            Self::Series { operator, index, from, to, body } => {
+                let name = if *operator == Operator::Multiply { "prod" } else { "sum" };
+                write!(f, "{name}({index}, {from}, {to}, {body})")
+            }
 
             Self::Assign { identifier, value, .. } => write!(f, "({identifier} = {value})"),
             Self::AssignOp { operator, identifier, value, .. } => write!(f, "({identifier} {operator} {value})"),
@@ -208,6 +352,11 @@ impl Display for AST<'_> {
                 write!(f, "{name}({})", arguments)
             }
 
+            Self::Vector { elements } => {
+                let elements = elements.iter().map(|element| format!("{element}")).collect::<Vec<_>>();
+                write!(f, "[{}]", elements.join(", "))
+            }
+
             Self::Print { expressions } => {
                 let mut arguments = String::new();
                 for expr in expressions {
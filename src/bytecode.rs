@@ -1,242 +1,314 @@
-use std::{borrow::Borrow, collections::HashMap, rc::Rc};
-use crate::{ast::{Tree, AST}, errors::Error, instruction::{Instruction, Value}, parser::Parser};
+use std::{borrow::Borrow, rc::Rc};
+use crate::{ast::{Tree, AST}, errors::Error, diagnostics::Diagnostic, instruction::{Instruction, Value}, optimize::{optimize, OptLevel}, lint::{Lint, Linter}, parser::{Parser, Symbols}, units::Quantity, utils::Span};
+
+/// Instructions, along with the span of the source each of them was generated from
+#[derive(Debug, Default)]
+pub struct Code<'a> {
+    pub(crate) instructions: Vec<Instruction<'a>>,
+    pub(crate) spans: Vec<Span>,
+}
+
+impl<'a> Code<'a> {
+    pub fn single(instruction: Instruction<'a>, span: Span) -> Self {
+        Self { instructions: vec![instruction], spans: vec![span] }
+    }
+
+    pub fn push(&mut self, instruction: Instruction<'a>, span: Span) {
+        self.instructions.push(instruction);
+        self.spans.push(span);
+    }
+
+    pub fn append(&mut self, mut other: Code<'a>) {
+        self.instructions.append(&mut other.instructions);
+        self.spans.append(&mut other.spans);
+    }
+}
 
 pub struct Bytecode<'a> {
     parser: Parser<'a>,
+    opt_level: OptLevel,
+    /// The span of the source each generated instruction comes from, so that runtime errors can show where they
+    /// happened. Instructions from earlier sources, like functions declared on another line of the repl, have none
+    spans: Vec<Option<Span>>,
+    linter: Linter<'a>,
+    warnings: Vec<Diagnostic>,
 }
 
 impl<'a> Bytecode<'a> {
     pub fn new(parser: Parser<'a>) -> Self {
-        Self { parser }
+        Self { parser, opt_level: OptLevel::default(), spans: vec![], linter: Linter::default(), warnings: vec![] }
     }
 
-    pub fn generate_bytecode(&mut self) -> Vec<Instruction<'a>> {
-        let mut complete_bytecode = vec![];
-        loop {
-            match self.parser.next_expression() {
-                Ok(tree) => {
-                    let mut instructions = Self::traverse(&tree);
-
-                    if instructions.get(0) == Some(&Instruction::CompileError) {
-                        complete_bytecode.clear();
-                        complete_bytecode.push(Instruction::CompileError);
-                    } else {
-                        // Check for recursive calling
-                        if let Instruction::FunctionDecl { name } = instructions[0] {
-                            for instruction in &instructions {
-                                // This function calls another function
-                                if let Instruction::FunctionCall { name: called } = instruction {
-                                    for instruction_test in &complete_bytecode {
-                                        if let Instruction::FunctionCall { name: other_calling } = instruction_test {
-                                            // If another function calls this function, we are experiencing recursion
-                                            if other_calling == &name {
-                                                let error = 
-                                                    Error::PError { 
-                                                        message: 
-                                                        format!("The function {name} is calling the function {called}, which is again calling {name}. This recursion is not allowed"), 
-                                                        span: tree.span 
-                                                    };
-                                                println!("{error}");
-
-                                                complete_bytecode.clear();
-                                                complete_bytecode.push(Instruction::CompileError);
-                            
-                                                return complete_bytecode;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        complete_bytecode.append(&mut instructions);
-                    }
+    /// The span table of the last bytecode generated, one entry for each instruction
+    pub fn spans(&self) -> Vec<Option<Span>> {
+        self.spans.clone()
+    }
+
+    /// The warnings about the source of the last bytecode generated. The repl does not check for them
+    pub fn warnings(&self) -> Vec<Diagnostic> {
+        self.warnings.clone()
+    }
+
+    /// Turn off the lints that were allowed, ie. with `--allow unused-variable`
+    pub fn set_allowed_lints(&mut self, allowed: Vec<Lint>) {
+        self.linter = Linter::new(allowed);
+    }
+
+    /// Choose how much the bytecode of each statement is optimised
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.opt_level = opt_level;
+    }
+
+    /// Generate the bytecode of a statement, optimised if it was asked for
+    fn statement(&self, tree: &Rc<Tree<'a>>) -> Result<Code<'a>, Error> {
+        let code = Self::traverse(tree);
+
+        // Check for recursion. Recursion makes no sense with single statement functions
+        if let AST::FunctionDecl { name, body, .. } = &tree.ast {
+            for instruction in &code.instructions {
+                let called_name = match instruction {
+                    Instruction::FunctionCall { name, .. } => *name,
+                    // Passing itself to a function like `root` is recursion too
+                    Instruction::Load { value: Value::Function(name) } => name.as_str(),
+                    _ => continue,
+                };
+                if name == &called_name {
+                    return Err(Error::CRecursion { message: format!("Function `{name}` cannot call itself recursively!"), span: body.span });
                 }
-                Err(error) => {
-                    complete_bytecode.clear();
-                    complete_bytecode.push(Instruction::CompileError);    
-                    if error != Error::NoResult {
-                        println!("{error}");
+            }
+        }
+        match self.opt_level {
+            OptLevel::O0 => Ok(code),
+            OptLevel::O1 => Ok(optimize(code)),
+        }
+    }
+
+    /// Check that a new function does not call a function which calls it back
+    fn check_mutual_recursion(instructions: &[Instruction<'a>], previous: &[Instruction<'a>], span: Span) -> Result<(), Error> {
+        let Some(&Instruction::FunctionDecl { name }) = instructions.first() else {
+            return Ok(());
+        };
+        for instruction in instructions {
+            // This function calls another function
+            if let Instruction::FunctionCall { name: called, .. } = instruction {
+                for instruction_test in previous {
+                    if let Instruction::FunctionCall { name: other_calling, .. } = instruction_test {
+                        // If another function calls this function, we are experiencing recursion
+                        if other_calling == &name {
+                            return Err(Error::CRecursion { 
+                                message: 
+                                format!("The function {name} is calling the function {called}, which is again calling {name}. This recursion is not allowed"), 
+                                span,
+                            });
+                        }
                     }
                 }
             }
+        }
+        Ok(())
+    }
 
-            if self.parser.eof {
+    /// Generate the bytecode of every statement, or every error found in them
+    pub fn generate_bytecode(&mut self) -> Result<Vec<Instruction<'a>>, Vec<Error>> {
+        let mut complete_bytecode = vec![];
+        let mut errors = vec![];
+        self.spans.clear();
+        loop {
+            // Parsing carries on after an error, so that every error in the source is reported at once
+            let result = self.parser.next_expression().inspect(|tree| self.linter.check(tree, self.parser.lowered_calls())).and_then(|tree| {
+                let code = self.statement(&tree)?;
+                Self::check_mutual_recursion(&code.instructions, &complete_bytecode, tree.span)?;
+                Ok(code)
+            });
+            match result {
+                Ok(mut code) => {
+                    complete_bytecode.append(&mut code.instructions);
+                    self.spans.extend(code.spans.into_iter().map(Some));
+                },
+                // The source is empty
+                Err(Error::NoResult) => (),
+                Err(error) => errors.push(error),
+            }
+
+            if self.parser.finished() {
                 break;
             }
         }
-        complete_bytecode
+        if !errors.is_empty() {
+            self.spans.clear();
+            return Err(errors);
+        }
+        self.warnings = self.linter.finish();
+        Ok(complete_bytecode)
     }
 
-    pub fn generate_fn_bytecode(&mut self, old_fn_bytecode: Vec<Instruction<'a>>) -> (Vec<Instruction<'a>>, Vec<Instruction<'a>>) {
+    /// Generate the bytecode of a line of the repl after the functions declared before it, along with the bytecode of
+    /// all the functions
+    pub fn generate_fn_bytecode(&mut self, old_fn_bytecode: Vec<Instruction<'a>>) -> Result<(Vec<Instruction<'a>>, Vec<Instruction<'a>>), Vec<Error>> {
         let mut complete_bytecode = old_fn_bytecode.clone();
         let mut function_bytecode = old_fn_bytecode;
+        let mut errors = vec![];
+        self.spans = vec![None; complete_bytecode.len()];
         loop {
             // This function is only used by the repl
-            match self.parser.next_expression_repl() {
-                Ok(tree) => {
-                    let mut instructions = Self::traverse(&tree);
-                    if instructions.get(0) == Some(&Instruction::CompileError) {
-                        complete_bytecode.clear();
-                        complete_bytecode.push(Instruction::CompileError);
-                        function_bytecode.clear();
-                    } else {
-                        if let Instruction::FunctionDecl { name } = instructions[0] {
-                            // Check for recursive calling
-                            for instruction in &instructions {
-                                // This function calls another function
-                                if let Instruction::FunctionCall { name: called } = instruction {
-                                    for instruction_test in &function_bytecode {
-                                        if let Instruction::FunctionCall { name: other_calling } = instruction_test {
-                                            // If another function calls this function, we are experiencing recursion
-                                            if other_calling == &name {
-                                                let error = 
-                                                    Error::PError { 
-                                                        message: 
-                                                        format!("The function {name} is calling the function {called}, which is again calling {name}. This recursion is not allowed"), 
-                                                        span: tree.span 
-                                                    };
-                                                println!("{error}");
-                                                complete_bytecode.clear();
-                                                complete_bytecode.push(Instruction::CompileError);
-                                                function_bytecode.clear();    
-                            
-                                                return (complete_bytecode, function_bytecode);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            function_bytecode.append(&mut (instructions.clone()));
-                        }
-                        complete_bytecode.append(&mut instructions);
-                    }
-                }
-                Err(error) => {
-                    complete_bytecode.clear();
-                    complete_bytecode.push(Instruction::CompileError);
-                    function_bytecode.clear();    
-                    if error != Error::NoResult {
-                        println!("{error}");
+            let result = self.parser.next_expression_repl().and_then(|tree| {
+                let code = self.statement(&tree)?;
+                Self::check_mutual_recursion(&code.instructions, &function_bytecode, tree.span)?;
+                Ok(code)
+            });
+            match result {
+                Ok(mut code) => {
+                    if let Some(Instruction::FunctionDecl { .. }) = code.instructions.first() {
+                        function_bytecode.extend(code.instructions.iter().cloned());
                     }
-                }
+                    complete_bytecode.append(&mut code.instructions);
+                    self.spans.extend(code.spans.into_iter().map(Some));
+                },
+                Err(Error::NoResult) => (),
+                Err(error) => errors.push(error),
             }
 
-            if self.parser.eof {
+            if self.parser.finished() {
                 break;
             }
         }
+        if !errors.is_empty() {
+            self.spans.clear();
+            return Err(errors);
+        }
 
-        (complete_bytecode, function_bytecode)
+        Ok((complete_bytecode, function_bytecode))
     }
 
-    pub fn get_symbols(self) -> (HashMap<&'a str, (usize, bool)>, HashMap<&'a str, bool>) {
-        (self.parser.function_symbols, self.parser.symbols)
+    pub fn get_symbols(self) -> Symbols<'a> {
+        self.parser.into_symbols()
     }
 
-    fn traverse(tree: &Rc<Tree<'a>>) -> Vec<Instruction<'a>> {
+    fn traverse(tree: &Rc<Tree<'a>>) -> Code<'a> {
+        let span = tree.span;
         match tree.ast.borrow() {
             AST::Number { value } => {
-                vec![Instruction::Load { value: Value::Number(*value) }]
+                Code::single(Instruction::Load { value: Value::Number(*value) }, span)
             },
 
             AST::BinaryOp { lhs, rhs, op } => {
-                let mut instructions = Self::traverse(lhs);
-                instructions.append(&mut Self::traverse(rhs));
-                instructions.push(Instruction::Binary { operator: *op });
-                instructions
+                let mut code = Self::traverse(lhs);
+                code.append(Self::traverse(rhs));
+                code.push(Instruction::Binary { operator: *op }, span);
+                code
             },
 
             AST::UnaryOp { rhs, op } => {
-                let mut instructions = Self::traverse(rhs);
-                instructions.push(Instruction::Unary { operator: *op });
-                instructions
+                let mut code = Self::traverse(rhs);
+                code.push(Instruction::Unary { operator: *op }, span);
+                code
             },
 
             AST::Declare { identifier, .. } => {
-                vec![Instruction::LoadSymbolName { name: identifier }]
+                Code::single(Instruction::LoadSymbolName { name: identifier }, span)
             }
 
             AST::DeclareAssign { identifier, value, .. } => {
-                let mut instructions = Self::traverse(value);
-                instructions.push(Instruction::LoadSymbol { name: identifier });
-                instructions
+                let mut code = Self::traverse(value);
+                code.push(Instruction::LoadSymbol { name: identifier }, span);
+                code
             }
 
             AST::Assign { identifier, value, .. } => {
-                let mut instructions = Self::traverse(value);
-                instructions.push(Instruction::ReloadSymbol { name: identifier });
-                instructions
+                let mut code = Self::traverse(value);
+                code.push(Instruction::ReloadSymbol { name: identifier }, span);
+                code
             }
 
             AST::AssignOp { identifier, operator, value, .. } => {
-                let mut instructions = Self::traverse(value);
-                instructions.push(Instruction::ReloadSymbolOp { name: identifier });
-                instructions.push(Instruction::OData { operator: *operator });
-                instructions
+                let mut code = Self::traverse(value);
+                code.push(Instruction::ReloadSymbolOp { name: identifier }, span);
+                code.push(Instruction::OData { operator: *operator }, span);
+                code
             }
             
             AST::Identifier { name } => {
-                vec![Instruction::CallSymbol { name }]
+                Code::single(Instruction::CallSymbol { name }, span)
+            }
+
+            AST::FunctionRef { name } => {
+                Code::single(Instruction::Load { value: Value::Function((*name).to_owned()) }, span)
             }
 
             AST::Output { value } => {
-                let mut instructions = Self::traverse(value);
-                instructions.push(Instruction::Output);
-                instructions
+                let mut code = Self::traverse(value);
+                code.push(Instruction::Output, span);
+                code
             }
 
             AST::FunctionCall { name, expressions } => {
-                let mut instructions = vec![];
+                let mut code = Code::default();
                 for expr in expressions {
-                    instructions.extend(Self::traverse(expr));
+                    code.append(Self::traverse(expr));
                 }
-                instructions.push(Instruction::FunctionCall { name });
-                instructions
+                code.push(Instruction::FunctionCall { name, arguments: expressions.len() }, span);
+                code
             }
 
             AST::FunctionDecl { name, arguments, body } => {
-                let mut instructions = vec![Instruction::FunctionDecl { name }];
+                let mut code = Code::single(Instruction::FunctionDecl { name }, span);
                
-                instructions.push(Instruction::UData { number: arguments.len() });
-                instructions.push(Instruction::UData { number: 0 });
+                code.push(Instruction::UData { number: arguments.len() }, span);
+                code.push(Instruction::UData { number: 0 }, span);
 
-                instructions.extend(arguments.iter().map(|name: &&str| Instruction::ArgumentName { name }));
-                instructions.extend(Self::traverse(body));
-                
-                let end = instructions.len() - 1;
-                instructions[2] = Instruction::UData { number: end - 2 };
-
-                // Check for recursion. Recursion makes no sense with single statement functions
-                for instruction in &instructions {
-                    if let Instruction::FunctionCall { name: called_name } = instruction {
-                        if name == called_name {
-                            let span = body.span;
-                            let error = Error::PError { message: format!("Function `{name}` cannot call itself recursively!"), span };
-                            println!("{error}");
-                            instructions[0] = Instruction::CompileError;
-                            break;
-                        }
-                    }
+                for name in arguments {
+                    code.push(Instruction::ArgumentName { name }, span);
                 }
-
-                instructions
+                code.append(Self::traverse(body));
+                
+                let end = code.instructions.len() - 1;
+                code.instructions[2] = Instruction::UData { number: end - 2 };
+                code
             }
 
-            AST::Delete { name } => vec![Instruction::Delete { name }],
+            AST::Delete { name } => Code::single(Instruction::Delete { name }, span),
 
-            AST::Null => vec![Instruction::Null],
+            AST::Null => Code::single(Instruction::Null, span),
 
             AST::Print { expressions } => {
-                let mut instructions = vec![];
+                let mut code = Code::default();
                 for expr in expressions {
-                    instructions.extend(Self::traverse(expr));
+                    code.append(Self::traverse(expr));
                 }
-                instructions.push(Instruction::Print { depth: expressions.len() });
-                instructions
+                code.push(Instruction::Print { depth: expressions.len() }, span);
+                code
             }
 
             AST::String { contents } => {
-                vec![Instruction::Load { value: Value::String((*contents).to_owned()) }]
+                Code::single(Instruction::Load { value: Value::String((*contents).to_owned()) }, span)
+            }
+
+            AST::Vector { elements } => {
+                let mut code = Code::default();
+                for element in elements {
+                    code.append(Self::traverse(element));
+                }
+                code.push(Instruction::Vector { length: elements.len() }, span);
+                code
+            }
+
+            AST::Unit { units } => {
+                Code::single(Instruction::Load { value: Value::Quantity(Quantity::new(1.0, units.clone())) }, span)
+            }
+
+            AST::Convert { value, units } => {
+                let mut code = Self::traverse(value);
+                code.push(Instruction::Convert { units: units.clone() }, span);
+                code
+            }
+
+            AST::Series { operator, index, from, to, body } => {
+                let mut code = Self::traverse(from);
+                code.append(Self::traverse(to));
+                let body = Self::traverse(body);
+                code.push(Instruction::Series { index, operator: *operator, length: body.instructions.len() }, span);
+                code.append(body);
+                code
             }
 
             // Unreachable
@@ -0,0 +1,149 @@
+use std::f64::consts::SQRT_2;
+
+use crate::{functions::{evaluate, expect_function, expect_number, Arity, Builtin}, instruction::Value, vm::{VMError, VM}};
+
+/// The error allowed over the whole interval of an integral
+const TOLERANCE: f64 = 1e-10;
+
+/// How many times an interval can be halved before the integral is given up on
+const MAX_DEPTH: usize = 50;
+
+/// How many times an interval is halved before its estimate can be accepted, so that a few points that happen to
+/// agree, like those of `floor(x)` at whole numbers, do not end the search early
+const MIN_DEPTH: usize = 4;
+
+/// How closely two estimates of an integral have to agree, relative to its size
+const AGREEMENT: f64 = 1e-6;
+
+/// How many times the function can be evaluated for one integral
+const MAX_EVALUATIONS: usize = 100_000;
+
+/// A point of the function being integrated, and its value
+#[derive(Clone, Copy)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+/// The function being integrated, and how many times it has been evaluated
+struct Integrand<'v, 'a> {
+    vm: &'v mut VM<'a>,
+    function: &'v str,
+    evaluations: usize,
+}
+
+impl Integrand<'_, '_> {
+    fn point(&mut self, x: f64) -> Result<Point, VMError> {
+        self.evaluations += 1;
+        if self.evaluations > MAX_EVALUATIONS {
+            return Err(VMError::ErrString(format!("The function `integrate` gave up on `{}` after {MAX_EVALUATIONS} evaluations without converging!", self.function)));
+        }
+        Ok(Point { x, y: evaluate(self.vm, self.function, "integrate", x)? })
+    }
+}
+
+/// Simpson's rule over `[a, b]`, given the middle point `m`
+fn simpson(a: Point, m: Point, b: Point) -> f64 {
+    (b.x - a.x) / 6.0 * (a.y + 4.0 * m.y + b.y)
+}
+
+/// Adaptive Simpson's method: halve the interval until both halves agree with the estimate for the whole of it
+fn adaptive_simpson(integrand: &mut Integrand, (a, m, b): (Point, Point, Point), whole: f64, tolerance: f64, depth: usize) -> Result<f64, VMError> {
+    let left_middle = integrand.point((a.x + m.x) / 2.0)?;
+    let right_middle = integrand.point((m.x + b.x) / 2.0)?;
+    let left = simpson(a, left_middle, m);
+    let right = simpson(m, right_middle, b);
+    let delta = left + right - whole;
+    // At the last depth the halves only need to agree as well as the whole integral has to, which they do around a
+    // jump since the interval is so small by then
+    let accepted = match depth {
+        0 if delta.abs() <= TOLERANCE => true,
+        0 => return Err(VMError::ErrString(format!("The function `integrate` found that the integral of `{}` does not converge near {}!", integrand.function, m.x))),
+        depth => depth <= MAX_DEPTH - MIN_DEPTH && delta.abs() <= 15.0 * tolerance,
+    };
+    if accepted {
+        // Richardson extrapolation of the two estimates
+        return Ok(left + right + delta / 15.0);
+    }
+    Ok(adaptive_simpson(integrand, (a, left_middle, m), left, tolerance / 2.0, depth - 1)?
+        + adaptive_simpson(integrand, (m, right_middle, b), right, tolerance / 2.0, depth - 1)?)
+}
+
+/// The integral over `[a, b]`, with an error of at most `tolerance`
+fn integral(integrand: &mut Integrand, a: f64, b: f64, tolerance: f64) -> Result<f64, VMError> {
+    let a = integrand.point(a)?;
+    let m = integrand.point((a.x + b) / 2.0)?;
+    let b = integrand.point(b)?;
+    adaptive_simpson(integrand, (a, m, b), simpson(a, m, b), tolerance, MAX_DEPTH)
+}
+
+fn integrate(vm: &mut VM, values: &[Value]) -> Result<Value, VMError> {
+    let function = expect_function(&values[0], "integrate")?;
+    let a = expect_number(&values[1], "integrate")?;
+    let b = expect_number(&values[2], "integrate")?;
+    if !a.is_finite() || !b.is_finite() {
+        return Err(VMError::OutOfDomain(format!("The function `integrate` expected finite bounds but found {a} and {b}!")));
+    }
+    if a == b {
+        return Ok(Value::Number(0.0));
+    }
+    let mut integrand = Integrand { vm, function: &function, evaluations: 0 };
+    let result = integral(&mut integrand, a, b, TOLERANCE)?;
+    if !result.is_finite() {
+        return Err(VMError::ErrString(format!("The function `integrate` found that the integral of `{function}` between {a} and {b} does not converge!")));
+    }
+    // The points of a jump can agree by chance, so the integral is taken again over points that are placed
+    // differently, split where the first points never are
+    let split = a + (b - a) * (SQRT_2 - 1.0);
+    let check = integral(&mut integrand, a, split, TOLERANCE / 2.0)? + integral(&mut integrand, split, b, TOLERANCE / 2.0)?;
+    if (result - check).abs() > AGREEMENT * result.abs().max(1.0) {
+        return Err(VMError::ErrString(format!(
+            "The function `integrate` found that the integral of `{function}` between {a} and {b} does not converge, since it gave both {result} and {check}! Help: split the integral where the function jumps"
+        )));
+    }
+    Ok(Value::Number(result))
+}
+
+/// Central differences with shrinking steps, extrapolated to a step of zero (Ridders' method)
+fn derivative(vm: &mut VM, values: &[Value]) -> Result<Value, VMError> {
+    const SHRINK: f64 = 1.4;
+    const STEPS: usize = 10;
+    let function = expect_function(&values[0], "derivative")?;
+    let x = expect_number(&values[1], "derivative")?;
+
+    let mut difference = |h: f64| -> Result<f64, VMError> {
+        Ok((evaluate(vm, &function, "derivative", x + h)? - evaluate(vm, &function, "derivative", x - h)?) / (2.0 * h))
+    };
+
+    let mut h = 0.1 * x.abs().max(1.0);
+    // `table[j][i]` is the estimate with step `i`, extrapolated `j` times
+    let mut table = [[0.0; STEPS]; STEPS];
+    table[0][0] = difference(h)?;
+    let (mut result, mut error) = (table[0][0], f64::INFINITY);
+    for i in 1..STEPS {
+        h /= SHRINK;
+        table[0][i] = difference(h)?;
+        let mut factor = SHRINK * SHRINK;
+        for j in 1..=i {
+            table[j][i] = (table[j - 1][i] * factor - table[j - 1][i - 1]) / (factor - 1.0);
+            factor *= SHRINK * SHRINK;
+            let estimate = (table[j][i] - table[j - 1][i]).abs().max((table[j][i] - table[j - 1][i - 1]).abs());
+            if estimate <= error {
+                (result, error) = (table[j][i], estimate);
+            }
+        }
+        // Stop once the extrapolation starts getting worse
+        if (table[i][i] - table[i - 1][i - 1]).abs() >= 2.0 * error {
+            break;
+        }
+    }
+    if !result.is_finite() {
+        return Err(VMError::ErrString(format!("The function `derivative` found that `{function}` is not differentiable at {x}!")));
+    }
+    Ok(Value::Number(result))
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 2] = [
+    ("integrate", (Arity::exactly(3), Builtin::WithVM(integrate))),
+    ("derivative", (Arity::exactly(2), Builtin::WithVM(derivative))),
+];
@@ -0,0 +1,312 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ast::Operator, functions::{expect_integer, Arity, Builtin}, instruction::Value, units::Units, vm::VMError};
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// The earliest and latest years a date can be in, so that it can always be written as `YYYY-MM-DD`
+const YEARS: (i64, i64) = (0, 9999);
+
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+/// A moment in UTC, in whole seconds since 1970-01-01, on the proleptic Gregorian calendar
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Date {
+    pub(crate) seconds: i64,
+}
+
+/// A length of time in seconds, ie. the difference between two dates or `3h 20min`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Duration {
+    pub(crate) seconds: f64,
+}
+
+/// The number of days from 1970-01-01 to a date (Howard Hinnant's `days_from_civil`)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The year, month and day of a number of days from 1970-01-01 (Howard Hinnant's `civil_from_days`)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    (if month <= 2 { year_of_era + era * 400 + 1 } else { year_of_era + era * 400 }, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parse a fixed number of digits from the front of a string
+fn digits(string: &mut &str, count: usize) -> Option<i64> {
+    let (number, rest) = (string.get(..count)?, string.get(count..)?);
+    if !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    *string = rest;
+    number.parse().ok()
+}
+
+/// Take a separator from the front of a string
+fn separator(string: &mut &str, separators: &[char]) -> Option<()> {
+    *string = string.strip_prefix(separators)?;
+    Some(())
+}
+
+impl Date {
+    fn new(seconds: i64) -> Result<Self, String> {
+        let first = days_from_civil(YEARS.0, 1, 1) * SECONDS_PER_DAY;
+        let last = days_from_civil(YEARS.1 + 1, 1, 1) * SECONDS_PER_DAY;
+        if !(first..last).contains(&seconds) {
+            return Err(format!("Dates must be between the years {} and {}!", YEARS.0, YEARS.1));
+        }
+        Ok(Self { seconds })
+    }
+
+    /// Parse an ISO 8601 date, `2026-10-17`, with an optional time, `2026-10-17T08:30`, `2026-10-17 08:30:15`, and an
+    /// optional offset from UTC, `Z` or `+02:00`
+    pub fn parse(string: &str) -> Result<Self, String> {
+        let error = || format!("Expected a date like `2026-10-17` or `2026-10-17T08:30:00Z` but found `{string}`!");
+        let mut rest = string.trim();
+        let (year, month, day) = (|| {
+            let year = digits(&mut rest, 4)?;
+            separator(&mut rest, &['-'])?;
+            let month = digits(&mut rest, 2)?;
+            separator(&mut rest, &['-'])?;
+            Some((year, month, digits(&mut rest, 2)?))
+        })().ok_or_else(error)?;
+        if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+            return Err(format!("The date `{string}` does not exist!"));
+        }
+
+        let mut time = 0;
+        if separator(&mut rest, &['T', ' ']).is_some() {
+            let (hours, minutes, seconds) = (|| {
+                let hours = digits(&mut rest, 2)?;
+                separator(&mut rest, &[':'])?;
+                let minutes = digits(&mut rest, 2)?;
+                let seconds = match separator(&mut rest, &[':']) {
+                    Some(()) => digits(&mut rest, 2)?,
+                    None => 0,
+                };
+                Some((hours, minutes, seconds))
+            })().ok_or_else(error)?;
+            if hours > 23 || minutes > 59 || seconds > 59 {
+                return Err(format!("The time in `{string}` does not exist!"));
+            }
+            time = hours * 3600 + minutes * 60 + seconds;
+
+            // Times are stored in UTC, so an offset is taken away
+            if let Some(sign) = rest.chars().next().filter(|sign| matches!(sign, '+' | '-')) {
+                rest = &rest[1..];
+                let (hours, minutes) = (|| {
+                    let hours = digits(&mut rest, 2)?;
+                    separator(&mut rest, &[':'])?;
+                    Some((hours, digits(&mut rest, 2)?))
+                })().ok_or_else(error)?;
+                let offset = hours * 3600 + minutes * 60;
+                time -= if sign == '+' { offset } else { -offset };
+            } else if let Some(stripped) = rest.strip_prefix('Z') {
+                rest = stripped;
+            }
+        }
+        if !rest.is_empty() {
+            return Err(error());
+        }
+        Self::new(days_from_civil(year, month, day) * SECONDS_PER_DAY + time)
+    }
+
+    pub fn days(&self) -> i64 {
+        self.seconds.div_euclid(SECONDS_PER_DAY)
+    }
+
+    /// The name of the day of the week. 1970-01-01 was a Thursday
+    pub fn weekday(&self) -> &'static str {
+        WEEKDAYS[(self.days() + 3).rem_euclid(7) as usize]
+    }
+
+    /// Move the date by a length of time, which must be a whole number of seconds. Rounding errors from converting
+    /// between units, ie. `1.2857142857 weeks`, are allowed
+    pub fn add(&self, seconds: f64) -> Result<Self, String> {
+        let rounded = seconds.round();
+        if (seconds - rounded).abs() > 1e-9 * seconds.abs().max(1.0) || !seconds.is_finite() {
+            return Err(format!("Dates are precise to a second, so {seconds} s cannot be added to one!"));
+        }
+        if seconds.abs() > (YEARS.1 - YEARS.0 + 1) as f64 * 366.0 * SECONDS_PER_DAY as f64 {
+            return Err(format!("Dates must be between the years {} and {}!", YEARS.0, YEARS.1));
+        }
+        Self::new(self.seconds + rounded as i64)
+    }
+
+    /// Move the date by calendar months, keeping the time of day. The day is clamped to the end of a shorter month, so
+    /// a month after 2026-01-31 is 2026-02-28
+    pub fn add_months(&self, months: i64) -> Result<Self, String> {
+        let (year, month, day) = civil_from_days(self.days());
+        let index = year * 12 + (month - 1) + months;
+        let (year, month) = (index.div_euclid(12), index.rem_euclid(12) + 1);
+        if !(YEARS.0..=YEARS.1).contains(&year) {
+            return Err(format!("Dates must be between the years {} and {}!", YEARS.0, YEARS.1));
+        }
+        let day = day.min(days_in_month(year, month));
+        Self::new(days_from_civil(year, month, day) * SECONDS_PER_DAY + self.seconds.rem_euclid(SECONDS_PER_DAY))
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = civil_from_days(self.days());
+        write!(f, "{year:04}-{month:02}-{day:02}")?;
+        let time = self.seconds.rem_euclid(SECONDS_PER_DAY);
+        if time != 0 {
+            write!(f, "T{:02}:{:02}:{:02}Z", time / 3600, time % 3600 / 60, time % 60)?;
+        }
+        Ok(())
+    }
+}
+
+impl Duration {
+    pub fn new(seconds: f64) -> Self {
+        Self { seconds }
+    }
+}
+
+impl Display for Duration {
+    /// Days, hours, minutes and seconds, leaving out the ones that are zero: `3h 20min`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.seconds.is_finite() {
+            return write!(f, "{} s", self.seconds);
+        }
+        let sign = if self.seconds < 0.0 { "-" } else { "" };
+        // Milliseconds are the smallest part shown, which hides rounding errors like `19.999999999 s`
+        let milliseconds = (self.seconds.abs() * 1000.0).round();
+        let mut parts = vec![];
+        for (name, size, within) in [("d", 86400000.0, f64::INFINITY), ("h", 3600000.0, 86400000.0), ("min", 60000.0, 3600000.0)] {
+            let count = (milliseconds % within / size).trunc();
+            if count != 0.0 {
+                parts.push(format!("{count}{name}"));
+            }
+        }
+        let seconds = milliseconds % 60000.0 / 1000.0;
+        if seconds != 0.0 || parts.is_empty() {
+            parts.push(format!("{seconds}s"));
+        }
+        write!(f, "{sign}{}", parts.join(" "))
+    }
+}
+
+/// The length in seconds of a duration, or of a quantity of time like `90 days`
+fn seconds(value: &Value) -> Option<f64> {
+    let seconds = Units::new("s", 1);
+    match value {
+        Value::Duration(duration) => Some(duration.seconds),
+        Value::Quantity(quantity) if quantity.dimension() == seconds.dimension() => quantity.convert(&seconds).ok().map(|quantity| quantity.magnitude),
+        _ => None,
+    }
+}
+
+/// Binary operations on dates and durations: a date plus or minus a length of time is a date, the difference of two
+/// dates is a duration, and durations can be added, scaled and divided
+pub fn binary(operator: Operator, lhs: Value, rhs: Value) -> Result<Value, VMError> {
+    let mismatched = |lhs: &Value, rhs: &Value| {
+        VMError::TypeMismatch(format!("Cannot perform binary operation `{operator}` on mismatched types: lhs `{}` and rhs `{}`!", lhs.type_of(), rhs.type_of()))
+    };
+    let result = match (&lhs, &rhs, operator) {
+        (Value::Date(a), Value::Date(b), Operator::Minus) => Value::Duration(Duration::new((a.seconds - b.seconds) as f64)),
+        (Value::Date(date), length, Operator::Plus) | (length, Value::Date(date), Operator::Plus) => match seconds(length) {
+            Some(seconds) => Value::Date(date.add(seconds).map_err(VMError::ErrString)?),
+            None => return Err(mismatched(&lhs, &rhs)),
+        },
+        (Value::Date(date), length, Operator::Minus) => match seconds(length) {
+            Some(seconds) => Value::Date(date.add(-seconds).map_err(VMError::ErrString)?),
+            None => return Err(mismatched(&lhs, &rhs)),
+        },
+
+        (Value::Duration(duration), Value::Number(number), Operator::Multiply) | (Value::Number(number), Value::Duration(duration), Operator::Multiply) => {
+            Value::Duration(Duration::new(duration.seconds * number))
+        },
+        (Value::Duration(duration), Value::Number(number), Operator::Divide) => {
+            if *number == 0.0 {
+                return Err(VMError::DivisionByZero);
+            }
+            Value::Duration(Duration::new(duration.seconds / number))
+        },
+        (a, b, Operator::Plus | Operator::Minus | Operator::Divide) => match (seconds(a), seconds(b)) {
+            (Some(a), Some(b)) => match operator {
+                Operator::Plus => Value::Duration(Duration::new(a + b)),
+                Operator::Minus => Value::Duration(Duration::new(a - b)),
+                _ if b == 0.0 => return Err(VMError::DivisionByZero),
+                _ => Value::Number(a / b),
+            },
+            _ => return Err(mismatched(&lhs, &rhs)),
+        },
+        _ => return Err(VMError::TypeMismatch(format!("Cannot perform binary operation `{operator}` on `{}` and `{}`!", lhs.type_of(), rhs.type_of()))),
+    };
+    Ok(result)
+}
+
+fn expect_date(value: &Value, function: &str) -> Result<Date, VMError> {
+    match value {
+        Value::Date(date) => Ok(*date),
+        Value::String(string) => Date::parse(string).map_err(VMError::OutOfDomain),
+        value => Err(VMError::TypeMismatch(format!("The function `{function}` expected a date but found `{}`!", value.type_of()))),
+    }
+}
+
+fn date(values: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::Date(expect_date(&values[0], "date")?))
+}
+
+/// Turn a quantity of time, or a number of seconds, into a duration
+fn duration(values: &[Value]) -> Result<Value, VMError> {
+    match (&values[0], seconds(&values[0])) {
+        (_, Some(seconds)) | (&Value::Number(seconds), _) => Ok(Value::Duration(Duration::new(seconds))),
+        (value, None) => Err(VMError::TypeMismatch(format!("The function `duration` expected a length of time but found `{}`!", value.type_of()))),
+    }
+}
+
+/// The number of days from the first date to the second, which is negative if the second is earlier
+fn days_between(values: &[Value]) -> Result<Value, VMError> {
+    let a = expect_date(&values[0], "days_between")?;
+    let b = expect_date(&values[1], "days_between")?;
+    Ok(Value::Number((b.seconds - a.seconds) as f64 / SECONDS_PER_DAY as f64))
+}
+
+fn weekday(values: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::String(expect_date(&values[0], "weekday")?.weekday().to_owned()))
+}
+
+fn add_months(values: &[Value]) -> Result<Value, VMError> {
+    let date = expect_date(&values[0], "add_months")?;
+    let months = expect_integer(&values[1], "add_months")?;
+    Ok(Value::Date(date.add_months(months).map_err(VMError::OutOfDomain)?))
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 5] = [
+    ("date", (Arity::exactly(1), Builtin::Pure(date))),
+    ("duration", (Arity::exactly(1), Builtin::Pure(duration))),
+    ("days_between", (Arity::exactly(2), Builtin::Pure(days_between))),
+    ("weekday", (Arity::exactly(1), Builtin::Pure(weekday))),
+    ("add_months", (Arity::exactly(2), Builtin::Pure(add_months))),
+];
@@ -0,0 +1,281 @@
+use std::fmt::Display;
+
+use serde_json::{json, Value};
+
+use crate::utils::Span;
+
+/// Where each line of the source starts, to turn the offsets in a `Span` into lines and columns
+pub struct LineIndex<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0).chain(source.match_indices('\n').map(|(index, _)| index + 1)).collect();
+        Self { source, starts }
+    }
+
+    /// The closest offset at or before `offset` that is the start of a character
+    fn clamp(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+
+    /// The line of an offset, counting from 0
+    fn line_of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|start| *start <= offset) - 1
+    }
+
+    /// The text of a line, without its line break
+    fn line(&self, line: usize) -> &'a str {
+        let start = self.starts[line];
+        let end = self.starts.get(line + 1).map_or(self.source.len(), |next| next - 1);
+        self.source[start..end].trim_end_matches('\r')
+    }
+
+    /// The offset where a line ends, before its line break
+    fn line_end(&self, line: usize) -> usize {
+        self.starts[line] + self.line(line).len()
+    }
+
+    /// The line and column of an offset, both counting from 1. Columns count characters, not bytes
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let offset = self.clamp(offset);
+        let line = self.line_of(offset);
+        (line + 1, self.source[self.starts[line]..offset].chars().count() + 1)
+    }
+}
+
+/// The number of characters that have to be inserted, removed, replaced or swapped with the next one to turn `a` into
+/// `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    // Each row holds the distances from the first `i` characters of `a` to every start of `b`
+    let mut before = vec![0; b.len() + 1];
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// The closest candidate to a name that was not found, if it is close enough to be a typo. Ties go to the first name
+/// in alphabetical order, so the suggestion does not depend on the order of a `HashMap`
+pub fn closest<'b>(name: &str, candidates: impl IntoIterator<Item = &'b str>) -> Option<&'b str> {
+    // About one typo for every three characters, so short names are not matched with everything
+    let limit = name.chars().count() / 3;
+    candidates.into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// A help note suggesting the closest candidate, to put at the end of an error message, or nothing if none are close
+pub fn did_you_mean<'b>(name: &str, candidates: impl IntoIterator<Item = &'b str>) -> String {
+    closest(name, candidates).map_or(String::new(), |candidate| format!(" Help: did you mean `{candidate}`?"))
+}
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// Stops the program from running any further
+    Error,
+    /// Something that is probably a mistake, but does not stop the program from running
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        };
+        write!(f, "{string}")
+    }
+}
+
+/// An error or other message about the source, with the place it is about, other places that explain it and ways to
+/// fix it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// What went wrong, ie. `PARSE ERROR`
+    pub(crate) kind: &'static str,
+    /// A code that stays the same between versions, ie. `E0202`, so that tools can tell errors apart
+    pub(crate) code: Option<&'static str>,
+    pub(crate) severity: Severity,
+    pub(crate) message: String,
+    pub(crate) span: Option<Span>,
+    /// Other places in the source that help explain the error, ie. the bracket that was never closed
+    pub(crate) labels: Vec<(Span, String)>,
+    /// More about what happened, ie. the function the error happened in
+    pub(crate) notes: Vec<String>,
+    pub(crate) help: Vec<String>,
+}
+
+impl Diagnostic {
+    /// A diagnostic without a place in the source. A `Help:` in the message becomes a help note of its own
+    pub fn new(kind: &'static str, message: impl Into<String>) -> Self {
+        let message: String = message.into();
+        let (message, help) = match message.split_once(" Help: ") {
+            Some((message, help)) => (message.to_owned(), vec![help.to_owned()]),
+            None => (message, vec![]),
+        };
+        Self { kind, code: None, severity: Severity::Error, message, span: None, labels: vec![], notes: vec![], help }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, label: impl Into<String>) -> Self {
+        self.labels.push((span, label.into()));
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// The kind of the diagnostic and its code, ie. `[PARSE ERROR E0202]`
+    fn header(&self) -> String {
+        match self.code {
+            Some(code) => format!("[{} {code}]", self.kind),
+            None => format!("[{}]", self.kind),
+        }
+    }
+
+    /// The diagnostic as a single line of JSON, for editors and scripts. Lines and columns count from 1, and are
+    /// `null` like the span when the place is not known
+    pub fn to_json(&self, source: &str) -> String {
+        let index = LineIndex::new(source);
+        let location = |span: Span| {
+            let (line, column) = index.location(span.start);
+            let (end_line, end_column) = index.location(span.end);
+            json!({ "start": span.start, "end": span.end, "line": line, "column": column, "end_line": end_line, "end_column": end_column })
+        };
+        let (line, column) = match self.span {
+            Some(span) => {
+                let (line, column) = index.location(span.start);
+                (json!(line), json!(column))
+            },
+            None => (Value::Null, Value::Null),
+        };
+        json!({
+            "code": self.code,
+            "severity": self.severity.to_string(),
+            "message": self.message,
+            "span": self.span.map(location),
+            "line": line,
+            "column": column,
+            "labels": self.labels.iter().map(|(span, label)| json!({ "span": location(*span), "message": label })).collect::<Vec<_>>(),
+            "notes": self.notes,
+            "help": self.help,
+        }).to_string()
+    }
+
+    /// Show the diagnostic with the lines of the source it is about, underlining the place of the error with `^` and
+    /// the places of the labels with `-`:
+    ///
+    /// ```text
+    /// [PARSE ERROR] 2:14: Expected closing bracket `)`! Found `;`
+    ///   |
+    /// 2 | let y = (1 + 2;
+    ///   |         - this bracket is never closed
+    ///   |               ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let Some(span) = self.span else {
+            return self.to_string();
+        };
+        let index = LineIndex::new(source);
+        let (line, column) = index.location(span.start);
+        let mut output = format!("{} {line}:{column}: {}", self.header(), self.message);
+
+        let mut marks = vec![(span, '^', "")];
+        marks.extend(self.labels.iter().map(|(span, label)| (*span, '-', label.as_str())));
+        let mut lines = marks.iter().map(|(span, ..)| index.line_of(index.clamp(span.start))).collect::<Vec<_>>();
+        lines.sort();
+        lines.dedup();
+
+        let width = (lines.last().copied().unwrap_or(0) + 1).to_string().len();
+        let gutter = " ".repeat(width);
+        output += &format!("\n{gutter} |");
+        for (position, line) in lines.iter().enumerate() {
+            if position > 0 && lines[position - 1] + 1 != *line {
+                output += &format!("\n{gutter} ...");
+            }
+            output += &format!("\n{:>width$} | {}", line + 1, index.line(*line));
+            // Labels come before the error on the same line, since they usually point further back
+            for (span, marker, label) in marks.iter().rev() {
+                let start = index.clamp(span.start);
+                if index.line_of(start) != *line {
+                    continue;
+                }
+                let (_, column) = index.location(start);
+                // Spans over several lines are underlined to the end of their first line
+                let end = index.clamp(span.end.min(index.line_end(*line))).max(start);
+                let length = source[start..end].chars().count().max(1);
+                let underline = marker.to_string().repeat(length);
+                let label = if label.is_empty() { String::new() } else { format!(" {label}") };
+                output += &format!("\n{gutter} | {}{underline}{label}", " ".repeat(column - 1));
+            }
+        }
+        for note in &self.notes {
+            output += &format!("\n{gutter} = note: {note}");
+        }
+        for help in &self.help {
+            output += &format!("\n{gutter} = help: {help}");
+        }
+        output
+    }
+}
+
+/// Without the source, the place of the error is shown as offsets
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{} {span}: {}", self.header(), self.message)?,
+            None => write!(f, "{}: {}", self.header(), self.message)?,
+        }
+        for note in &self.notes {
+            write!(f, "\n  = note: {note}")?;
+        }
+        for help in &self.help {
+            write!(f, "\n  = help: {help}")?;
+        }
+        Ok(())
+    }
+}
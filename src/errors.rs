@@ -1,8 +1,8 @@
 use std::fmt::Display;
 
-use crate::utils::Span;
+use crate::{diagnostics::Diagnostic, utils::Span};
 
-/// T: Tokenizer, P: Parser
+/// T: Tokenizer, P: Parser, C: Compiler (bytecode generation)
 #[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     /// An error that occurs when an octal, binary or hexadecimal number provided is incomplete - `0x` `0b` `0o`
@@ -20,6 +20,9 @@ pub enum Error {
     /// An incomplete, or non terminated string
     TIncompleteString { span: Span }, 
 
+    /// A `/*` comment without a matching `*/`
+    TIncompleteComment { span: Span },
+
     /// End of file
     TEOF,
 
@@ -29,12 +32,55 @@ pub enum Error {
         span: Span,
     },
 
-    /// Any parser error (Also includes one error generated during bytecode generation)
+    /// Any parser error
     PError {
         message: String,
         span: Span,
     },
 
+    /// A variable that has not been declared
+    PUnknownVariable {
+        message: String,
+        span: Span,
+    },
+
+    /// A function that is neither built in nor declared
+    PUnknownFunction {
+        message: String,
+        span: Span,
+    },
+
+    /// A call with the wrong number of arguments
+    PArity {
+        message: String,
+        span: Span,
+    },
+
+    /// A statement that is not ended by a `;` or a `:`
+    PMissingTerminator {
+        message: String,
+        span: Span,
+    },
+
+    /// A unit that does not exist, after a number or in a conversion
+    PUnknownUnit {
+        message: String,
+        span: Span,
+    },
+
+    /// A parser error that also points at another place in the source, ie. the bracket that was never closed
+    PLabelled {
+        message: String,
+        span: Span,
+        label: (Span, String),
+    },
+
+    /// A function that calls itself, directly or through other functions, found while generating the bytecode
+    CRecursion {
+        message: String,
+        span: Span,
+    },
+
     /// An internal, or unexpected error
     PInternalError {
         message: String,
@@ -45,30 +91,195 @@ pub enum Error {
     NoResult
 }
 
+impl Error {
+    /// The code of the error, which never changes once given out. `E01..` are from the tokenizer, `E02..` from the
+    /// parser and `E03..` from the compiler
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::TNumberExpected { .. } => "E0101",
+            Self::TInvalidOctal { .. } => "E0102",
+            Self::TInvalidBinary { .. } => "E0103",
+            Self::TInvalidCharacter { .. } => "E0104",
+            Self::TIncompleteString { .. } => "E0105",
+            Self::TEOF => "E0106",
+            Self::TIncompleteComment { .. } => "E0107",
+            Self::PInvalidStatement { .. } => "E0201",
+            Self::PError { .. } => "E0202",
+            Self::PLabelled { .. } => "E0203",
+            Self::PInternalError { .. } => "E0204",
+            Self::NoResult => "E0205",
+            Self::PUnknownVariable { .. } => "E0206",
+            Self::PUnknownFunction { .. } => "E0207",
+            Self::PArity { .. } => "E0208",
+            Self::PMissingTerminator { .. } => "E0209",
+            Self::PUnknownUnit { .. } => "E0210",
+            Self::CRecursion { .. } => "E0301",
+        }
+    }
+
+    /// The diagnostic for this error, which can show the source it is about
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self {
+            Self::TNumberExpected { location } => 
+                Diagnostic::new("TOKENIZER ERROR", "Expected number!").with_span(Span::new(*location, *location)),
+
+            Self::TInvalidCharacter { location } => 
+                Diagnostic::new("TOKENIZER ERROR", "Found invalid character!")
+                    .with_span(Span::new(*location, *location + 1))
+                    .with_help("Remove this character"),
+
+            Self::TInvalidBinary { span } => 
+                Diagnostic::new("TOKENIZER ERROR", "Invalid Binary number!")
+                    .with_span(*span)
+                    .with_help("Binary numbers can only contain the digits 0 or 1"),
+
+            Self::TInvalidOctal { span } => 
+                Diagnostic::new("TOKENIZER ERROR", "Invalid Octal number!")
+                    .with_span(*span)
+                    .with_help("Octal numbers can only contain the digits 0 to 7"),
+
+            Self::TIncompleteString { span } => 
+                Diagnostic::new("TOKENIZER ERROR", "Incomplete string!")
+                    .with_span(*span)
+                    .with_help("Complete this string by inserting a `\"` at the end of it"),
+
+            Self::TIncompleteComment { span } => 
+                Diagnostic::new("TOKENIZER ERROR", "Incomplete comment!")
+                    .with_span(*span)
+                    .with_help("Close this comment with `*/`. Comments can be nested, so every `/*` inside it needs a `*/` too"),
+
+            // Usually when the file is empty and an early EOF has been produced
+            Self::NoResult => Diagnostic::new("", ""),
+
+            Self::TEOF => Diagnostic::new("TOKENIZER ERROR", "End of file reached. No new tokens can be generated"),
+
+            Self::PError { message, span } | Self::PInvalidStatement { message, span } | Self::PUnknownVariable { message, span }
+            | Self::PUnknownFunction { message, span } | Self::PArity { message, span } | Self::PMissingTerminator { message, span }
+            | Self::PUnknownUnit { message, span } => 
+                Diagnostic::new("PARSE ERROR", message.as_str()).with_span(*span),
+
+            Self::PLabelled { message, span, label: (label_span, label) } => 
+                Diagnostic::new("PARSE ERROR", message.as_str()).with_span(*span).with_label(*label_span, label.as_str()),
+
+            Self::CRecursion { message, span } => Diagnostic::new("COMPILE ERROR", message.as_str()).with_span(*span),
+
+            Self::PInternalError { message, span } => Diagnostic::new("INTERNAL PARSE ERROR", message.as_str()).with_span(*span),
+        };
+        diagnostic.with_code(self.code())
+    }
+
+    /// Show the error with the lines of the source it is about
+    pub fn render(&self, source: &str) -> String {
+        self.diagnostic().render(source)
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let string = match self {
-            Self::TNumberExpected { location } => format!("[TOKENIZER ERROR] [{location}]: Expected number!"),
+        match self {
+            Self::NoResult => Ok(()),
+            error => write!(f, "{}", error.diagnostic()),
+        }
+    }
+}
 
-            Self::TInvalidCharacter { location } => format!("[TOKENIZER ERROR] [{location}]: Found invalid character! Help: Remove this character"),
+impl std::error::Error for Error {}
 
-            Self::TInvalidBinary { span } => format!("[TOKENIZER ERROR] {span}: Invalid Binary number! Help: Binary numbers can only contain the digits 0 or 1"),
+/// Everything that can go wrong when running a calculation, so that it can be embedded in another program. Nothing is
+/// printed: the error shows itself, with the source it is about, through `Display`
+#[derive(Debug)]
+pub enum CalcError {
+    /// A file could not be read or written
+    Io { path: String, error: std::io::Error },
 
-            Self::TInvalidOctal { span } => format!("[TOKENIZER ERROR] {span}: Invalid Octal number! Help: Octal numbers can only contain the digits 0 to 7"),
+    /// Errors found while lexing, parsing or compiling the source. Every error in the source is found at once
+    Compile { source: String, errors: Vec<Error> },
 
-            Self::TIncompleteString { span } => format!("[TOKENIZER ERROR] {span}: Incomplete string! Help: Complete this string by inserting a `\"` at the end of it"),
+    /// Warnings about the source, when they are denied with `--deny-warnings`
+    Warnings { source: String, warnings: Vec<Diagnostic> },
 
-            // Usually when the file is empty and an early EOF has been produced
-            Self::NoResult => format!(""),
+    /// Bytecode that could not be encoded or decoded
+    Bytecode { path: String, message: String },
+
+    /// A table of units that could not be loaded. The message says which file it was
+    Units { message: String },
+
+    /// An error while running, with the source it happened in if it is known
+    Runtime { source: String, diagnostic: Box<Diagnostic> },
+}
 
-            Self::TEOF => format!("End of file reached. No new tokens can be generated"),
+impl Display for CalcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "An error has occured while accessing the file at path {path}: {error}"),
 
-            Self::PError { message, span } | Self::PInvalidStatement { message, span } => {
-                format!("[PARSE ERROR] {span}: {message}")
+            Self::Compile { source, errors } => {
+                for error in errors {
+                    writeln!(f, "{}", error.render(source))?;
+                }
+                match errors.len() {
+                    1 => write!(f, "Found 1 error, so nothing was run"),
+                    count => write!(f, "Found {count} errors, so nothing was run"),
+                }
             },
 
-            Self::PInternalError { message, span: _ } => format!("[INTERNAL PARSE ERROR]: {message}"),
-        };
-        write!(f, "{string}")
+            Self::Warnings { source, warnings } => {
+                for warning in warnings {
+                    writeln!(f, "{}", warning.render(source))?;
+                }
+                match warnings.len() {
+                    1 => write!(f, "Found 1 warning, which is denied by `--deny-warnings`, so nothing was run"),
+                    count => write!(f, "Found {count} warnings, which are denied by `--deny-warnings`, so nothing was run"),
+                }
+            },
+
+            Self::Bytecode { path, message } => write!(f, "An error occured while trying to read or write the bytecode at path {path}: {message}"),
+
+            Self::Units { message } => write!(f, "{message}"),
+
+            Self::Runtime { source, diagnostic } => write!(f, "{}", diagnostic.render(source)),
+        }
+    }
+}
+
+impl CalcError {
+    /// The code of the error. `E04..` are runtime errors, `E05..` are errors reading or writing files, and `W....` are
+    /// denied warnings
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Io { .. } => "E0501",
+            Self::Compile { errors, .. } => errors.first().map_or("E0205", Error::code),
+            Self::Warnings { warnings, .. } => warnings.first().and_then(|warning| warning.code).unwrap_or("W0001"),
+            Self::Bytecode { .. } => "E0502",
+            Self::Units { .. } => "E0503",
+            Self::Runtime { diagnostic, .. } => diagnostic.code.unwrap_or("E0403"),
+        }
+    }
+
+    /// Every diagnostic in the error, along with the source they are about
+    pub fn diagnostics(&self) -> (Vec<Diagnostic>, &str) {
+        match self {
+            Self::Io { .. } => (vec![Diagnostic::new("IO ERROR", self.to_string()).with_code(self.code())], ""),
+            Self::Compile { source, errors } => (errors.iter().map(Error::diagnostic).collect(), source),
+            Self::Warnings { source, warnings } => (warnings.clone(), source),
+            Self::Bytecode { .. } => (vec![Diagnostic::new("BYTECODE ERROR", self.to_string()).with_code(self.code())], ""),
+            Self::Units { .. } => (vec![Diagnostic::new("UNITS ERROR", self.to_string()).with_code(self.code())], ""),
+            Self::Runtime { source, diagnostic } => (vec![(**diagnostic).clone()], source),
+        }
+    }
+
+    /// The diagnostics as JSON, one object on each line
+    pub fn to_json(&self) -> String {
+        let (diagnostics, source) = self.diagnostics();
+        diagnostics.iter().map(|diagnostic| diagnostic.to_json(source)).collect::<Vec<_>>().join("\n")
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for CalcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{functions::{expect_integer, expect_number, Arity, Builtin}, instruction::Value, vm::VMError};
+
+/// How close two estimates of a rate must be for `rate` and `irr` to stop
+const TOLERANCE: f64 = 1e-10;
+
+/// How many steps `rate` and `irr` take before giving up
+const MAX_ITERATIONS: usize = 100;
+
+/// Take a number that must be finite, like every amount of money
+fn expect_finite(value: &Value, function: &str) -> Result<f64, VMError> {
+    let number = expect_number(value, function)?;
+    if !number.is_finite() {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` expected a finite number but found {number}!")));
+    }
+    Ok(number)
+}
+
+/// An optional argument, with the value spreadsheets use when it is left out
+fn optional(values: &[Value], index: usize, default: f64, function: &str) -> Result<f64, VMError> {
+    values.get(index).map_or(Ok(default), |value| expect_finite(value, function))
+}
+
+/// An interest rate per period, which cannot lose everything or more in one period
+fn expect_rate(value: &Value, function: &str) -> Result<f64, VMError> {
+    let rate = expect_finite(value, function)?;
+    if rate <= -1.0 {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` expected a rate greater than -1 but found {rate}!")));
+    }
+    Ok(rate)
+}
+
+/// Whether payments are made at the end of each period (0, the default) or at the beginning (1)
+fn payment_type(values: &[Value], index: usize, function: &str) -> Result<f64, VMError> {
+    match optional(values, index, 0.0, function)? {
+        timing if timing == 0.0 || timing == 1.0 => Ok(timing),
+        timing => Err(VMError::OutOfDomain(format!("The function `{function}` expected the type of payment to be 0 (at the end of each period) or 1 (at the beginning) but found {timing}!"))),
+    }
+}
+
+/// Cash flows, given as numbers, vectors or a mix of the two: `npv(0.1, -100, 60, 60)` or `npv(0.1, [-100, 60, 60])`
+fn cash_flows(values: &[Value], function: &str) -> Result<Vec<f64>, VMError> {
+    let mut flows = vec![];
+    for value in values {
+        match value {
+            Value::Vector(vector) => flows.extend(vector),
+            value => flows.push(expect_finite(value, function)?),
+        }
+    }
+    if flows.is_empty() {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` expected at least one cash flow!")));
+    }
+    if let Some(flow) = flows.iter().find(|flow| !flow.is_finite()) {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` expected finite cash flows but found {flow}!")));
+    }
+    Ok(flows)
+}
+
+/// The growth of an amount over `nper` periods, and the growth of a series of payments of 1 over them. Every function
+/// here comes from `pv * growth + pmt * annuity + fv = 0`
+fn factors(rate: f64, nper: f64, timing: f64) -> (f64, f64) {
+    // `growth - 1` is worked out without subtracting, so that it stays accurate for rates close to 0
+    let exponent = nper * rate.ln_1p();
+    let growth = exponent.exp();
+    let annuity = if rate == 0.0 { nper } else { (1.0 + rate * timing) * exponent.exp_m1() / rate };
+    (growth, annuity)
+}
+
+/// `pmt(rate, nper, pv[, fv, type])`, the payment per period that pays off a loan
+fn pmt(values: &[Value]) -> Result<Value, VMError> {
+    let rate = expect_rate(&values[0], "pmt")?;
+    let nper = expect_finite(&values[1], "pmt")?;
+    let pv = expect_finite(&values[2], "pmt")?;
+    let fv = optional(values, 3, 0.0, "pmt")?;
+    let timing = payment_type(values, 4, "pmt")?;
+    if nper == 0.0 {
+        return Err(VMError::OutOfDomain("The function `pmt` expected a number of periods that is not 0!".to_owned()));
+    }
+    let (growth, annuity) = factors(rate, nper, timing);
+    Ok(Value::Number(-(pv * growth + fv) / annuity))
+}
+
+/// `pv(rate, nper, pmt[, fv, type])`, what a series of payments is worth now
+fn pv(values: &[Value]) -> Result<Value, VMError> {
+    let rate = expect_rate(&values[0], "pv")?;
+    let nper = expect_finite(&values[1], "pv")?;
+    let pmt = expect_finite(&values[2], "pv")?;
+    let fv = optional(values, 3, 0.0, "pv")?;
+    let timing = payment_type(values, 4, "pv")?;
+    let (growth, annuity) = factors(rate, nper, timing);
+    Ok(Value::Number(-(pmt * annuity + fv) / growth))
+}
+
+/// `fv(rate, nper, pmt[, pv, type])`, what an investment is worth after `nper` periods
+fn fv(values: &[Value]) -> Result<Value, VMError> {
+    let rate = expect_rate(&values[0], "fv")?;
+    let nper = expect_finite(&values[1], "fv")?;
+    let pmt = expect_finite(&values[2], "fv")?;
+    let pv = optional(values, 3, 0.0, "fv")?;
+    let timing = payment_type(values, 4, "fv")?;
+    let (growth, annuity) = factors(rate, nper, timing);
+    Ok(Value::Number(-(pv * growth + pmt * annuity)))
+}
+
+/// `nper(rate, pmt, pv[, fv, type])`, how many periods it takes to pay off a loan
+fn nper(values: &[Value]) -> Result<Value, VMError> {
+    let rate = expect_rate(&values[0], "nper")?;
+    let pmt = expect_finite(&values[1], "nper")?;
+    let pv = expect_finite(&values[2], "nper")?;
+    let fv = optional(values, 3, 0.0, "nper")?;
+    let timing = payment_type(values, 4, "nper")?;
+    let never = || Err(VMError::ErrString(format!("The function `nper` found that a payment of {pmt} never reaches {fv} from {pv}!")));
+    if rate == 0.0 {
+        if pmt == 0.0 {
+            return never();
+        }
+        return Ok(Value::Number(-(pv + fv) / pmt));
+    }
+    let payment = pmt * (1.0 + rate * timing) / rate;
+    let ratio = (payment - fv) / (payment + pv);
+    if ratio <= 0.0 || !ratio.is_finite() {
+        return never();
+    }
+    Ok(Value::Number(ratio.ln() / (1.0 + rate).ln()))
+}
+
+/// Newton's method from `guess`, keeping the rate above -1
+fn newton(function: &str, guess: f64, f: impl Fn(f64) -> (f64, f64)) -> Result<f64, VMError> {
+    let mut rate = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let (value, slope) = f(rate);
+        if slope == 0.0 || !slope.is_finite() || !value.is_finite() {
+            break;
+        }
+        let next = rate - value / slope;
+        if next <= -1.0 || !next.is_finite() {
+            break;
+        }
+        if (next - rate).abs() <= TOLERANCE * next.abs().max(1.0) {
+            return Ok(next);
+        }
+        rate = next;
+    }
+    Err(VMError::ErrString(format!("The function `{function}` did not converge! Help: try passing a guess for the rate as the last argument")))
+}
+
+/// `rate(nper, pmt, pv[, fv, type, guess])`, the interest rate per period of a loan or an investment
+fn rate(values: &[Value]) -> Result<Value, VMError> {
+    let nper = expect_finite(&values[0], "rate")?;
+    let pmt = expect_finite(&values[1], "rate")?;
+    let pv = expect_finite(&values[2], "rate")?;
+    let fv = optional(values, 3, 0.0, "rate")?;
+    let timing = payment_type(values, 4, "rate")?;
+    let guess = optional(values, 5, 0.1, "rate")?;
+    if nper <= 0.0 {
+        return Err(VMError::OutOfDomain(format!("The function `rate` expected a positive number of periods but found {nper}!")));
+    }
+    let balance = |rate: f64| {
+        let (growth, annuity) = factors(rate, nper, timing);
+        pv * growth + pmt * annuity + fv
+    };
+    let rate = newton("rate", guess, |rate| {
+        let step = 1e-7 * rate.abs().max(1.0);
+        (balance(rate), (balance(rate + step) - balance(rate - step)) / (2.0 * step))
+    })?;
+    Ok(Value::Number(rate))
+}
+
+/// `npv(rate, flows...)`, the value now of cash flows at the end of each of the coming periods. Like in spreadsheets,
+/// the first flow is discounted by one period
+fn npv(values: &[Value]) -> Result<Value, VMError> {
+    let rate = expect_rate(&values[0], "npv")?;
+    let flows = cash_flows(&values[1..], "npv")?;
+    let value = flows.iter().zip(1..).map(|(flow, period)| flow / (1.0 + rate).powi(period)).sum();
+    Ok(Value::Number(value))
+}
+
+/// `irr(flows[, guess])`, the rate at which the cash flows have a net present value of 0. The first flow is now
+fn irr(values: &[Value]) -> Result<Value, VMError> {
+    let flows = cash_flows(&values[..1], "irr")?;
+    let guess = optional(values, 1, 0.1, "irr")?;
+    if !flows.iter().any(|flow| *flow > 0.0) || !flows.iter().any(|flow| *flow < 0.0) {
+        return Err(VMError::OutOfDomain("The function `irr` expected at least one positive and one negative cash flow!".to_owned()));
+    }
+    let rate = newton("irr", guess, |rate| {
+        flows.iter().zip(0..).fold((0.0, 0.0), |(value, slope), (flow, period)| {
+            let discount = (1.0 + rate).powi(period);
+            (value + flow / discount, slope - period as f64 * flow / (discount * (1.0 + rate)))
+        })
+    })?;
+    Ok(Value::Number(rate))
+}
+
+/// `compound(principal, rate, periods)`, the principal after the interest of every period has been added to it
+fn compound(values: &[Value]) -> Result<Value, VMError> {
+    let principal = expect_finite(&values[0], "compound")?;
+    let rate = expect_rate(&values[1], "compound")?;
+    let periods = expect_finite(&values[2], "compound")?;
+    Ok(Value::Number(principal * (1.0 + rate).powf(periods)))
+}
+
+/// The number of compounding periods in a year, for `effect` and `nominal`
+fn periods_per_year(value: &Value, function: &str) -> Result<f64, VMError> {
+    match expect_integer(value, function)? {
+        periods if periods >= 1 => Ok(periods as f64),
+        periods => Err(VMError::OutOfDomain(format!("The function `{function}` expected at least 1 period per year but found {periods}!"))),
+    }
+}
+
+/// `effect(nominal, periods)`, the effective annual rate of a nominal rate compounded `periods` times a year
+fn effect(values: &[Value]) -> Result<Value, VMError> {
+    let nominal = expect_finite(&values[0], "effect")?;
+    let periods = periods_per_year(&values[1], "effect")?;
+    if nominal / periods <= -1.0 {
+        return Err(VMError::OutOfDomain(format!("The function `effect` expected a nominal rate greater than -{periods} but found {nominal}!")));
+    }
+    Ok(Value::Number((1.0 + nominal / periods).powf(periods) - 1.0))
+}
+
+/// `nominal(effect, periods)`, the nominal annual rate that gives an effective rate when compounded `periods` times
+fn nominal(values: &[Value]) -> Result<Value, VMError> {
+    let effect = expect_rate(&values[0], "nominal")?;
+    let periods = periods_per_year(&values[1], "nominal")?;
+    Ok(Value::Number(periods * ((1.0 + effect).powf(1.0 / periods) - 1.0)))
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 10] = [
+    ("pmt", (Arity::between(3, 5), Builtin::Pure(pmt))),
+    ("pv", (Arity::between(3, 5), Builtin::Pure(pv))),
+    ("fv", (Arity::between(3, 5), Builtin::Pure(fv))),
+    ("nper", (Arity::between(3, 5), Builtin::Pure(nper))),
+    ("rate", (Arity::between(3, 6), Builtin::Pure(rate))),
+    ("npv", (Arity::at_least(2), Builtin::Pure(npv))),
+    ("irr", (Arity::between(1, 2), Builtin::Pure(irr))),
+    ("compound", (Arity::exactly(3), Builtin::Pure(compound))),
+    ("effect", (Arity::exactly(2), Builtin::Pure(effect))),
+    ("nominal", (Arity::exactly(2), Builtin::Pure(nominal))),
+];
@@ -0,0 +1,201 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notation {
+    /// 1234.5
+    Normal,
+    /// 1.2345e3
+    Scientific,
+    /// 1.2345e3, but the exponent is always a multiple of 3
+    Engineering,
+}
+
+impl Display for Notation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Normal => "normal",
+            Self::Scientific => "scientific",
+            Self::Engineering => "engineering",
+        };
+        write!(f, "{string}")
+    }
+}
+
+impl TryFrom<&str> for Notation {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "normal" | "norm" | "n" => Ok(Self::Normal),
+            "scientific" | "sci" | "s" => Ok(Self::Scientific),
+            "engineering" | "eng" | "e" => Ok(Self::Engineering),
+            _ => Err(format!("Unknown notation `{value}`! Expected one of `normal`, `sci` or `eng`")),
+        }
+    }
+}
+
+/// How numbers are rendered in the output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberFormat {
+    /// One of 2, 8, 10 or 16
+    pub base: u32,
+    /// A fixed number of digits after the point
+    pub precision: Option<usize>,
+    /// A number of significant digits to round to. Ignored if `precision` is set
+    pub digits: Option<usize>,
+    /// Only applies to decimal output
+    pub notation: Notation,
+    /// Group the digits of the integer part, ie. `1,000,000` or `0xffff_ffff`
+    pub separators: bool,
+}
+
+/// How many digits after the point are shown for non decimal fractions when no precision is set
+const DEFAULT_RADIX_DIGITS: usize = 12;
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            base: 10,
+            precision: None,
+            digits: None,
+            notation: Notation::Normal,
+            separators: false,
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn with_base(base: u32) -> Self {
+        Self { base, ..Self::default() }
+    }
+
+    pub fn check_base(base: u32) -> Result<u32, String> {
+        match base {
+            2 | 8 | 10 | 16 => Ok(base),
+            _ => Err(format!("Unsupported base {base}! Expected one of 2, 8, 10 or 16")),
+        }
+    }
+
+    pub fn format(&self, number: f64) -> String {
+        if !number.is_finite() {
+            return format!("{number}");
+        }
+        if self.base != 10 {
+            return self.format_radix(number);
+        }
+        match self.notation {
+            Notation::Normal => {
+                let string = match (self.precision, self.digits) {
+                    (Some(precision), _) => format!("{number:.precision$}"),
+                    (None, Some(digits)) => format!("{}", Self::round_significant(number, digits)),
+                    (None, None) => format!("{number}"),
+                };
+                self.group(&string)
+            },
+            Notation::Scientific => self.format_exponent(number, 1),
+            Notation::Engineering => self.format_exponent(number, 3),
+        }
+    }
+
+    fn round_significant(number: f64, digits: usize) -> f64 {
+        let digits = digits.max(1) - 1;
+        format!("{number:.digits$e}").parse().unwrap_or(number)
+    }
+
+    /// Write the number as `mantissa`e`exponent`, with the exponent a multiple of `step`
+    fn format_exponent(&self, number: f64, step: i32) -> String {
+        if number == 0.0 {
+            return "0e0".to_owned();
+        }
+        // Round first, so that rounding up (9.99 -> 10) moves the exponent
+        let number = match self.digits {
+            Some(digits) if self.precision.is_none() => Self::round_significant(number, digits),
+            _ => number,
+        };
+        let exponent = number.abs().log10().floor() as i32;
+        let mut exponent = exponent - exponent.rem_euclid(step);
+        let write = |mantissa: f64| match self.precision {
+            Some(precision) => format!("{mantissa:.precision$}"),
+            None => format!("{}", Self::round_significant(mantissa, 15)),
+        };
+        let mut mantissa = write(number / 10f64.powi(exponent));
+        // Rounding the mantissa can carry into another digit, ie. 9.96 -> `10.0`, which moves the exponent up a step
+        if mantissa.parse::<f64>().is_ok_and(|value| value.abs() >= 10f64.powi(step)) {
+            exponent += step;
+            mantissa = write(number / 10f64.powi(exponent));
+        }
+        format!("{mantissa}e{exponent}")
+    }
+
+    /// Whether the integer part of a number can be written in a base other than 10. Numbers that don't fit are shown in
+    /// decimal instead
+    pub fn fits_radix(number: f64) -> bool {
+        number.is_finite() && number.abs().trunc() < u64::MAX as f64
+    }
+
+    fn format_radix(&self, number: f64) -> String {
+        let prefix = match self.base {
+            2 => "0b",
+            8 => "0o",
+            _ => "0x",
+        };
+        let sign = if number < 0.0 { "-" } else { "" };
+        let number = number.abs();
+        let integer = number.trunc();
+        // Numbers that don't fit are better off in decimal than wrong
+        if !Self::fits_radix(integer) {
+            return format!("{sign}{number}");
+        }
+        let mut integer = integer as u64;
+
+        let mut digits = vec![];
+        loop {
+            digits.push(std::char::from_digit((integer % self.base as u64) as u32, self.base).unwrap_or('?'));
+            integer /= self.base as u64;
+            if integer == 0 {
+                break;
+            }
+        }
+        let integer_part: String = digits.iter().rev().collect();
+
+        let mut fraction = number.fract();
+        let mut fraction_part = String::new();
+        let max_digits = self.precision.unwrap_or(DEFAULT_RADIX_DIGITS);
+        while fraction_part.len() < max_digits && (fraction != 0.0 || self.precision.is_some()) {
+            fraction *= self.base as f64;
+            fraction_part.push(std::char::from_digit(fraction.trunc() as u32, self.base).unwrap_or('?'));
+            fraction = fraction.fract();
+        }
+
+        let integer_part = self.group(&integer_part);
+        if fraction_part.is_empty() {
+            format!("{sign}{prefix}{integer_part}")
+        } else {
+            format!("{sign}{prefix}{integer_part}.{fraction_part}")
+        }
+    }
+
+    /// Insert separators into the integer part of a number written out as a string
+    fn group(&self, number: &str) -> String {
+        if !self.separators {
+            return number.to_owned();
+        }
+        let (separator, size) = if self.base == 10 { (',', 3) } else { ('_', 4) };
+        let (sign, unsigned) = match number.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", number),
+        };
+        let (integer, rest) = match unsigned.find(|c: char| !c.is_ascii_alphanumeric()) {
+            Some(index) => unsigned.split_at(index),
+            None => (unsigned, ""),
+        };
+        let mut grouped = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % size == 0 {
+                grouped.push(separator);
+            }
+            grouped.push(digit);
+        }
+        format!("{sign}{grouped}{rest}")
+    }
+}
@@ -1,45 +1,186 @@
+use std::fmt::Display;
+
+use crate::{calculus, dates, finance, format::NumberFormat, instruction::Value, linalg, number_theory, random, roots, stats, vm::{VMError, VM}};
+
+/// A built in function, which receives its arguments in order
+pub type PureFn = fn(&[Value]) -> Result<Value, VMError>;
+
+/// A built in function that needs the state of the VM, ie. the random number generator
+pub type VMFn = fn(&mut VM, &[Value]) -> Result<Value, VMError>;
+
+#[derive(Clone, Copy)]
+pub enum Builtin {
+    Pure(PureFn),
+    WithVM(VMFn),
+}
+
+/// How many arguments a built in function accepts. The ones after `min` are optional
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arity {
+    pub(crate) min: usize,
+    pub(crate) max: usize,
+}
+
+impl Arity {
+    pub const fn exactly(count: usize) -> Self {
+        Self { min: count, max: count }
+    }
+
+    pub const fn between(min: usize, max: usize) -> Self {
+        Self { min, max }
+    }
+
+    /// Any number of arguments from `min` up
+    pub const fn at_least(min: usize) -> Self {
+        Self { min, max: usize::MAX }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        (self.min..=self.max).contains(&count)
+    }
+
+    /// Placeholders for the arguments, with the optional ones in brackets, `*, *[, *]`, or the last one repeating, `*, *...`
+    pub fn placeholders(&self) -> String {
+        let required = vec!["*"; self.min].join(", ");
+        let separator = if self.min > 0 { ", " } else { "" };
+        match self.max {
+            usize::MAX if self.min > 0 => format!("{required}..."),
+            usize::MAX => "*...".to_owned(),
+            max if max > self.min => format!("{required}[{separator}{}]", vec!["*"; max - self.min].join(", ")),
+            _ => required,
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            usize::MAX => write!(f, "at least {}", self.min),
+            max if max > self.min => write!(f, "{} to {max}", self.min),
+            _ => write!(f, "{}", self.min),
+        }
+    }
+}
 
 macro_rules! decl_fn {
     ($($name: ident,)*) => {
-        $(fn $name(values: &[f64]) -> f64 {
-            f64::$name(values[0])
+        $(fn $name(values: &[Value]) -> Result<Value, VMError> {
+            Ok(Value::Number(f64::$name(expect_number(&values[0], stringify!($name))?)))
         })*
     };
 }
 
 decl_fn!(sin, cos, tan, abs, acos, cosh, acosh, asin, atan, asinh, atanh, cbrt, ceil, floor, log10, log2, ln, round, sqrt, to_radians, to_degrees, );
 
+macro_rules! decl_radix_fn {
+    ($($name: ident => $base: expr,)*) => {
+        $(fn $name(values: &[Value]) -> Result<Value, VMError> {
+            let number = expect_number(&values[0], stringify!($name))?;
+            // The output falls back to decimal for these, but a function named after the base must not
+            if !NumberFormat::fits_radix(number) {
+                return Err(VMError::OutOfDomain(format!("The function `{}` can only write numbers whose integer part is below 2**64, but found {number}!", stringify!($name))));
+            }
+            Ok(Value::String(NumberFormat::with_base($base).format(number)))
+        })*
+    };
+}
+
+decl_radix_fn!(hex => 16, bin => 2, oct => 8, );
+
+pub fn expect_number(value: &Value, function: &str) -> Result<f64, VMError> {
+    match value {
+        Value::Number(number) => Ok(*number),
+        value => Err(VMError::TypeMismatch(format!("The function `{function}` expected a number but found a value of type `{}`!", value.type_of()))),
+    }
+}
+
+/// Take a function passed by name, ie. the `f` in `root(f, 0, 2)`
+pub fn expect_function(value: &Value, function: &str) -> Result<String, VMError> {
+    match value {
+        Value::Function(name) => Ok(name.clone()),
+        value => Err(VMError::TypeMismatch(format!("The function `{function}` expected a function but found a value of type `{}`!", value.type_of()))),
+    }
+}
+
+/// Evaluate a function of a single number, ie. a user function declared with `let f x = ...`
+pub fn evaluate(vm: &mut VM, function: &str, caller: &str, x: f64) -> Result<f64, VMError> {
+    match vm.call_function(function, vec![Value::Number(x)])? {
+        Value::Number(y) => Ok(y),
+        value => Err(VMError::TypeMismatch(format!("The function `{caller}` expected {} to return a number but it returned a value of type `{}`!", describe_function(function), value.type_of()))),
+    }
+}
 
-pub const FUNCTIONS: [(&str, (usize, fn(&[f64]) -> f64)); 22] = [
-    ("cos", (1, cos)),
-    ("sin", (1, sin)),
-    ("tan", (1, tan)),
-    ("abs", (1, abs)),
-    ("acos", (1, acos)),
-    ("cosh", (1, cosh)),
-    ("acosh", (1, acos)),
-    ("asin", (1, asin)),
-    ("atan", (1, atan)),
-    ("acosh", (1, acosh)),
-    ("asinh", (1, asinh)),
-    ("atanh", (1, atanh)),
-    ("cbrt", (1, cbrt)),
-    ("ceil", (1,ceil)),
-    ("floor", (1, floor)),
-    ("log10", (1, log10)),
-    ("log2", (1, log2)),
-    ("ln", (1, ln)),
-    ("round", (1, round)),
-    ("sqrt", (1, sqrt)),
-    ("to_radians", (1, to_radians)),
-    ("to_degrees", (1, to_degrees)),
+/// How a function passed to a built in function is named in its errors. The functions declared for the equations of
+/// `solve`, ie. `<solve#1>`, are private, so they are called the equation instead
+pub fn describe_function(name: &str) -> String {
+    if name.starts_with('<') {
+        "the equation".to_owned()
+    } else {
+        format!("`{name}`")
+    }
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 25] = [
+    ("cos", (Arity::exactly(1), Builtin::Pure(cos))),
+    ("sin", (Arity::exactly(1), Builtin::Pure(sin))),
+    ("tan", (Arity::exactly(1), Builtin::Pure(tan))),
+    ("abs", (Arity::exactly(1), Builtin::Pure(abs))),
+    ("acos", (Arity::exactly(1), Builtin::Pure(acos))),
+    ("cosh", (Arity::exactly(1), Builtin::Pure(cosh))),
+    ("acosh", (Arity::exactly(1), Builtin::Pure(acos))),
+    ("asin", (Arity::exactly(1), Builtin::Pure(asin))),
+    ("atan", (Arity::exactly(1), Builtin::Pure(atan))),
+    ("acosh", (Arity::exactly(1), Builtin::Pure(acosh))),
+    ("asinh", (Arity::exactly(1), Builtin::Pure(asinh))),
+    ("atanh", (Arity::exactly(1), Builtin::Pure(atanh))),
+    ("cbrt", (Arity::exactly(1), Builtin::Pure(cbrt))),
+    ("ceil", (Arity::exactly(1), Builtin::Pure(ceil))),
+    ("floor", (Arity::exactly(1), Builtin::Pure(floor))),
+    ("log10", (Arity::exactly(1), Builtin::Pure(log10))),
+    ("log2", (Arity::exactly(1), Builtin::Pure(log2))),
+    ("ln", (Arity::exactly(1), Builtin::Pure(ln))),
+    ("round", (Arity::exactly(1), Builtin::Pure(round))),
+    ("sqrt", (Arity::exactly(1), Builtin::Pure(sqrt))),
+    ("to_radians", (Arity::exactly(1), Builtin::Pure(to_radians))),
+    ("to_degrees", (Arity::exactly(1), Builtin::Pure(to_degrees))),
+    ("hex", (Arity::exactly(1), Builtin::Pure(hex))),
+    ("bin", (Arity::exactly(1), Builtin::Pure(bin))),
+    ("oct", (Arity::exactly(1), Builtin::Pure(oct))),
 ];
 
-pub fn get_function(key: &str) -> Result<(usize, fn(&[f64]) -> f64), ()> {
-    for element in FUNCTIONS {
+/// The largest integer below which every integer can be represented exactly by a `f64`
+pub const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
+/// Take an integer argument, rejecting fractions rather than truncating them like the bitwise operators do
+pub fn expect_integer(value: &Value, function: &str) -> Result<i64, VMError> {
+    let number = expect_number(value, function)?;
+    if number.fract() != 0.0 || !number.is_finite() {
+        return Err(VMError::NotAnInteger(format!("The function `{function}` expected an integer but found {number}!")));
+    }
+    if number.abs() > MAX_EXACT_INTEGER {
+        return Err(VMError::NotAnInteger(format!("The function `{function}` expected an integer but found {number}, which is too large to be represented exactly!")));
+    }
+    Ok(number as i64)
+}
+
+/// Every built in function, from this module and the libraries registered beside it
+pub fn all_functions() -> impl Iterator<Item = &'static (&'static str, (Arity, Builtin))> {
+    FUNCTIONS.iter()
+        .chain(linalg::FUNCTIONS.iter())
+        .chain(random::FUNCTIONS.iter())
+        .chain(number_theory::FUNCTIONS.iter())
+        .chain(stats::FUNCTIONS.iter())
+        .chain(roots::FUNCTIONS.iter())
+        .chain(calculus::FUNCTIONS.iter())
+        .chain(dates::FUNCTIONS.iter())
+        .chain(finance::FUNCTIONS.iter())
+}
+
+pub fn get_function(key: &str) -> Option<(Arity, Builtin)> {
+    for element in all_functions() {
         if element.0 == key {
-            return Ok(element.1);
+            return Some(element.1);
         }
     }
-    Err(())
-}
\ No newline at end of file
+    None
+}
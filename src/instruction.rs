@@ -2,35 +2,60 @@ use std::{ops::Range, fmt::Display};
 
 use serde::{Deserialize, Serialize};
 
-use crate::ast::Operator;
+use crate::{ast::Operator, dates::{Date, Duration}, format::NumberFormat, linalg::{self, Matrix}, units::{Quantity, Units}};
 
 // There most definitely is a better, more efficient way to represent the bytecode, but I cannot think of it
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Value {
     Number(f64),
     String(String),
+    Quantity(Quantity),
+    Vector(Vec<f64>),
+    Matrix(Matrix),
+    /// A function passed by name, ie. the `f` in `root(f, 0, 2)`
+    Function(String),
+    Date(Date),
+    Duration(Duration),
     Null,
 }
 
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let res = match &self {
-            Value::Number(number) => format!("{number}"),
-            Value::String(string) => {format!("{}", string)},
-            // WHY?
-            Value::Null => format!("{}NULL{}", "{", "}"),
-        };
-        write!(f, "{res}")
+        // `{:.3}` fixes the number of digits after the point and `{:#}` groups digits
+        let format = NumberFormat { precision: f.precision(), separators: f.alternate(), ..NumberFormat::default() };
+        write!(f, "{}", self.format(&format))
     }
 }
 
 
 impl Value {
+    /// Render the value, writing every number in it in the given format
+    pub fn format(&self, format: &NumberFormat) -> String {
+        match &self {
+            Value::Number(number) => format.format(*number),
+            Value::String(string) => string.clone(),
+            Value::Quantity(quantity) => format!("{} {}", format.format(quantity.magnitude), quantity.units),
+            Value::Vector(vector) => linalg::format_vector(vector, format),
+            Value::Matrix(matrix) => matrix.format(format),
+            Value::Function(name) => format!("{{Function {name}}}"),
+            Value::Date(date) => date.to_string(),
+            Value::Duration(duration) => duration.to_string(),
+            // WHY?
+            Value::Null => format!("{}NULL{}", "{", "}"),
+        }
+    }
+
     pub fn type_of(&self) -> &str {
         match self {
             Value::Null => "{Null}",
             Value::Number(..) => "{Number}",
             Value::String(..) => "{String}",
+            Value::Quantity(..) => "{Quantity}",
+            Value::Vector(..) => "{Vector}",
+            Value::Matrix(..) => "{Matrix}",
+            Value::Function(..) => "{Function}",
+            Value::Date(..) => "{Date}",
+            Value::Duration(..) => "{Duration}",
         }
     }
 }
@@ -78,9 +103,10 @@ pub enum Instruction<'a> {
         name: &'a str,
     },
 
-    /// Invoke a function
+    /// Invoke a function with the given number of arguments from the top of the stack
     FunctionCall {
         name: &'a str,
+        arguments: usize,
     },
 
     FunctionDecl {
@@ -99,6 +125,24 @@ pub enum Instruction<'a> {
         depth: usize
     },
 
+    /// Build a vector, or a matrix out of vectors, from the values on top of the stack
+    Vector {
+        length: usize,
+    },
+
+    /// Convert the quantity on top of the stack to other units
+    Convert {
+        units: Units,
+    },
+
+    /// Evaluate the next `length` instructions once for each integer between the two values on top of the stack, with
+    /// `index` bound to it, and combine the results with `operator`
+    Series {
+        index: &'a str,
+        operator: Operator,
+        length: usize,
+    },
+
     UData { number: usize },
 
     OData { operator: Operator },
@@ -1,4 +1,4 @@
-use std::str::Chars;
+use std::{ops::Range, str::Chars};
 use crate::{errors::Error, tokens::{NumberType, Token, TokenType}, utils::Span};
 
 pub struct Lexer<'a> {
@@ -6,21 +6,56 @@ pub struct Lexer<'a> {
     chars: Chars<'a>,
     current: Option<char>,
     position: usize,
+    /// The lines of the `///` comments read since the last token
+    doc_lines: Vec<&'a str>,
+    /// Documentation comments, along with the start of the token they come before
+    docs: Vec<(usize, String)>,
 }
 
-impl Lexer<'_> {
+impl<'a> Lexer<'a> {
 
-    pub fn new(source: &'_ str) -> Result<Lexer, ()> {
+    pub fn new(source: &'a str) -> Lexer<'a> {
         let chars = source.chars();
-        Ok(Lexer {
+        Lexer {
             source,
             chars,
             current: None,
             position: 0,
-        })
+            doc_lines: vec![],
+            docs: vec![],
+        }
+    }
+
+    /// Lex only a part of the source, keeping the spans relative to the whole source
+    pub fn with_range(source: &'a str, range: Range<usize>) -> Lexer<'a> {
+        Lexer {
+            chars: source[range.clone()].chars(),
+            source,
+            current: None,
+            position: range.start,
+            doc_lines: vec![],
+            docs: vec![],
+        }
     }
 
-    pub fn next(&mut self) -> Result<Token, Error>{
+    /// The `///` comments right before the token starting at `start`, one line of text for each comment
+    pub fn doc(&self, start: usize) -> Option<&str> {
+        self.docs.iter().find(|(position, _)| *position == start).map(|(_, doc)| doc.as_str())
+    }
+
+    pub fn next_token(&mut self) -> Result<Token, Error> {
+        let token = self.token();
+        // Documentation comments belong to the token after them
+        if !self.doc_lines.is_empty() {
+            let doc = std::mem::take(&mut self.doc_lines).join("\n");
+            if let Ok(token) = &token {
+                self.docs.push((token.span.start, doc));
+            }
+        }
+        token
+    }
+
+    fn token(&mut self) -> Result<Token, Error> {
         let start = self.position;
 
         macro_rules! token {
@@ -51,7 +86,7 @@ impl Lexer<'_> {
             // Skip whitespace
             _ if Self::check_whitespace(next) => {
                 self.take_while(Self::check_whitespace);
-                return self.next();
+                return self.next_token();
             },
 
             // Numbers
@@ -170,9 +205,13 @@ impl Lexer<'_> {
 
 
             // Delimiters 
-            '(' | '[' => token!(TokenType::OpeningBracket),
+            '(' => token!(TokenType::OpeningBracket),
+
+            ')' => token!(TokenType::ClosingBracket),
+
+            '[' => token!(TokenType::OpeningSquareBracket),
 
-            ')' | ']' => token!(TokenType::ClosingBracket),
+            ']' => token!(TokenType::ClosingSquareBracket),
 
             ';' => token!(TokenType::Semicolon),
             
@@ -203,10 +242,34 @@ impl Lexer<'_> {
                 self.increment();
                 match self.peek() {
                     Ok('=') => token!(TokenType::DivideEqual),
-                    // Get rid of comments
+                    // Get rid of comments. Ones that start with exactly three slashes document the function after them
                     Ok('/') => {
                         self.take_while(|character| character != '\n');
-                        return self.next();
+                        let comment = &self.source[start..self.position];
+                        if let Some(doc) = comment.strip_prefix("///").filter(|doc| !doc.starts_with('/')) {
+                            self.doc_lines.push(doc.strip_prefix(' ').unwrap_or(doc).trim_end());
+                        }
+                        self.token()
+                    }
+                    // Block comments, which can be nested
+                    Ok('*') => {
+                        self.increment();
+                        let mut depth = 1;
+                        while depth > 0 {
+                            match (self.chars.next(), self.peek()) {
+                                (Some('/'), Ok('*')) => depth += 1,
+                                (Some('*'), Ok('/')) => depth -= 1,
+                                (Some(character), _) => {
+                                    self.position += character.len_utf8();
+                                    continue;
+                                },
+                                (None, _) => return Err(Error::TIncompleteComment { span: Span::new(start, start + 2) }),
+                            }
+                            // Both characters of the `/*` or `*/`
+                            self.position += 1;
+                            self.increment();
+                        }
+                        self.token()
                     }
                     _ => variable_token!(0, TokenType::Divide)
                 }
@@ -347,12 +410,14 @@ impl Lexer<'_> {
         cloned_iter.next().ok_or(())
     }
 
+    /// Identifiers follow Unicode's rules for them (XID), so `µ`, `é` and `x̃` can be names but `°` cannot
     fn check_ident_start(character: char) -> bool {
-        character.is_alphabetic() || matches!(character,  '_' | '~' | '#' | '$' | '@' | '`')
+        unicode_ident::is_xid_start(character) || matches!(character,  '_' | '~' | '#' | '$' | '@' | '`')
     }
 
+    /// `'` is allowed after the start, for derivatives like `f'`
     fn check_ident_continue(character: char) -> bool {
-        character.is_alphanumeric() || matches!(character, '_' | '~' | '#' | '$' | '@' | '`')
+        unicode_ident::is_xid_continue(character) || matches!(character, '_' | '~' | '#' | '$' | '@' | '`' | '\'')
     }
 
     /// just a wrapper for now
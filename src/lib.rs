@@ -0,0 +1,33 @@
+//! A calculator that compiles expressions to bytecode and runs them on a stack based virtual machine
+
+pub mod tokens;
+pub mod lexer;
+pub mod parser;
+pub mod bytecode;
+pub mod instruction;
+pub mod ast;
+pub mod errors;
+pub mod diagnostics;
+pub mod utils;
+pub mod vm;
+pub mod functions;
+pub mod processchain;
+pub mod units;
+pub mod linalg;
+pub mod format;
+pub mod random;
+pub mod number_theory;
+pub mod stats;
+pub mod roots;
+pub mod calculus;
+pub mod dates;
+pub mod finance;
+pub mod symbolic;
+pub mod simplify;
+pub mod optimize;
+pub mod lint;
+
+// Tests
+mod tests;
+
+pub use errors::CalcError;
@@ -0,0 +1,394 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ast::Operator, format::NumberFormat, functions::{expect_number, Arity, Builtin}, instruction::Value, vm::VMError};
+
+/// The most rows or columns a matrix made by `identity` can have, so that a typo cannot use up all of the memory
+const MAX_SIZE: usize = 4096;
+
+/// A dense matrix of numbers, stored row by row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Matrix {
+    pub(crate) rows: usize,
+    pub(crate) cols: usize,
+    pub(crate) data: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn new(rows: usize, cols: usize, data: Vec<f64>) -> Self {
+        Self { rows, cols, data }
+    }
+
+    pub fn zeros(rows: usize, cols: usize) -> Self {
+        Self::new(rows, cols, vec![0.0; rows * cols])
+    }
+
+    pub fn identity(size: usize) -> Self {
+        let mut matrix = Self::zeros(size, size);
+        for i in 0..size {
+            matrix.set(i, i, 1.0);
+        }
+        matrix
+    }
+
+    /// Build a matrix out of its rows, which must all be of the same length
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Result<Self, String> {
+        let cols = rows.first().map_or(0, |row| row.len());
+        for (i, row) in rows.iter().enumerate() {
+            if row.len() != cols {
+                return Err(
+                    format!(
+                        "Cannot build a matrix from rows of mismatched lengths: row 1 has {cols} element(s) but row {} has {} element(s)!",
+                        i + 1, row.len()
+                    )
+                );
+            }
+        }
+        Ok(Self::new(rows.len(), cols, rows.concat()))
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.data[row * self.cols + col]
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, value: f64) {
+        self.data[row * self.cols + col] = value;
+    }
+
+    pub fn shape(&self) -> String {
+        format!("{}x{}", self.rows, self.cols)
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut result = Self::zeros(self.cols, self.rows);
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                result.set(col, row, self.get(row, col));
+            }
+        }
+        result
+    }
+
+    pub fn matmul(&self, other: &Matrix) -> Result<Self, String> {
+        if self.cols != other.rows {
+            return Err(format!("Cannot multiply matrices of mismatched shapes {} and {}!", self.shape(), other.shape()));
+        }
+        let mut result = Self::zeros(self.rows, other.cols);
+        for row in 0..self.rows {
+            for col in 0..other.cols {
+                let sum = (0..self.cols).map(|i| self.get(row, i) * other.get(i, col)).sum();
+                result.set(row, col, sum);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Reduce the matrix to upper triangular form with partial pivoting, applying the same row operations to `rhs`.
+    /// Returns the determinant, which is zero for singular matrices
+    fn eliminate(&mut self, rhs: &mut Matrix) -> f64 {
+        let size = self.rows;
+        let mut determinant = 1.0;
+        for col in 0..size {
+            let pivot = (col..size)
+                .max_by(|a, b| self.get(*a, col).abs().total_cmp(&self.get(*b, col).abs()))
+                .unwrap_or(col);
+            if self.get(pivot, col).abs() < f64::EPSILON {
+                return 0.0;
+            }
+            if pivot != col {
+                self.swap_rows(pivot, col);
+                rhs.swap_rows(pivot, col);
+                determinant = -determinant;
+            }
+            let pivot_value = self.get(col, col);
+            determinant *= pivot_value;
+            for row in (col + 1)..size {
+                let factor = self.get(row, col) / pivot_value;
+                for i in col..size {
+                    self.set(row, i, self.get(row, i) - factor * self.get(col, i));
+                }
+                for i in 0..rhs.cols {
+                    rhs.set(row, i, rhs.get(row, i) - factor * rhs.get(col, i));
+                }
+            }
+        }
+        determinant
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        for col in 0..self.cols {
+            self.data.swap(a * self.cols + col, b * self.cols + col);
+        }
+    }
+
+    pub fn determinant(&self) -> Result<f64, String> {
+        if !self.is_square() {
+            return Err(format!("Cannot find the determinant of a non square matrix of shape {}!", self.shape()));
+        }
+        Ok(self.clone().eliminate(&mut Matrix::zeros(self.rows, 0)))
+    }
+
+    /// Solve `self * x = rhs` for every column of `rhs`
+    pub fn solve(&self, rhs: &Matrix) -> Result<Matrix, String> {
+        if !self.is_square() {
+            return Err(format!("Cannot solve a system with a non square matrix of shape {}!", self.shape()));
+        }
+        if rhs.rows != self.rows {
+            return Err(format!("Cannot solve a system with a matrix of shape {} and a right hand side of shape {}!", self.shape(), rhs.shape()));
+        }
+        let mut lhs = self.clone();
+        let mut result = rhs.clone();
+        if lhs.eliminate(&mut result) == 0.0 {
+            return Err(format!("Cannot solve a system with a singular matrix {self}!"));
+        }
+        // Back substitution
+        for col in 0..result.cols {
+            for row in (0..self.rows).rev() {
+                let sum: f64 = ((row + 1)..self.rows).map(|i| lhs.get(row, i) * result.get(i, col)).sum();
+                result.set(row, col, (result.get(row, col) - sum) / lhs.get(row, row));
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn inverse(&self) -> Result<Matrix, String> {
+        if !self.is_square() {
+            return Err(format!("Cannot invert a non square matrix of shape {}!", self.shape()));
+        }
+        self.solve(&Matrix::identity(self.rows))
+            .map_err(|_| format!("Cannot invert the singular matrix {self}!"))
+    }
+
+    pub fn format(&self, format: &NumberFormat) -> String {
+        let rows = (0..self.rows)
+            .map(|row| format_vector(&self.data[(row * self.cols)..((row + 1) * self.cols)], format))
+            .collect::<Vec<_>>();
+        format!("[{}]", rows.join(", "))
+    }
+}
+
+impl Display for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(&NumberFormat::default()))
+    }
+}
+
+pub fn format_vector(vector: &[f64], format: &NumberFormat) -> String {
+    format!("[{}]", vector.iter().map(|value| format.format(*value)).collect::<Vec<_>>().join(", "))
+}
+
+fn vector_shape(vector: &[f64]) -> String {
+    format!("vector of length {}", vector.len())
+}
+
+fn elementwise(a: &[f64], b: &[f64], operator: Operator) -> Vec<f64> {
+    a.iter().zip(b).map(|(a, b)| if operator == Operator::Plus { a + b } else { a - b }).collect()
+}
+
+/// Binary operations involving at least one vector or matrix
+pub fn binary(operator: Operator, lhs: Value, rhs: Value) -> Result<Value, VMError> {
+    let scale = |operator: Operator, data: &[f64], scalar: f64, scalar_lhs: bool| -> Result<Vec<f64>, VMError> {
+        match operator {
+            Operator::Multiply => Ok(data.iter().map(|value| value * scalar).collect()),
+            Operator::Divide if !scalar_lhs => {
+                if scalar == 0.0 {
+                    return Err(VMError::DivisionByZero);
+                }
+                Ok(data.iter().map(|value| value / scalar).collect())
+            },
+            _ => Err(VMError::TypeMismatch(format!("Cannot perform binary operation `{operator}` between a number and a vector or matrix!"))),
+        }
+    };
+
+    match (lhs, rhs) {
+        (Value::Vector(a), Value::Vector(b)) => match operator {
+            Operator::Plus | Operator::Minus => {
+                if a.len() != b.len() {
+                    return Err(VMError::ShapeMismatch(format!("Cannot perform binary operation `{operator}` on vectors of mismatched shapes: {} and {}!", vector_shape(&a), vector_shape(&b))));
+                }
+                Ok(Value::Vector(elementwise(&a, &b, operator)))
+            },
+            Operator::Multiply => Err(VMError::ErrString("Cannot multiply two vectors with `*`! Help: use `dot(a, b)` or `cross(a, b)`".to_owned())),
+            _ => Err(VMError::ErrString(format!("Cannot perform binary operation `{operator}` on vectors!"))),
+        },
+
+        (Value::Matrix(a), Value::Matrix(b)) => match operator {
+            Operator::Plus | Operator::Minus => {
+                if a.rows != b.rows || a.cols != b.cols {
+                    return Err(VMError::ShapeMismatch(format!("Cannot perform binary operation `{operator}` on matrices of mismatched shapes {} and {}!", a.shape(), b.shape())));
+                }
+                Ok(Value::Matrix(Matrix::new(a.rows, a.cols, elementwise(&a.data, &b.data, operator))))
+            },
+            Operator::Multiply => Ok(Value::Matrix(a.matmul(&b).map_err(VMError::ShapeMismatch)?)),
+            _ => Err(VMError::ErrString(format!("Cannot perform binary operation `{operator}` on matrices!"))),
+        },
+
+        (Value::Matrix(a), Value::Vector(b)) if operator == Operator::Multiply => {
+            if a.cols != b.len() {
+                return Err(VMError::ShapeMismatch(format!("Cannot multiply a matrix of shape {} with a {}!", a.shape(), vector_shape(&b))));
+            }
+            Ok(Value::Vector(a.matmul(&Matrix::new(b.len(), 1, b)).map_err(VMError::ShapeMismatch)?.data))
+        },
+
+        (Value::Vector(a), Value::Matrix(b)) if operator == Operator::Multiply => {
+            if a.len() != b.rows {
+                return Err(VMError::ShapeMismatch(format!("Cannot multiply a {} with a matrix of shape {}!", vector_shape(&a), b.shape())));
+            }
+            Ok(Value::Vector(Matrix::new(1, a.len(), a).matmul(&b).map_err(VMError::ShapeMismatch)?.data))
+        },
+
+        (Value::Matrix(a), Value::Number(b)) if operator == Operator::Exponent => {
+            if !a.is_square() {
+                return Err(VMError::ShapeMismatch(format!("Cannot raise a non square matrix of shape {} to a power!", a.shape())));
+            }
+            if b.fract() != 0.0 {
+                return Err(VMError::ErrString(format!("Matrices can only be raised to integer powers, but the power provided was {b}!")));
+            }
+            let mut base = if b < 0.0 { a.inverse().map_err(VMError::ErrString)? } else { a };
+            let mut result = Matrix::identity(base.rows);
+            // Square the base for each bit of the power, so that large powers take few multiplications
+            let mut power = b.abs();
+            while power > 0.0 {
+                if power % 2.0 == 1.0 {
+                    result = result.matmul(&base).map_err(VMError::ShapeMismatch)?;
+                }
+                power = (power / 2.0).floor();
+                if power > 0.0 {
+                    base = base.matmul(&base).map_err(VMError::ShapeMismatch)?;
+                }
+            }
+            Ok(Value::Matrix(result))
+        },
+
+        (Value::Vector(a), Value::Number(b)) => Ok(Value::Vector(scale(operator, &a, b, false)?)),
+        (Value::Number(a), Value::Vector(b)) => Ok(Value::Vector(scale(operator, &b, a, true)?)),
+        (Value::Matrix(a), Value::Number(b)) => Ok(Value::Matrix(Matrix::new(a.rows, a.cols, scale(operator, &a.data, b, false)?))),
+        (Value::Number(a), Value::Matrix(b)) => Ok(Value::Matrix(Matrix::new(b.rows, b.cols, scale(operator, &b.data, a, true)?))),
+
+        (a, b) => Err(VMError::TypeMismatch(format!("Cannot perform binary operation `{operator}` on mismatched types: lhs `{}` and rhs `{}`!", a.type_of(), b.type_of()))),
+    }
+}
+
+/// Build a vector out of numbers, or a matrix out of vectors of the same length
+pub fn build(elements: Vec<Value>) -> Result<Value, VMError> {
+    match elements.first() {
+        None | Some(Value::Number(..)) => {
+            let mut vector = vec![];
+            for element in elements {
+                match element {
+                    Value::Number(number) => vector.push(number),
+                    value => return Err(VMError::TypeMismatch(format!("Vectors can only contain numbers, but found a value of type `{}`!", value.type_of()))),
+                }
+            }
+            Ok(Value::Vector(vector))
+        }
+
+        Some(Value::Vector(..)) => {
+            let mut rows = vec![];
+            for element in elements {
+                match element {
+                    Value::Vector(row) => rows.push(row),
+                    value => return Err(VMError::TypeMismatch(format!("The rows of a matrix must be vectors, but found a value of type `{}`!", value.type_of()))),
+                }
+            }
+            Ok(Value::Matrix(Matrix::from_rows(rows).map_err(VMError::ShapeMismatch)?))
+        }
+
+        Some(value) => Err(VMError::TypeMismatch(format!("Vectors can only contain numbers, but found a value of type `{}`!", value.type_of()))),
+    }
+}
+
+fn expect_matrix(value: &Value, function: &str) -> Result<Matrix, VMError> {
+    match value {
+        Value::Matrix(matrix) => Ok(matrix.clone()),
+        value => Err(VMError::TypeMismatch(format!("The function `{function}` expected a matrix but found a value of type `{}`!", value.type_of()))),
+    }
+}
+
+pub fn expect_vector(value: &Value, function: &str) -> Result<Vec<f64>, VMError> {
+    match value {
+        Value::Vector(vector) => Ok(vector.clone()),
+        value => Err(VMError::TypeMismatch(format!("The function `{function}` expected a vector but found a value of type `{}`!", value.type_of()))),
+    }
+}
+
+fn transpose(values: &[Value]) -> Result<Value, VMError> {
+    match &values[0] {
+        Value::Vector(vector) => Ok(Value::Matrix(Matrix::new(vector.len(), 1, vector.clone()))),
+        value => Ok(Value::Matrix(expect_matrix(value, "transpose")?.transpose())),
+    }
+}
+
+fn det(values: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::Number(expect_matrix(&values[0], "det")?.determinant().map_err(VMError::ShapeMismatch)?))
+}
+
+fn inv(values: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::Matrix(expect_matrix(&values[0], "inv")?.inverse()?))
+}
+
+/// `solve(A, b)`. Registered by `roots`, since `solve` also finds the roots of functions
+pub fn solve(values: &[Value]) -> Result<Value, VMError> {
+    let matrix = expect_matrix(&values[0], "solve")?;
+    let rhs = expect_vector(&values[1], "solve")?;
+    if rhs.len() != matrix.rows {
+        return Err(VMError::ShapeMismatch(format!("Cannot solve a system with a matrix of shape {} and a {}!", matrix.shape(), vector_shape(&rhs))));
+    }
+    Ok(Value::Vector(matrix.solve(&Matrix::new(rhs.len(), 1, rhs))?.data))
+}
+
+fn dot(values: &[Value]) -> Result<Value, VMError> {
+    let a = expect_vector(&values[0], "dot")?;
+    let b = expect_vector(&values[1], "dot")?;
+    if a.len() != b.len() {
+        return Err(VMError::ShapeMismatch(format!("Cannot find the dot product of vectors of mismatched shapes: {} and {}!", vector_shape(&a), vector_shape(&b))));
+    }
+    Ok(Value::Number(a.iter().zip(&b).map(|(a, b)| a * b).sum()))
+}
+
+fn cross(values: &[Value]) -> Result<Value, VMError> {
+    let a = expect_vector(&values[0], "cross")?;
+    let b = expect_vector(&values[1], "cross")?;
+    if a.len() != 3 || b.len() != 3 {
+        return Err(VMError::ShapeMismatch(format!("The cross product is only defined for vectors of length 3, but found a {} and a {}!", vector_shape(&a), vector_shape(&b))));
+    }
+    Ok(Value::Vector(vec![
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]))
+}
+
+fn norm(values: &[Value]) -> Result<Value, VMError> {
+    let data = match &values[0] {
+        Value::Matrix(matrix) => matrix.data.clone(),
+        value => expect_vector(value, "norm")?,
+    };
+    Ok(Value::Number(data.iter().map(|value| value * value).sum::<f64>().sqrt()))
+}
+
+fn identity(values: &[Value]) -> Result<Value, VMError> {
+    let size = expect_number(&values[0], "identity")?;
+    if size.fract() != 0.0 || size < 1.0 {
+        return Err(VMError::OutOfDomain(format!("The function `identity` expected a positive integer size but found {size}!")));
+    }
+    if size > MAX_SIZE as f64 {
+        return Err(VMError::OutOfDomain(format!("The function `identity` can make matrices of size up to {MAX_SIZE}, but the size provided was {size}!")));
+    }
+    Ok(Value::Matrix(Matrix::identity(size as usize)))
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 7] = [
+    ("transpose", (Arity::exactly(1), Builtin::Pure(transpose))),
+    ("det", (Arity::exactly(1), Builtin::Pure(det))),
+    ("inv", (Arity::exactly(1), Builtin::Pure(inv))),
+    ("dot", (Arity::exactly(2), Builtin::Pure(dot))),
+    ("cross", (Arity::exactly(2), Builtin::Pure(cross))),
+    ("norm", (Arity::exactly(1), Builtin::Pure(norm))),
+    ("identity", (Arity::exactly(1), Builtin::Pure(identity))),
+];
@@ -0,0 +1,250 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{ast::{Operator, Tree, AST}, diagnostics::{Diagnostic, Severity}, functions::{get_function, Builtin}, utils::Span};
+
+/// Something that is allowed but is probably a mistake. Each one can be turned off with `--allow <name>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lint {
+    /// A variable that is never read after it is declared
+    UnusedVariable,
+    /// A variable and a function with the same name, so that the older one can no longer be used
+    Shadowing,
+    /// A statement whose value is thrown away by its `;`, and which does nothing else
+    NoEffect,
+    /// A bitwise operator on a number with a fraction, which is dropped without an error
+    BitwiseFraction,
+}
+
+pub const LINTS: [Lint; 4] = [Lint::UnusedVariable, Lint::Shadowing, Lint::NoEffect, Lint::BitwiseFraction];
+
+impl Lint {
+    /// The code of the warning, which stays the same between versions like the codes of errors
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnusedVariable => "W0001",
+            Self::Shadowing => "W0002",
+            Self::NoEffect => "W0003",
+            Self::BitwiseFraction => "W0004",
+        }
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::UnusedVariable => "unused-variable",
+            Self::Shadowing => "shadowing",
+            Self::NoEffect => "no-effect",
+            Self::BitwiseFraction => "bitwise-fraction",
+        };
+        write!(f, "{string}")
+    }
+}
+
+impl TryFrom<&str> for Lint {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        LINTS.into_iter().find(|lint| lint.to_string() == value).ok_or_else(|| {
+            let names = LINTS.iter().map(|lint| format!("`{lint}`")).collect::<Vec<_>>();
+            format!("Unknown lint `{value}`! Expected one of {}", names.join(", "))
+        })
+    }
+}
+
+/// A number written in the source, ie. `2.5` or `-2.5`
+fn literal(tree: &Tree) -> Option<f64> {
+    match &tree.ast {
+        AST::Number { value } => Some(*value),
+        AST::UnaryOp { rhs, op: Operator::Minus } => literal(rhs).map(|value| -value),
+        _ => None,
+    }
+}
+
+fn is_bitwise(operator: Operator) -> bool {
+    matches!(operator.without_assign(), Operator::BitAnd | Operator::BitOr | Operator::BitXor | Operator::BitLeftShift | Operator::BitRightShift)
+}
+
+/// Whether an expression does anything besides producing a value
+fn has_effect(tree: &Tree) -> bool {
+    match &tree.ast {
+        AST::Print { .. } | AST::Output { .. } | AST::Delete { .. } | AST::FunctionDecl { .. } => true,
+        AST::Declare { .. } | AST::DeclareAssign { .. } | AST::Assign { .. } | AST::AssignOp { .. } => true,
+        // Built in functions that use the state of the VM, like the random number generator, and user functions, which
+        // might call them
+        AST::FunctionCall { name, expressions } => {
+            !matches!(get_function(name), Some((_, Builtin::Pure(_)))) || expressions.iter().any(|expression| has_effect(expression))
+        },
+        AST::BinaryOp { lhs, rhs, .. } => has_effect(lhs) || has_effect(rhs),
+        AST::UnaryOp { rhs, .. } => has_effect(rhs),
+        AST::Vector { elements } => elements.iter().any(|element| has_effect(element)),
+        AST::Convert { value, .. } => has_effect(value),
+        AST::Series { from, to, body, .. } => has_effect(from) || has_effect(to) || has_effect(body),
+        _ => false,
+    }
+}
+
+/// Checks the statements of a program as they are parsed, collecting warnings about them. Warnings never stop the
+/// program from running, unless `--deny-warnings` is used
+#[derive(Debug, Default)]
+pub struct Linter<'a> {
+    allowed: Vec<Lint>,
+    /// The variables that exist, with where they were declared and whether they have been read since
+    variables: HashMap<&'a str, (Span, bool)>,
+    /// The user functions that exist, with where they were declared
+    functions: HashMap<&'a str, Span>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(allowed: Vec<Lint>) -> Self {
+        Self { allowed, ..Self::default() }
+    }
+
+    fn warn(&mut self, lint: Lint, diagnostic: Diagnostic) {
+        if !self.allowed.contains(&lint) {
+            let diagnostic = diagnostic
+                .with_severity(Severity::Warning)
+                .with_code(lint.code())
+                .with_note(format!("turn this warning off with `--allow {lint}`"));
+            self.warnings.push(diagnostic);
+        }
+    }
+
+    /// Check a statement. `lowered_calls` are the spans of the calls to `diff` and `help` in it, which the parser has
+    /// replaced by what they give
+    pub fn check(&mut self, tree: &Tree<'a>, lowered_calls: &[Span]) {
+        match &tree.ast {
+            AST::Output { value } => self.statement(value, true, lowered_calls),
+            _ => self.statement(tree, false, lowered_calls),
+        }
+    }
+
+    /// Check a statement, which is `shown` if it ends with `:`
+    fn statement(&mut self, tree: &Tree<'a>, shown: bool, lowered_calls: &[Span]) {
+        match &tree.ast {
+            AST::DeclareAssign { identifier, identifier_span, value } => {
+                // The old variable can be read in the value of the new one
+                self.visit(value, &[]);
+                self.declare(identifier, *identifier_span, shown);
+            },
+            AST::Declare { identifier, identifier_span } => self.declare(identifier, *identifier_span, shown),
+            AST::Assign { value, .. } => self.visit(value, &[]),
+            // `a += 1` reads `a` before changing it
+            AST::AssignOp { identifier, value, operator, .. } => {
+                self.check_bitwise(*operator, value);
+                self.visit(value, &[]);
+                if let Some((_, read)) = self.variables.get_mut(identifier) {
+                    *read = true;
+                }
+            },
+            AST::FunctionDecl { name, arguments, body } => {
+                // The arguments are different variables inside the body
+                self.visit(body, arguments);
+                if let Some((span, _)) = self.variables.get(name) {
+                    let diagnostic = Diagnostic::new("WARNING", format!("The function `{name}` shadows the variable of the same name, so the variable can no longer be used"))
+                        .with_span(tree.span)
+                        .with_label(*span, "the variable is declared here");
+                    self.warn(Lint::Shadowing, diagnostic);
+                }
+                self.functions.insert(name, tree.span);
+            },
+            AST::Delete { name } => {
+                self.forget(name);
+                self.functions.remove(name);
+            },
+            _ => {
+                self.visit(tree, &[]);
+                if !shown && !has_effect(tree) && !lowered_calls.contains(&tree.span) {
+                    let diagnostic = Diagnostic::new("WARNING", "This expression has no effect, since its value is thrown away by the `;`")
+                        .with_span(tree.span)
+                        .with_help("end it with `:` to show its value");
+                    self.warn(Lint::NoEffect, diagnostic);
+                }
+            },
+        }
+    }
+
+    /// The warnings about the statements checked so far, in the order of the source. Variables that are never read
+    /// are only known once every statement has been checked
+    pub fn finish(&mut self) -> Vec<Diagnostic> {
+        let names = self.variables.keys().copied().collect::<Vec<_>>();
+        for name in names {
+            self.forget(name);
+        }
+        let mut warnings = std::mem::take(&mut self.warnings);
+        warnings.sort_by_key(|warning| warning.span.map(|span| span.start));
+        warnings
+    }
+
+    /// Add a variable, which has been read already if its value was `shown`
+    fn declare(&mut self, name: &'a str, span: Span, shown: bool) {
+        self.forget(name);
+        if let Some(function) = self.functions.get(name) {
+            let diagnostic = Diagnostic::new("WARNING", format!("The variable `{name}` shadows the function of the same name, so the function can no longer be called"))
+                .with_span(span)
+                .with_label(*function, "the function is declared here");
+            self.warn(Lint::Shadowing, diagnostic);
+        }
+        self.variables.insert(name, (span, shown));
+    }
+
+    /// Remove a variable, warning if it was never read
+    fn forget(&mut self, name: &str) {
+        let Some((span, read)) = self.variables.remove(name) else {
+            return;
+        };
+        if !read && !name.starts_with('_') {
+            let diagnostic = Diagnostic::new("WARNING", format!("The variable `{name}` is never used"))
+                .with_span(span)
+                .with_help(format!("if this is on purpose, start its name with an underscore: `_{name}`"));
+            self.warn(Lint::UnusedVariable, diagnostic);
+        }
+    }
+
+    fn check_bitwise(&mut self, operator: Operator, operand: &Tree) {
+        if !is_bitwise(operator) {
+            return;
+        }
+        if let Some(value) = literal(operand).filter(|value| value.fract() != 0.0) {
+            let diagnostic = Diagnostic::new("WARNING", format!("`{value}` is not an integer, so `{}` drops its fraction", operator.without_assign()))
+                .with_span(operand.span)
+                .with_help("use `floor`, `round` or `ceil` to choose the integer");
+            self.warn(Lint::BitwiseFraction, diagnostic);
+        }
+    }
+
+    /// Check an expression, marking the variables it reads. `locals` are the names that are not global variables in
+    /// it, like the arguments of a function
+    fn visit(&mut self, tree: &Tree<'a>, locals: &[&'a str]) {
+        match &tree.ast {
+            AST::Identifier { name } if !locals.contains(name) => {
+                if let Some((_, read)) = self.variables.get_mut(name) {
+                    *read = true;
+                }
+            },
+            AST::BinaryOp { lhs, rhs, op } => {
+                self.check_bitwise(*op, lhs);
+                self.check_bitwise(*op, rhs);
+                self.visit(lhs, locals);
+                self.visit(rhs, locals);
+            },
+            AST::UnaryOp { rhs, .. } => self.visit(rhs, locals),
+            AST::FunctionCall { expressions, .. } | AST::Print { expressions } | AST::Vector { elements: expressions } => {
+                for expression in expressions {
+                    self.visit(expression, locals);
+                }
+            },
+            AST::Convert { value, .. } | AST::Output { value } => self.visit(value, locals),
+            // The index of a series is a different variable inside its body
+            AST::Series { index, from, to, body, .. } => {
+                self.visit(from, locals);
+                self.visit(to, locals);
+                let mut locals = locals.to_vec();
+                locals.push(index);
+                self.visit(body, &locals);
+            },
+            _ => (),
+        }
+    }
+}
@@ -1,32 +1,42 @@
-mod tokens;
-mod lexer;
-mod parser;
-mod bytecode;
-mod instruction;
-mod ast;
-mod errors;
-mod utils;
-mod vm;
-mod functions;
-mod processchain;
-
-// Tests
-mod tests;
-
-use std::{collections::HashMap, io::Write, time::Instant};
-
-use instruction::Instruction;
-use processchain::ProcessChain;
-
-fn main() -> Result<(), ()> {
-    run()?;
-    Ok(())
+use std::{collections::HashMap, io::Write, process::ExitCode, time::Instant};
+
+use vm_calc::{bytecode, functions, lexer, parser, units, vm, CalcError};
+use vm_calc::instruction::{Instruction, Value};
+use vm_calc::format::{NumberFormat, Notation};
+use vm_calc::processchain::{parse_base, parse_count, parse_options, parse_seed, ErrorFormat, Options, ProcessChain, RunOutput};
+use vm_calc::random::Random;
+
+fn main() -> ExitCode {
+    let (options, args) = match parse_options(std::env::args().collect()) {
+        Ok(result) => result,
+        Err(error) => {
+            println!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&options, &args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            report(&error, options.error_format);
+            ExitCode::FAILURE
+        },
+    }
 }
 
-fn run() -> Result<(), ()> {
-    let args: Vec<String> = std::env::args().collect();
-    let store = || -> Result<(), ()> {
-        let output = 
+/// Print an error in the format chosen with `--error-format`
+fn report(error: &CalcError, format: ErrorFormat) {
+    match format {
+        ErrorFormat::Human => println!("{error}"),
+        ErrorFormat::Json => println!("{}", error.to_json()),
+    }
+}
+
+fn run(options: &Options, args: &[String]) -> Result<(), CalcError> {
+    for path in &options.units {
+        units::load_table(path).map_err(|message| CalcError::Units { message })?;
+    }
+    let store = || -> Result<(), CalcError> {
+        let path = 
         if args.len() >= 4 { &args[3] } 
         else { 
             let location = args[2].rfind(".");
@@ -37,42 +47,112 @@ fn run() -> Result<(), ()> {
             };
             &format!("{}.bin", res) 
         };
-        ProcessChain::store_bytecode_from_file(&args[2], output)?;
+        step(options, |output| ProcessChain::store_bytecode_from_file(&args[2], path, options, output))?;
+        println!("Wrote bytecode to path: {path}");
         Ok(())
     };
     match args[1].as_str() {
-        "-rb" | "--run-binary" => ProcessChain::run_from_bytecode(&args[2])?,
+        "-rb" | "--run-binary" => step(options, |output| ProcessChain::run_from_bytecode(&args[2], options, output))?,
         "-wb" | "--write-binary" => store()?,
-        "-rf" | "--run-file" => ProcessChain::run_from_file(&args[2])?,
+        "-rf" | "--run-file" => step(options, |output| ProcessChain::run_from_file(&args[2], options, output))?,
         "-rfs" | "--run-store" | "--run-and-store-binary" => {
             store()?;
-            ProcessChain::run_from_file(&args[2])?;
+            step(options, |output| ProcessChain::run_from_file(&args[2], options, output))?;
         },
-        "-t" | "--text" => ProcessChain::run_from_text(&args[2])?,
-        "repl" => repl(),
+        "-t" | "--text" => step(options, |output| ProcessChain::run_from_text(&args[2], options, output))?,
+        "repl" => repl(options.clone()),
         arg => println!("Invalid argument `{}` provided.", arg)
     }
     Ok(())
 }
 
-fn repl() {
+/// Run one step of the command line, showing what it gave even if it failed
+fn step(options: &Options, step: impl FnOnce(&mut RunOutput) -> Result<(), CalcError>) -> Result<(), CalcError> {
+    let mut output = RunOutput::default();
+    let result = step(&mut output);
+    show(&output, &options.format);
+    result
+}
+
+/// Print what a run gave. Warnings go to stderr, so that they do not mix with the output of the program
+fn show(output: &RunOutput, format: &NumberFormat) {
+    for warning in &output.warnings {
+        eprintln!("{warning}");
+    }
+    for line in output.emitted.iter().chain(&output.printed) {
+        println!("{line}");
+    }
+    if let Some(results) = &output.results {
+        print_results(results, format);
+    }
+}
+
+/// Print the results of a run on one line
+fn print_results(results: &[Value], format: &NumberFormat) {
+    if results.is_empty() {
+        println!("No results for this expression");
+    } else {
+        println!("Results: {}", results.iter().map(|value| value.format(format)).collect::<Vec<_>>().join(", "));
+    }
+}
+
+/// Handle the repl commands that change how numbers are shown. Returns `false` if the command is not one of them
+fn format_command(command: &str, format: &mut NumberFormat) -> bool {
+    let mut split = command.split_whitespace();
+    let (command, value) = (split.next().unwrap_or(""), split.next());
+    let result = match (command, value) {
+        (".base", Some(value)) => parse_base(value).map(|base| format.base = base),
+        (".precision", Some("off")) => { format.precision = None; Ok(()) },
+        (".precision", Some(value)) => parse_count(value).map(|precision| format.precision = Some(precision)),
+        (".digits", Some("off")) => { format.digits = None; Ok(()) },
+        (".digits", Some(value)) => parse_count(value).map(|digits| format.digits = Some(digits)),
+        (".notation", Some(value)) => Notation::try_from(value).map(|notation| format.notation = notation),
+        (".separators", Some("on")) => { format.separators = true; Ok(()) },
+        (".separators", Some("off")) => { format.separators = false; Ok(()) },
+        (".format", Some("reset")) => { *format = NumberFormat::default(); Ok(()) },
+        (".format", None) => Ok(()),
+        (".base" | ".precision" | ".digits" | ".notation" | ".separators", _) => Err(format!("Expected a value for `{command}`!")),
+        _ => return false,
+    };
+    match result {
+        Ok(()) => println!(
+            "Numbers are shown in base {}, {} notation, precision: {}, significant digits: {}, separators: {}",
+            format.base, format.notation, 
+            format.precision.map_or("off".to_owned(), |precision| precision.to_string()),
+            format.digits.map_or("off".to_owned(), |digits| digits.to_string()),
+            if format.separators { "on" } else { "off" },
+        ),
+        Err(error) => println!("{error}"),
+    }
+    true
+}
+
+fn repl(options: Options) {
     // Introduction
     println!("Running repl...");
     println!("Type `.quit` | `.q` to exit the repl");
     println!("Type `.show variables` | `.show var` to show the variables in the session");
+    println!("Type `.show fn <name>` to show the body of a function, ie. one created by `diff(f)`");
+    println!("Type `.show units` to show the units and currencies that can be used, including the ones loaded with `--units`");
     println!("Type `.time` | `.timer` to time the execution of the code");
     println!("Type `.load <filepath>` to load and execute code (timer does not apply to this)");
     println!("Type `.load bytecode <filepath>` | `.load b <filepath>` to load and execute bytecode (timer does not apply to this)");
+    println!("Type `.base <2|8|10|16>`, `.precision <n|off>`, `.digits <n|off>`, `.notation <normal|sci|eng>` or `.separators <on|off>` to change how numbers are shown");
+    println!("Type `.format` to show how numbers are shown, and `.format reset` to reset it");
+    println!("Type `.seed <n>` to seed the random number generator, and `.seed` to show the seed in use");
 
     let mut symbols = HashMap::new();
     let mut fn_symbols = HashMap::new();
-    let mut pfn_symbols = HashMap::new();
-    let mut p_symbols = HashMap::new();
+    let mut parser_symbols = parser::Symbols::default();
+    // `///` lines, which document the function declared on the next line
+    let mut doc_lines = String::new();
 
     let mut fn_bytecode = vec![];
     let mut functions = HashMap::new();
     
     let mut time = false;
+    let mut options = options;
+    let mut random = options.seed.map_or_else(Random::from_time, Random::new);
     loop {
         print!(">> ");
         std::io::stdout().flush().expect("Failed to flush the buffer");
@@ -81,6 +161,10 @@ fn repl() {
         if [".quit", ".q", ".exit", ".quit()", ".q()", ".stop", ".stop()"].contains(&buffer.trim()) {
             break;
         }
+        if buffer.trim_start().starts_with("///") {
+            doc_lines += buffer.trim_start();
+            continue;
+        }
         let comment: Option<usize> = buffer.find("//");
         match comment {
             Some(location) => buffer = buffer[0..location].to_string(),
@@ -100,7 +184,9 @@ fn repl() {
             match split.next() {
                 Some(filename) => {
                     println!("loading binary file and executing: ");
-                    ProcessChain::run_from_bytecode(filename).ok();
+                    if let Err(error) = step(&options, |output| ProcessChain::run_from_bytecode(filename, &options, output)) {
+                        report(&error, options.error_format);
+                    }
                 },
                 None => println!("Expected file path to load file!"),
             };
@@ -111,7 +197,9 @@ fn repl() {
             match split.next() {
                 Some(filename) => {
                     println!("loading file and executing: ");
-                    ProcessChain::run_from_file(filename).ok();
+                    if let Err(error) = step(&options, |output| ProcessChain::run_from_file(filename, &options, output)) {
+                        report(&error, options.error_format);
+                    }
                 },
                 None => println!("Expected file path to load file!"),
             };
@@ -119,26 +207,59 @@ fn repl() {
         } else if [".show functions", ".show fns", ".disp fns", ".display functions"].contains(&buffer.as_str()) {
             println!("Functions in this session: ");
             println!("BUILTIN FUNCTIONS: ");
-            for (function, (args, _)) in functions::FUNCTIONS {
-                let repeated = "*, ".repeat(args);
-                println!("{function}({})", if args > 0 { &repeated[..(args * 3 - 2)] } else { "" });
+            for (function, (arity, _)) in functions::all_functions() {
+                println!("{function}({})", arity.placeholders());
             }
             println!("USER FUNCTIONS: ");
-            for (key, (args, shadow)) in &pfn_symbols {
+            // The functions declared for the equations of `solve` are private
+            for (key, (args, shadow)) in parser_symbols.functions.iter().filter(|(key, _)| !key.starts_with('<')) {
                 let repeated = "*, ".repeat(*args);
                 println!("{key}({}){}", 
                                     if *args > 0 { &repeated[..(*args * 3 - 2)] } else { "" }, 
                                     if *shadow { " [SHADOWED - UNREACHABLE]" } else { "" }
                         );
+                if let Some(doc) = parser_symbols.docs.get(key) {
+                    for line in doc.lines() {
+                        println!("    /// {line}");
+                    }
+                }
             }
-            if pfn_symbols.is_empty() {
+            if parser_symbols.functions.keys().all(|key| key.starts_with('<')) {
+                println!("None");
+            }
+            continue;
+        } else if let Some(name) = buffer.strip_prefix(".show fn ").or(buffer.strip_prefix(".show function ")) {
+            let name = name.trim();
+            match parser_symbols.bodies.get(name) {
+                Some((arguments, body)) => {
+                    if let Some(doc) = parser_symbols.docs.get(name) {
+                        for line in doc.lines() {
+                            println!("/// {line}");
+                        }
+                    }
+                    println!("{name} {} = {body}", arguments.join(" "));
+                },
+                None => println!("The function `{name}` does not exist!"),
+            }
+            continue;
+        } else if [".show units", ".disp units", ".display units"].contains(&buffer.as_str()) {
+            println!("BUILTIN UNITS: ");
+            for unit in &units::UNITS {
+                println!("{}{} [{}]", unit.name, if unit.prefixable { " (prefixable)" } else { "" }, units::describe_dimension(&unit.dimension));
+            }
+            println!("LOADED UNITS: ");
+            let loaded = units::loaded_units();
+            for unit in loaded.iter() {
+                println!("{} = {} [{}] from {}", unit.name, unit.definition, units::describe_dimension(&unit.dimension), unit.source);
+            }
+            if loaded.is_empty() {
                 println!("None");
             }
             continue;
         } else if [".show variables", ".show var", ".disp var", ".display variables"].contains(&buffer.as_str()) {
             println!("Variables in this session: ");
             for (key, value) in &symbols {
-                if let Some(true) = p_symbols.get(key) {
+                if let Some(true) = parser_symbols.variables.get(key) {
                     println!("{key} = {value} [SHADOWED - UNREACHABLE]");
                 } else {
                     println!("{key} = {value}");
@@ -148,6 +269,21 @@ fn repl() {
                 println!("None");
             }
             continue;
+        } else if buffer == ".seed" || buffer.starts_with(".seed ") {
+            match buffer.split_whitespace().nth(1) {
+                Some(value) => match parse_seed(value) {
+                    Ok(seed) => {
+                        options.seed = Some(seed);
+                        random = Random::new(seed);
+                        println!("The random number generator has been seeded with {seed}");
+                    },
+                    Err(error) => println!("{error}"),
+                },
+                None => println!("The random number generator was seeded with {}", random.seed()),
+            }
+            continue;
+        } else if format_command(&buffer, &mut options.format) {
+            continue;
         } else if [".time", ".timer"].contains(&buffer.as_str()) {
             time = !time;
             println!("The timer is now {}", if time { "on" } else { "off" });
@@ -161,19 +297,30 @@ fn repl() {
         
         buffer = buffer.replace(";", ":");
         
-        let source = Box::leak(Box::new(buffer));
+        // The documentation comes before the line, like in a file
+        let source = Box::leak(Box::new(std::mem::take(&mut doc_lines) + &buffer));
         
         if time { println!("Begin compilation"); }
         let instant = Instant::now();
         
         // Crazy workaround things...
 
-        let lexer = lexer::Lexer::new(source).expect("Failed to initialize the lexer!");
-        let parser = parser::Parser::new_fn_symbols(lexer, pfn_symbols, p_symbols);
+        let lexer = lexer::Lexer::new(source);
+        let parser = parser::Parser::new_fn_symbols(lexer, parser_symbols);
         let mut bytecode_gen = bytecode::Bytecode::new(parser);
-        let (instructions, new_fn_bytecode) = bytecode_gen.generate_fn_bytecode(fn_bytecode.clone());
+        bytecode_gen.set_opt_level(options.opt_level);
+        let result = bytecode_gen.generate_fn_bytecode(fn_bytecode.clone());
+        let spans = bytecode_gen.spans();
 
-        (pfn_symbols, p_symbols) = bytecode_gen.get_symbols();
+        parser_symbols = bytecode_gen.get_symbols();
+
+        let (instructions, new_fn_bytecode) = match result {
+            Ok(result) => result,
+            Err(errors) => {
+                report(&CalcError::Compile { source: source.clone(), errors }, options.error_format);
+                continue;
+            },
+        };
 
         // TODO: Prevent recursive functions from being registered in the parser as valid functions (Very minor issue - gets caught at runtime)
         let mut i = 0;
@@ -208,14 +355,29 @@ fn repl() {
         if time { println!("Finished compilation in {:?}", instant.elapsed()); }
         
         let mut vm = vm::VM::new_with_symbols(instructions, symbols, fn_symbols);
+        vm.set_spans(source, spans);
+        vm.set_format(options.format);
+        vm.set_random(random);
         
         if time { println!("Begin run"); }
         let instant = Instant::now();
         
-        vm.execute_all();
-
-        vm.print_output();
+        let result = vm.execute_all();
+        for line in vm.take_printed() {
+            println!("{line}");
+        }
+        match result {
+            Ok(results) => print_results(&results, &options.format),
+            Err(error) => {
+                let results = vm.take_outputs();
+                if !results.is_empty() {
+                    print_results(&results, &options.format);
+                }
+                report(&error, options.error_format);
+            },
+        }
 
+        random = vm.get_random();
         (symbols, fn_symbols) = vm.get_symbols();
         
         if time { println!("Finished run in {:?}", instant.elapsed()); }
@@ -0,0 +1,269 @@
+use crate::{functions::{expect_integer, Arity, Builtin}, instruction::Value, vm::VMError};
+
+/// Take an integer argument that must not be negative
+fn expect_natural(value: &Value, function: &str) -> Result<u64, VMError> {
+    let number = expect_integer(value, function)?;
+    if number < 0 {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` expected an integer that is not negative but found {number}!")));
+    }
+    Ok(number as u64)
+}
+
+fn gcd_u64(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+fn mul_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    ((a as u128 * b as u128) % modulus as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, modulus: u64) -> u64 {
+    let mut result = 1 % modulus;
+    base %= modulus;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, modulus);
+        }
+        base = mul_mod(base, base, modulus);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// A deterministic Miller-Rabin test. These bases are enough for every 64 bit integer
+fn prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    for base in BASES {
+        if n.is_multiple_of(base) {
+            return n == base;
+        }
+    }
+    let mut d = n - 1;
+    let mut s = 0;
+    while d.is_multiple_of(2) {
+        d /= 2;
+        s += 1;
+    }
+    'witness: for base in BASES {
+        let mut x = pow_mod(base, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// Find a non trivial factor of a composite number with Pollard's rho algorithm
+fn pollard_rho(n: u64) -> u64 {
+    if n.is_multiple_of(2) {
+        return 2;
+    }
+    let mut c = 1;
+    loop {
+        let step = |x: u64| (mul_mod(x, x, n) + c) % n;
+        let (mut x, mut y, mut d) = (2, 2, 1);
+        while d == 1 {
+            x = step(x);
+            y = step(step(y));
+            d = gcd_u64(x.abs_diff(y), n);
+        }
+        if d != n {
+            return d;
+        }
+        c += 1;
+    }
+}
+
+/// The prime factors of a number in ascending order, repeated as many times as they divide it
+fn prime_factors(n: u64) -> Vec<u64> {
+    let mut factors = vec![];
+    let mut remaining = n;
+    for small in [2, 3, 5, 7, 11, 13] {
+        while remaining.is_multiple_of(small) {
+            factors.push(small);
+            remaining /= small;
+        }
+    }
+    let mut stack = vec![remaining];
+    while let Some(n) = stack.pop() {
+        if n == 1 {
+            continue;
+        }
+        if prime(n) {
+            factors.push(n);
+            continue;
+        }
+        let factor = pollard_rho(n);
+        stack.push(factor);
+        stack.push(n / factor);
+    }
+    factors.sort_unstable();
+    factors
+}
+
+fn gcd(values: &[Value]) -> Result<Value, VMError> {
+    let a = expect_integer(&values[0], "gcd")?;
+    let b = expect_integer(&values[1], "gcd")?;
+    Ok(Value::Number(gcd_u64(a.unsigned_abs(), b.unsigned_abs()) as f64))
+}
+
+fn lcm(values: &[Value]) -> Result<Value, VMError> {
+    let a = expect_integer(&values[0], "lcm")?.unsigned_abs();
+    let b = expect_integer(&values[1], "lcm")?.unsigned_abs();
+    if a == 0 || b == 0 {
+        return Ok(Value::Number(0.0));
+    }
+    Ok(Value::Number((a as u128 / gcd_u64(a, b) as u128 * b as u128) as f64))
+}
+
+fn is_prime(values: &[Value]) -> Result<Value, VMError> {
+    let n = expect_integer(&values[0], "is_prime")?;
+    Ok(Value::Number(if n > 0 && prime(n as u64) { 1.0 } else { 0.0 }))
+}
+
+fn next_prime(values: &[Value]) -> Result<Value, VMError> {
+    let n = expect_integer(&values[0], "next_prime")?;
+    let mut candidate = if n < 2 { 2 } else { n as u64 + 1 };
+    while !prime(candidate) {
+        candidate += 1;
+    }
+    Ok(Value::Number(candidate as f64))
+}
+
+fn factor(values: &[Value]) -> Result<Value, VMError> {
+    let n = expect_natural(&values[0], "factor")?;
+    if n == 0 {
+        return Err(VMError::OutOfDomain("The function `factor` cannot factor 0!".to_owned()));
+    }
+    Ok(Value::Vector(prime_factors(n).into_iter().map(|factor| factor as f64).collect()))
+}
+
+/// The inverse of `a` modulo `modulus`, using the extended Euclidean algorithm
+fn inverse_mod(a: i64, modulus: u64, function: &str) -> Result<u64, VMError> {
+    let modulus = modulus as i128;
+    let (mut old_r, mut r) = ((a as i128).rem_euclid(modulus), modulus);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let quotient = old_r / r;
+        (old_r, r) = (r, old_r - quotient * r);
+        (old_s, s) = (s, old_s - quotient * s);
+    }
+    if old_r != 1 {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` found that {a} has no inverse modulo {modulus}, since they share the factor {old_r}!")));
+    }
+    Ok(old_s.rem_euclid(modulus) as u64)
+}
+
+fn expect_modulus(value: &Value, function: &str) -> Result<u64, VMError> {
+    let modulus = expect_integer(value, function)?;
+    if modulus < 1 {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` expected a positive modulus but found {modulus}!")));
+    }
+    Ok(modulus as u64)
+}
+
+fn mod_pow(values: &[Value]) -> Result<Value, VMError> {
+    let base = expect_integer(&values[0], "mod_pow")?;
+    let exponent = expect_integer(&values[1], "mod_pow")?;
+    let modulus = expect_modulus(&values[2], "mod_pow")?;
+    // A negative exponent is a power of the inverse
+    let base = if exponent < 0 {
+        inverse_mod(base, modulus, "mod_pow")?
+    } else {
+        (base as i128).rem_euclid(modulus as i128) as u64
+    };
+    Ok(Value::Number(pow_mod(base, exponent.unsigned_abs(), modulus) as f64))
+}
+
+fn mod_inv(values: &[Value]) -> Result<Value, VMError> {
+    let a = expect_integer(&values[0], "mod_inv")?;
+    let modulus = expect_modulus(&values[1], "mod_inv")?;
+    Ok(Value::Number(inverse_mod(a, modulus, "mod_inv")? as f64))
+}
+
+fn totient(values: &[Value]) -> Result<Value, VMError> {
+    let n = expect_natural(&values[0], "totient")?;
+    if n == 0 {
+        return Ok(Value::Number(0.0));
+    }
+    let mut factors = prime_factors(n);
+    factors.dedup();
+    let result = factors.iter().fold(n, |result, factor| result / factor * (factor - 1));
+    Ok(Value::Number(result as f64))
+}
+
+/// `n! / (n - r)!`, divided by `r!` for combinations. Exact while it fits in 128 bits
+fn arrangements(n: u64, r: u64, combinations: bool) -> f64 {
+    // Choosing `r` is the same as leaving out `n - r`, which takes fewer steps
+    let r = if combinations { r.min(n - r) } else { r };
+    let mut exact: Option<u128> = Some(1);
+    let mut approximate = 1.0;
+    for i in 1..=r {
+        let factor = n - r + i;
+        exact = exact.and_then(|value| value.checked_mul(factor as u128)).map(|value| if combinations { value / i as u128 } else { value });
+        approximate *= factor as f64 / if combinations { i as f64 } else { 1.0 };
+        // Every step makes the result larger, so it stays infinite once it is
+        if exact.is_none() && approximate.is_infinite() {
+            return f64::INFINITY;
+        }
+    }
+    exact.map_or(approximate, |value| value as f64)
+}
+
+macro_rules! decl_arrangement_fn {
+    ($($name: ident => $combinations: expr,)*) => {
+        // Named after the calculator buttons
+        $(#[allow(non_snake_case)]
+        fn $name(values: &[Value]) -> Result<Value, VMError> {
+            let n = expect_natural(&values[0], stringify!($name))?;
+            let r = expect_natural(&values[1], stringify!($name))?;
+            if r > n {
+                return Ok(Value::Number(0.0));
+            }
+            Ok(Value::Number(arrangements(n, r, $combinations)))
+        })*
+    };
+}
+
+decl_arrangement_fn!(nCr => true, nPr => false, );
+
+fn fib(values: &[Value]) -> Result<Value, VMError> {
+    let n = expect_natural(&values[0], "fib")?;
+    let (mut a, mut b) = (0u128, 1u128);
+    let (mut approximate_a, mut approximate_b) = (0.0f64, 1.0f64);
+    for _ in 0..n {
+        (a, b) = (b, a.saturating_add(b));
+        (approximate_a, approximate_b) = (approximate_b, approximate_a + approximate_b);
+        if approximate_a.is_infinite() {
+            break;
+        }
+    }
+    Ok(Value::Number(if a < u128::MAX { a as f64 } else { approximate_a }))
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 11] = [
+    ("gcd", (Arity::exactly(2), Builtin::Pure(gcd))),
+    ("lcm", (Arity::exactly(2), Builtin::Pure(lcm))),
+    ("is_prime", (Arity::exactly(1), Builtin::Pure(is_prime))),
+    ("next_prime", (Arity::exactly(1), Builtin::Pure(next_prime))),
+    ("factor", (Arity::exactly(1), Builtin::Pure(factor))),
+    ("mod_pow", (Arity::exactly(3), Builtin::Pure(mod_pow))),
+    ("mod_inv", (Arity::exactly(2), Builtin::Pure(mod_inv))),
+    ("totient", (Arity::exactly(1), Builtin::Pure(totient))),
+    ("nCr", (Arity::exactly(2), Builtin::Pure(nCr))),
+    ("nPr", (Arity::exactly(2), Builtin::Pure(nPr))),
+    ("fib", (Arity::exactly(1), Builtin::Pure(fib))),
+];
@@ -0,0 +1,112 @@
+use crate::{bytecode::Code, functions::{get_function, Builtin}, instruction::{Instruction, Value}, linalg, vm::VM};
+
+/// How much the bytecode generator optimises, chosen with `-O0` or `-O1`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OptLevel {
+    /// Keep the bytecode exactly as it was generated, which helps when debugging the generator
+    O0,
+    /// Fold constants and remove values that are never used
+    #[default]
+    O1,
+}
+
+/// The values loaded by the last `count` instructions, if every one of them loads a constant
+fn constants(instructions: &[Instruction], count: usize) -> Option<Vec<Value>> {
+    let start = instructions.len().checked_sub(count)?;
+    instructions[start..].iter().map(|instruction| match instruction {
+        Instruction::Load { value } => Some(value.clone()),
+        _ => None,
+    }).collect()
+}
+
+/// How many values an instruction takes off the stack, for the instructions that can be folded
+fn operands(instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Binary { .. } => Some(2),
+        Instruction::Unary { .. } | Instruction::Convert { .. } => Some(1),
+        Instruction::Vector { length } => Some(*length),
+        // Only pure functions give the same result every time. The random functions and the ones that call other
+        // functions need the VM
+        Instruction::FunctionCall { name, arguments } => match get_function(name) {
+            Some((arity, Builtin::Pure(..))) if arity.accepts(*arguments) => Some(*arguments),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Run an instruction on constants ahead of time. Anything that fails is left to fail at runtime, with the same message
+fn evaluate(instruction: &Instruction, mut values: Vec<Value>) -> Option<Value> {
+    let result = match instruction {
+        Instruction::Binary { operator } => {
+            let rhs = values.pop()?;
+            let lhs = values.pop()?;
+            VM::binary(*operator, lhs, rhs).ok()
+        },
+        Instruction::Unary { operator } => VM::unary(*operator, values.pop()?).ok(),
+        Instruction::Convert { units } => VM::convert(values.pop()?, units).ok(),
+        Instruction::Vector { .. } => linalg::build(values).ok(),
+        Instruction::FunctionCall { name, .. } => match get_function(name) {
+            Some((_, Builtin::Pure(function))) => function(&values).ok(),
+            _ => None,
+        },
+        _ => None,
+    };
+    // Functions are looked up when they are called, so they are never folded into a constant
+    result.filter(|value| !matches!(value, Value::Function(..)))
+}
+
+/// Replace operations on constants with their results, ie. `Load 2, Load 3, Binary Plus` with `Load 5`. The result
+/// takes the span of the operation that made it
+fn fold_constants(code: Code) -> Code {
+    let mut folded = Code::default();
+    // The values loaded before this are not on top of the stack for the next instruction, since they are in the body
+    // of a series
+    let mut barrier = 0;
+    let mut instructions = code.instructions.into_iter().zip(code.spans);
+    while let Some((instruction, span)) = instructions.next() {
+        if let Instruction::Series { index, operator, length } = instruction {
+            let (body, spans) = instructions.by_ref().take(length).unzip();
+            let body = fold_constants(Code { instructions: body, spans });
+            folded.push(Instruction::Series { index, operator, length: body.instructions.len() }, span);
+            folded.append(body);
+            barrier = folded.instructions.len();
+            continue;
+        }
+        let result = operands(&instruction)
+            .and_then(|count| Some((count, constants(&folded.instructions[barrier..], count)?)))
+            .and_then(|(count, values)| Some((count, evaluate(&instruction, values)?)));
+        match result {
+            Some((count, value)) => {
+                let length = folded.instructions.len() - count;
+                folded.instructions.truncate(length);
+                folded.spans.truncate(length);
+                folded.push(Instruction::Load { value }, span);
+            },
+            None => folded.push(instruction, span),
+        }
+    }
+    folded
+}
+
+/// Optimise the bytecode of a single statement
+pub fn optimize(code: Code) -> Code {
+    let length = code.instructions.len();
+    let mut code = fold_constants(code);
+    let removed = length - code.instructions.len();
+    match code.instructions.as_slice() {
+        // A value that is never used, ie. from `2 + 3;`
+        [Instruction::Load { .. } | Instruction::Null] => {
+            code.instructions.clear();
+            code.spans.clear();
+        },
+        // The body of the function has shrunk, so its end has moved. Only the body can shrink, and in the repl the
+        // declaration is followed by an `Output`, so the end is not always the end of the statement
+        [Instruction::FunctionDecl { .. }, _, Instruction::UData { number }, ..] => {
+            let end = number - removed;
+            code.instructions[2] = Instruction::UData { number: end };
+        },
+        _ => (),
+    }
+    code
+}
@@ -1,6 +1,19 @@
 
-use std::{collections::HashMap, rc::Rc};
-use crate::{ast::{Operator, Tree, AST}, errors::Error, functions::get_function, lexer::Lexer, tokens::{NumberType, Token, TokenType}, utils::Span};
+use std::{collections::{HashMap, VecDeque}, ops::Range, rc::Rc};
+use crate::{ast::{Operator, Tree, AST}, diagnostics::did_you_mean, errors::Error, functions::{all_functions, get_function}, lexer::Lexer, tokens::{NumberType, Token, TokenType}, simplify, symbolic::{self, FunctionBody}, units::{self, Units}, utils::{intern, Span}};
+
+/// The names a parser knows about, which the REPL carries over from one line to the next
+#[derive(Clone, Default)]
+pub struct Symbols<'a> {
+    /// The arity of each user function, and whether it shadows a built in function
+    pub functions: HashMap<&'a str, (usize, bool)>,
+    /// Every variable, and whether it shadows a unit
+    pub variables: HashMap<&'a str, bool>,
+    /// The bodies of user functions, for symbolic differentiation
+    pub bodies: HashMap<&'a str, FunctionBody<'a>>,
+    /// The `///` comments before the declarations of user functions, for `help(f)`
+    pub docs: HashMap<&'a str, String>,
+}
 
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
@@ -8,6 +21,21 @@ pub struct Parser<'a> {
     pub(crate) eof: bool,
     pub(crate) function_symbols: HashMap<&'a str, (usize, bool)>,
     pub(crate) symbols: HashMap<&'a str, bool>,
+    /// The bodies of user functions, for symbolic differentiation
+    pub(crate) function_bodies: HashMap<&'a str, FunctionBody<'a>>,
+    /// The `///` comments before the declarations of user functions, for `help(f)`
+    pub(crate) function_docs: HashMap<&'a str, String>,
+    /// Allow variables that do not exist, for expressions that are only simplified and never run
+    pub(crate) symbolic: bool,
+    /// Where the units of the last quantity end, ie. after the `m` of `5 m`, so that `5 m/s` divides by a second
+    units_end: Option<usize>,
+    /// Functions declared inside the statement being parsed, ie. by `solve("x**2 = 2", x)`
+    declarations: Vec<Rc<Tree<'a>>>,
+    /// The spans of the calls to `diff` and `help` in the statement being parsed. They are replaced by what they give,
+    /// but still declare a function or show its documentation
+    lowered_calls: Vec<Span>,
+    /// Statements that have been parsed but not returned yet, since their declarations come first
+    queued: VecDeque<Rc<Tree<'a>>>,
 }
 macro_rules! create_fn {
     ($self: ident, $below_fn: ident, $token_type: pat) => {{
@@ -17,7 +45,7 @@ macro_rules! create_fn {
             $self.increment()?;
             if $self.eof {
                 return Err(Error::PError { 
-                    message: format!("Expected an expression after the `{}` operator, but found nothing. @ {}", operator, &$self.token.span), 
+                    message: format!("Expected an expression after the `{}` operator, but found nothing.", operator), 
                     span: $self.token.span
                 });
             } else {
@@ -45,16 +73,30 @@ impl<'a> Parser<'a> {
             eof: false,
             function_symbols: HashMap::new(),
             symbols: HashMap::new(),
+            function_bodies: HashMap::new(),
+            function_docs: HashMap::new(),
+            symbolic: false,
+            units_end: None,
+            declarations: vec![],
+            lowered_calls: vec![],
+            queued: VecDeque::new(),
         }
     }
 
-    pub fn new_fn_symbols(lexer: Lexer<'a>, function_symbols: HashMap<&'a str, (usize, bool)>, symbols: HashMap<&'a str, bool>) -> Self {
+    pub fn new_fn_symbols(lexer: Lexer<'a>, symbols: Symbols<'a>) -> Self {
         Self {
             token: Token::null(),
             lexer,
             eof: false,
-            function_symbols,
-            symbols,
+            function_symbols: symbols.functions,
+            symbols: symbols.variables,
+            function_bodies: symbols.bodies,
+            function_docs: symbols.docs,
+            symbolic: false,
+            units_end: None,
+            declarations: vec![],
+            lowered_calls: vec![],
+            queued: VecDeque::new(),
         }
     }
 
@@ -62,7 +104,7 @@ impl<'a> Parser<'a> {
     pub fn generate_expressions(&mut self) -> Vec<Result<Rc<Tree<'a>>, Error>> {
         self.increment().ok();
         let mut expressions = vec![];
-        while !self.eof {
+        while !self.finished() {
             match self.expression(false) {
                 Ok(ast) => {
                     expressions.push(Ok(ast))
@@ -89,8 +131,23 @@ impl<'a> Parser<'a> {
         self.expression(true)
     }
 
+    /// Whether every statement has been parsed and returned
+    pub fn finished(&self) -> bool {
+        self.eof && self.queued.is_empty()
+    }
+
+    /// The spans of the calls to `diff` and `help` in the last statement returned, which do something even though
+    /// they are replaced by a value
+    pub fn lowered_calls(&self) -> &[Span] {
+        &self.lowered_calls
+    }
+
+    pub fn into_symbols(self) -> Symbols<'a> {
+        Symbols { functions: self.function_symbols, variables: self.symbols, bodies: self.function_bodies, docs: self.function_docs }
+    }
+
     pub fn increment(&mut self) -> Result<(), Error>{
-        match self.lexer.next() {
+        match self.lexer.next_token() {
             Ok(token) => {
                 self.token = token;
                 Ok(())
@@ -107,7 +164,58 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a statement. After an error, the rest of the statement is skipped so that the next one can be parsed and
+    /// checked for errors too
     fn expression(&mut self, repl: bool) -> Result<Rc<Tree<'a>>, Error> {
+        if let Some(statement) = self.queued.pop_front() {
+            return Ok(statement);
+        }
+        self.declarations.clear();
+        self.lowered_calls.clear();
+        // There is nothing left to parse only if the statement has not started yet
+        let empty = self.token.token_type == TokenType::EOF;
+        self.statement(repl)
+            .map_err(|error| match error {
+                Error::NoResult if !empty => Error::PError { 
+                    message: "Expected an expression, but the source ended!".to_owned(), 
+                    span: Span::new(self.token.span.start, self.token.span.start),
+                },
+                error => error,
+            })
+            .inspect_err(|_| self.synchronize())
+    }
+
+    /// Add a variable, which shadows a function with the same name if it exists
+    fn declare_variable(&mut self, name: &'a str) {
+        if let Some((_, shadow)) = self.function_symbols.get_mut(name) {
+            *shadow = true;
+        }
+
+        // Unshadow an existing variable name or insert it if it does not
+        if let Some(shadow) = self.symbols.get_mut(name) {
+            *shadow = false;
+        } else {
+            self.symbols.insert(name, false);
+        }
+    }
+
+    /// Skip tokens until the end of the current statement, after its `;` or `:`
+    fn synchronize(&mut self) {
+        loop {
+            match self.token.token_type {
+                TokenType::Semicolon | TokenType::Colon => {
+                    // Errors in the rest of the source are found by the next statement
+                    self.increment().ok();
+                    return;
+                },
+                TokenType::EOF => return,
+                // The lexer moves past invalid tokens too, so they are skipped along with the rest of the statement
+                _ => { self.increment().ok(); },
+            }
+        }
+    }
+
+    fn statement(&mut self, repl: bool) -> Result<Rc<Tree<'a>>, Error> {
         let mut result = self.final_stage()?;
         match &self.token.token_type {
             TokenType::Semicolon => {
@@ -128,7 +236,7 @@ impl<'a> Parser<'a> {
             _ if !repl => {
                 let span = Span::new(self.token.span.start, self.token.span.start);
                 return 
-                    Err(Error::PError {
+                    Err(Error::PMissingTerminator {
                         message: format!("Expected a semicolon (`;`) or colon (`:`) after an expression! Found `{}`", &self.lexer.source[self.token.span.as_range()]), 
                         span,
                     });
@@ -139,11 +247,26 @@ impl<'a> Parser<'a> {
                 result = Rc::new(Tree::new(AST::Output { value: result }, span));
             }
         }
+
+        // Declare the functions the statement uses before it
+        if !self.declarations.is_empty() {
+            self.queued.extend(self.declarations.drain(..));
+            self.queued.push_back(result);
+            return Ok(self.queued.pop_front().unwrap());
+        }
         Ok(result)
     }
 
     fn final_stage(&mut self) -> Result<Rc<Tree<'a>>, Error> {
-        self.bitor()
+        let mut result = self.bitor()?;
+        // Conversion of quantities to other units: `60 mph to m/s`
+        while self.token.token_type == TokenType::Identifier && &self.lexer.source[self.token.span.as_range()] == "to" {
+            self.increment()?;
+            let start = result.span.start;
+            let (units, end) = self.unit_expression()?;
+            result = Rc::new(Tree::new(AST::Convert { value: result, units }, Span::new(start, end)));
+        }
+        Ok(result)
     }
 
     fn bitor(&mut self) -> Result<Rc<Tree<'a>>, Error> {
@@ -182,7 +305,7 @@ impl<'a> Parser<'a> {
                 self.increment()?;
                 if self.eof {
                     return Err(Error::PError { 
-                        message: format!("Expected an expression after the `{}` operator, but found nothing. @ {}", operator, &self.token.span), 
+                        message: format!("Expected an expression after the `{}` operator, but found nothing.", operator), 
                         span: self.token.span
                     });
                 } else {
@@ -206,12 +329,12 @@ impl<'a> Parser<'a> {
         match self.token.token_type.clone() {
             TokenType::Number { number_type } => {
                 self.increment()?;
-                match number_type {
-                    NumberType::Binary => self.parse_number(span, 2, "Binary"),
+                let number = match number_type {
+                    NumberType::Binary => self.parse_number(span, 2, "Binary")?,
 
-                    NumberType::Octal => self.parse_number(span, 8, "Octal"),
+                    NumberType::Octal => self.parse_number(span, 8, "Octal")?,
 
-                    NumberType::Hex => self.parse_number(span, 16, "Hexadecimal"),
+                    NumberType::Hex => self.parse_number(span, 16, "Hexadecimal")?,
 
                     NumberType::Real => {
                         let real_str = &self.lexer.source[span.as_range()];
@@ -221,7 +344,7 @@ impl<'a> Parser<'a> {
                                 // This too.
                                 Err(parse_error) => {
                                     return Err(Error::PInternalError { 
-                                        message: format!("Real number parse error @ {span}. Message: {parse_error:?}"), 
+                                        message: format!("Real number parse error: {parse_error:?}"), 
                                         span,
                                     });
                                 }
@@ -229,9 +352,10 @@ impl<'a> Parser<'a> {
                         let number_ast = AST::Number {
                             value: real_number,
                         };
-                        Ok(Rc::new(Tree::new(number_ast, span)))
+                        Rc::new(Tree::new(number_ast, span))
                     },
-                }
+                };
+                self.quantity(number)
             }
 
             TokenType::Identifier => {
@@ -248,20 +372,11 @@ impl<'a> Parser<'a> {
                         // Declare a variable while assigning a value to it
                         TokenType::Equal => {
                             self.increment()?;
-                            let result = self.final_stage()?;
+                            // The variable exists even if its value has an error, so that the statements after it do
+                            // not report it as missing
+                            let result = self.final_stage().inspect_err(|_| self.declare_variable(name))?;
                             let end = result.span.end;
-
-                            // Shadow a function with the same name if it exists
-                            if let Some((_, shadow)) = self.function_symbols.get_mut(name) {
-                                *shadow = true;
-                            }
-
-                            // Unshadow an existing variable name or insert it if it does not
-                            if let Some(shadow) = self.symbols.get_mut(name) {
-                                *shadow = false;
-                            } else {
-                                self.symbols.insert(name, false);
-                            }
+                            self.declare_variable(name);
 
                             Ok(Rc::new(
                                 Tree::new(
@@ -274,16 +389,11 @@ impl<'a> Parser<'a> {
                         // A function declaration
                         TokenType::Identifier => {
 
-                            if let Ok(..) = get_function(name) {
+                            if get_function(name).is_some() || ["print", "diff", "help", "simplify", "sum", "prod", "Σ", "Π"].contains(&name) {
                                 return Err(Error::PError { 
                                     message: format!("The function `{name}` is a built in function and cannot be overwritten!"), 
                                     span: identifier_span,
                                 });
-                            } else if name == "print" {
-                                return Err(Error::PError { 
-                                    message: format!("The function `print` is a built in function and cannot be overwritten!"), 
-                                    span: identifier_span,
-                                });
                             }
 
                             let mut arguments = vec![];
@@ -319,8 +429,9 @@ impl<'a> Parser<'a> {
                                     self.symbols.insert(symbol, false);
                                 }
                                                                 
+                                let body = self.final_stage();
                                 let body = 
-                                    match self.final_stage() {
+                                    match body {
                                         Ok(value) => value,
                                         error => {
                                             // Revert back to the previous state if the function is of invalid grammar
@@ -335,6 +446,12 @@ impl<'a> Parser<'a> {
                                 if let Some(shadow) = self.symbols.get_mut(name) {
                                     *shadow = true;
                                 }
+                                self.function_bodies.insert(name, (arguments.clone(), Rc::clone(&body)));
+                                // A function declared again keeps the documentation only if it is documented again
+                                match self.lexer.doc(start) {
+                                    Some(doc) => self.function_docs.insert(name, doc.to_owned()),
+                                    None => self.function_docs.remove(name),
+                                };
 
                                 return Ok(Rc::new(
                                     Tree::new(
@@ -354,18 +471,7 @@ impl<'a> Parser<'a> {
                         // Just declare a variable
                         _ => {
                             let end = self.token.span.end;
-                            
-                            // Shadow a function with the same name if it exists
-                            if let Some((_, shadow)) = self.function_symbols.get_mut(name) {
-                                *shadow = true;
-                            }
-
-                            // Unshadow an existing variable name or insert it if it does not
-                            if let Some(shadow) = self.symbols.get_mut(name) {
-                                *shadow = false;
-                            } else {
-                                self.symbols.insert(name, false);
-                            }
+                            self.declare_variable(name);
 
                             Ok(Rc::new(
                                 Tree::new(
@@ -391,6 +497,8 @@ impl<'a> Parser<'a> {
                             } 
                             if self.function_symbols.contains_key(value) {
                                 self.function_symbols.remove(value);
+                                self.function_bodies.remove(value);
+                                self.function_docs.remove(value);
                                 removed = true;
                             }
 
@@ -474,6 +582,19 @@ impl<'a> Parser<'a> {
                         // Function Call
                         TokenType::OpeningBracket => {
                             self.increment()?;
+
+                            // An equation to solve, ie. `solve("x**2 = 2", x)`
+                            if name == "solve" && self.token.token_type == TokenType::String {
+                                return self.equation(name, start);
+                            }
+
+                            if name == "simplify" {
+                                return self.simplify_call(name, start);
+                            }
+
+                            if let Some(operator) = Self::series_operator(name) {
+                                return self.series(name, operator, start);
+                            }
                             let expr_start = self.token.span.start;
                             let mut expressions = vec![];
                            
@@ -488,8 +609,10 @@ impl<'a> Parser<'a> {
                                 self.increment()?;
                             }
                             
+                            // The end of the `)`, in bytes like every span
+                            let closing = self.token.span;
                             self.increment()?;
-                            let end = self.token.span.end - 1;
+                            let end = closing.end;
 
                             // Print is a special function that can accept any number of arguments
                             if name == "print" {
@@ -500,6 +623,18 @@ impl<'a> Parser<'a> {
                                     )
                                 ));
                             }
+
+                            // Symbolic differentiation happens here, since the VM does not have the bodies of functions
+                            if name == "diff" {
+                                self.lowered_calls.push(Span::new(start, end));
+                                return self.diff(name, expressions, Span::new(start, end));
+                            }
+
+                            // The documentation is known once the function has been parsed, so it is looked up here too
+                            if name == "help" {
+                                self.lowered_calls.push(Span::new(start, end));
+                                return self.help(name, expressions, Span::new(start, end));
+                            }
     
                             // Don't allow access to an overshadowed function
                             if let Some((_, true)) = self.function_symbols.get(name) {
@@ -510,27 +645,28 @@ impl<'a> Parser<'a> {
                             }
 
                             match get_function(name) {
-                                Ok((arg_len, _)) => {
-                                    if arg_len != expressions.len() {
-                                        return Err(Error::PError { 
-                                            message: format!("The function `{name}` expected {arg_len} argument(s) but {} argument(s) were found!", expressions.len()), 
-                                            span: Span::new(expr_start, end - 1),
+                                Some((arity, _)) => {
+                                    if !arity.accepts(expressions.len()) {
+                                        return Err(Error::PArity { 
+                                            message: format!("The function `{name}` expected {arity} argument(s) but {} argument(s) were found!", expressions.len()), 
+                                            span: Span::new(expr_start, closing.start),
                                         })
                                     }
                                 },
     
-                                Err(()) => {
+                                None => {
                                     if !self.function_symbols.contains_key(name) {
-                                        return Err(Error::PError { 
-                                            message: format!("The function `{name}` does not exist!"), 
+                                        let functions = all_functions().map(|(name, _)| *name).chain(self.function_symbols.keys().copied());
+                                        return Err(Error::PUnknownFunction { 
+                                            message: format!("The function `{name}` does not exist!{}", did_you_mean(name, functions)), 
                                             span: Span::new(start, end),
                                         });
                                     } else {
                                         let (arg_len, _) = self.function_symbols.get(name).unwrap();
                                         if expressions.len() != *arg_len {
-                                            return Err(Error::PError { 
+                                            return Err(Error::PArity { 
                                                 message: format!("The function `{name}` expected {arg_len} argument(s) but {} argument(s) were found!", expressions.len()), 
-                                                span: Span::new(expr_start, end - 1),
+                                                span: Span::new(expr_start, closing.start),
                                             });
                                         }
                                     }
@@ -559,12 +695,48 @@ impl<'a> Parser<'a> {
                         _ => ()
                     };
 
+                    // A function passed by name, ie. the `f` in `root(f, 0, 2)`. Variables take precedence over functions,
+                    // and user functions take precedence over units
+                    let variable = matches!(self.symbols.get(name), Some(false));
+                    if !variable && matches!(self.function_symbols.get(name), Some((_, false))) {
+                        return Ok(Rc::new(Tree::new(AST::FunctionRef { name }, Span::new(start, ident_end))));
+                    }
+
+                    // A unit on its own continues the units of a quantity, so that `5 m/s` divides by a second. Anywhere
+                    // else it has to come after a number, so that a misspelt name is not taken for a unit. Variables take
+                    // precedence over units
+                    let is_unit = !self.symbols.contains_key(name) && units::is_unit(name);
+                    if is_unit && self.continues_units(start) {
+                        self.units_end = Some(ident_end);
+                        return Ok(Rc::new(
+                            Tree::new(
+                                AST::Unit { units: Units::new(name, 1) },
+                                Span::new(start, ident_end)
+                            )
+                        ));
+                    }
+
+                    if !self.symbols.contains_key(name) && get_function(name).is_some() {
+                        return Ok(Rc::new(Tree::new(AST::FunctionRef { name }, Span::new(start, ident_end))));
+                    }
+
                     // Check if the variable exists
+                    if self.symbolic && !self.symbols.contains_key(name) {
+                        return Ok(Rc::new(Tree::new(AST::Identifier { name }, Span::new(start, ident_end))));
+                    }
                     if let None = self.symbols.get(name) {
-                        return Err(Error::PError { 
-                            message: format!("The variable `{name}` does not exist!"), 
-                            span: Span::new(start, ident_end),
-                        })
+                        // Functions can be passed by name, so they might have been meant too
+                        let names = self.symbols.iter()
+                            .filter(|(_, shadowed)| !**shadowed)
+                            .map(|(name, _)| *name)
+                            .chain(self.function_symbols.keys().copied())
+                            .chain(all_functions().map(|(name, _)| *name));
+                        let message = if is_unit {
+                            format!("The variable `{name}` does not exist! Units are written after a number, ie. `1 {name}`")
+                        } else {
+                            format!("The variable `{name}` does not exist!{}", did_you_mean(name, names))
+                        };
+                        return Err(Error::PUnknownVariable { message, span: Span::new(start, ident_end) })
                     }
 
                     // Don't allow access to an overshadowed variable
@@ -592,17 +764,35 @@ impl<'a> Parser<'a> {
                     TokenType::ClosingBracket => self.increment()?,
                      
                     _ => {
-                        let span = Span::new(self.token.span.start, self.token.span.start);
                         return 
-                            Err(Error::PError { 
-                                message: format!("Expected closing bracket `) | ]`! Found `{}` @ {}", &self.token.token_type, span), 
-                                span,
+                            Err(Error::PLabelled { 
+                                message: format!("Expected closing bracket `)`! Found `{}`", &self.token.token_type), 
+                                span: Span::new(self.token.span.start, self.token.span.start),
+                                label: (span, "this bracket is never closed".to_owned()),
                             });
                     }
                 };
                 Ok(result)
             }
 
+            TokenType::OpeningSquareBracket => {
+                self.increment()?;
+                let mut elements = vec![];
+                while self.token.token_type != TokenType::ClosingSquareBracket {
+                    elements.push(self.final_stage()?);
+
+                    if self.token.token_type == TokenType::ClosingSquareBracket {
+                        break;
+                    }
+
+                    self.expect(TokenType::Comma)?;
+                    self.increment()?;
+                }
+                let end = self.token.span.end;
+                self.increment()?;
+                Ok(Rc::new(Tree::new(AST::Vector { elements }, Span::new(span.start, end))))
+            }
+
             TokenType::String => {
                 self.increment()?;
                 let unprocessed_contents = &self.lexer.source[(span.start + 1)..(span.end - 1)];
@@ -637,7 +827,10 @@ impl<'a> Parser<'a> {
             TokenType::EOF => Err(Error::NoResult),
 
             token => {
-                self.increment()?;
+                // A terminator is left for the recovery to stop at, so that the statement after it is still parsed
+                if !matches!(token, TokenType::Semicolon | TokenType::Colon) {
+                    self.increment()?;
+                }
                 Err(Error::PInvalidStatement {
                     message: format!("An unexpected or invalid token `{}` was found", token),
                     span,
@@ -648,16 +841,359 @@ impl<'a> Parser<'a> {
     
 
 
+    /// `solve("x**2 = 2", x)` or `solve("x**2 = 2", x, guess)`, which finds a root of the difference of the sides of the
+    /// equation. The difference is declared as a function before the statement, with a name like `<solve#1>` that cannot
+    /// be written in the source
+    fn equation(&mut self, solve: &'a str, start: usize) -> Result<Rc<Tree<'a>>, Error> {
+        let equation_span = self.token.span;
+        let source = self.lexer.source;
+        self.increment()?;
+        self.expect(TokenType::Comma)?;
+        self.increment()?;
+        self.expect(TokenType::Identifier)?;
+        let variable = &source[self.token.span.as_range()];
+        self.increment()?;
+
+        let guess = if self.token.token_type == TokenType::Comma {
+            self.increment()?;
+            self.final_stage()?
+        } else {
+            Rc::new(Tree::new(AST::Number { value: 1.0 }, equation_span))
+        };
+        self.expect(TokenType::ClosingBracket)?;
+        let end = self.token.span.end;
+        self.increment()?;
+
+        let equals = source[equation_span.as_range()].match_indices('=').map(|(index, _)| equation_span.start + index).collect::<Vec<_>>();
+        let equals = match equals[..] {
+            [equals] => equals,
+            _ => return Err(Error::PError { 
+                message: format!("The equation passed to `{solve}` must contain exactly one `=`, but {} were found!", equals.len()), 
+                span: equation_span,
+            }),
+        };
+        let lhs = self.equation_side(solve, variable, (equation_span.start + 1)..equals)?;
+        let rhs = self.equation_side(solve, variable, (equals + 1)..(equation_span.end - 1))?;
+        let body_span = Span::new(lhs.span.start, rhs.span.end);
+        let body = Rc::new(Tree::new(AST::BinaryOp { lhs, rhs, op: Operator::Minus }, body_span));
+
+        // The smallest number not taken, so that functions declared before that still call theirs are left alone
+        let name = (1..).map(|number| format!("<{solve}#{number}>")).find(|name| !self.function_symbols.contains_key(name.as_str())).unwrap();
+        let name = intern(name);
+        self.function_symbols.insert(name, (1, false));
+        self.declarations.push(Rc::new(Tree::new(AST::FunctionDecl { name, arguments: vec![variable], body }, equation_span)));
+        let function = Rc::new(Tree::new(AST::FunctionRef { name }, equation_span));
+        Ok(Rc::new(
+            Tree::new(
+                AST::FunctionCall { name: solve, expressions: vec![function, guess] },
+                Span::new(start, end)
+            )
+        ))
+    }
+
+    /// Parse one side of an equation, in which the variable being solved for is an argument
+    fn equation_side(&mut self, solve: &'a str, variable: &'a str, range: Range<usize>) -> Result<Rc<Tree<'a>>, Error> {
+        let mut symbols = Symbols {
+            functions: self.function_symbols.clone(),
+            variables: self.symbols.clone(),
+            bodies: self.function_bodies.clone(),
+            docs: self.function_docs.clone(),
+        };
+        symbols.variables.insert(variable, false);
+        let lexer = Lexer::with_range(self.lexer.source, range.clone());
+        let mut parser = Parser::new_fn_symbols(lexer, symbols);
+        parser.increment()?;
+        if parser.eof {
+            return Err(Error::PError { 
+                message: format!("A side of the equation passed to `{solve}` is empty!"), 
+                span: Span::new(range.start, range.end),
+            });
+        }
+        let side = parser.final_stage()?;
+        if !parser.eof {
+            return Err(Error::PError { 
+                message: format!("Unexpected `{}` in the equation passed to `{solve}`!", &self.lexer.source[parser.token.span.as_range()]), 
+                span: parser.token.span,
+            });
+        }
+        self.declarations.append(&mut parser.declarations);
+        Ok(side)
+    }
+
+    /// `simplify(expr)`, which is the simplified expression written out as a string. The expression is never run, so its
+    /// variables do not need to exist
+    fn simplify_call(&mut self, simplify: &'a str, start: usize) -> Result<Rc<Tree<'a>>, Error> {
+        let symbolic = std::mem::replace(&mut self.symbolic, true);
+        let expression = self.final_stage();
+        self.symbolic = symbolic;
+        let expression = expression?;
+        if self.token.token_type != TokenType::ClosingBracket {
+            return Err(Error::PError { 
+                message: format!("The function `{simplify}` expected a single expression, but found `{}`!", &self.lexer.source[self.token.span.as_range()]), 
+                span: self.token.span,
+            });
+        }
+        let end = self.token.span.end;
+        self.increment()?;
+        let contents = simplify::simplify(&expression).to_string();
+        Ok(Rc::new(Tree::new(AST::String { contents }, Span::new(start, end))))
+    }
+
+    /// The operator that combines the terms of `sum(i, a, b, expr)` and `prod(i, a, b, expr)`, which can also be written
+    /// `Σ(...)` and `Π(...)`
+    fn series_operator(name: &str) -> Option<Operator> {
+        match name {
+            "sum" | "Σ" => Some(Operator::Plus),
+            "prod" | "Π" => Some(Operator::Multiply),
+            _ => None,
+        }
+    }
+
+    /// `sum(i, a, b, expr)` or `prod(i, a, b, expr)`, in which the index `i` is a variable only inside `expr`
+    fn series(&mut self, name: &'a str, operator: Operator, start: usize) -> Result<Rc<Tree<'a>>, Error> {
+        if self.token.token_type != TokenType::Identifier {
+            return Err(Error::PError { 
+                message: format!("The function `{name}` expected the name of its index first, ie. `{name}(i, 1, 10, i)`, but found `{}`!", &self.lexer.source[self.token.span.as_range()]), 
+                span: self.token.span,
+            });
+        }
+        let index = &self.lexer.source[self.token.span.as_range()];
+        self.increment()?;
+        let mut bound = || -> Result<Rc<Tree<'a>>, Error> {
+            self.expect(TokenType::Comma)?;
+            self.increment()?;
+            self.final_stage()
+        };
+        let from = bound()?;
+        let to = bound()?;
+        self.expect(TokenType::Comma)?;
+        self.increment()?;
+
+        // Bind the index like the argument of a function, only while the body is parsed
+        let old_symbols = self.symbols.clone();
+        self.symbols.insert(index, false);
+        let body = self.final_stage();
+        self.symbols = old_symbols;
+        let body = body?;
+
+        self.expect(TokenType::ClosingBracket)?;
+        let end = self.token.span.end;
+        self.increment()?;
+        Ok(Rc::new(Tree::new(AST::Series { operator, index, from, to, body }, Span::new(start, end))))
+    }
+
+    /// `diff(f)`, which declares `f'` as the derivative of `f` with respect to its first argument, and refers to it
+    fn diff(&mut self, diff: &'a str, expressions: Vec<Rc<Tree<'a>>>, span: Span) -> Result<Rc<Tree<'a>>, Error> {
+        let function = match &expressions[..] {
+            [function] => function,
+            _ => return Err(Error::PArity { 
+                message: format!("The function `{diff}` expected 1 argument(s) but {} argument(s) were found!", expressions.len()), 
+                span,
+            }),
+        };
+        let (arguments, body) = match &function.ast {
+            AST::FunctionRef { name } => match self.function_bodies.get(name) {
+                Some(function_body) => function_body.clone(),
+                None => return Err(Error::PError { 
+                    message: format!("The function `{diff}` can only differentiate user functions, but `{name}` is a built in function!"), 
+                    span: function.span,
+                }),
+            },
+            _ => return Err(Error::PError { 
+                message: format!("The function `{diff}` expected the name of a function, but found `{function}`!"), 
+                span: function.span,
+            }),
+        };
+        let AST::FunctionRef { name } = function.ast else { unreachable!() };
+        let variable = match arguments.first() {
+            Some(variable) => *variable,
+            None => return Err(Error::PError { 
+                message: format!("The function `{name}` has no arguments to differentiate with respect to!"), 
+                span: function.span,
+            }),
+        };
+        let derivative = symbolic::differentiate(name, &body, variable, &self.function_bodies)
+            .map_err(|message| Error::PError { message, span })?;
+
+        let derivative_name = intern(format!("{name}'"));
+        self.function_symbols.insert(derivative_name, (arguments.len(), false));
+        self.function_bodies.insert(derivative_name, (arguments.clone(), Rc::clone(&derivative)));
+        self.declarations.push(Rc::new(Tree::new(AST::FunctionDecl { name: derivative_name, arguments, body: derivative }, span)));
+        Ok(Rc::new(Tree::new(AST::FunctionRef { name: derivative_name }, span)))
+    }
+
+    /// `help(f)`, which becomes a string with the arguments of the function and the `///` comments before its
+    /// declaration
+    fn help(&self, help: &'a str, expressions: Vec<Rc<Tree<'a>>>, span: Span) -> Result<Rc<Tree<'a>>, Error> {
+        let function = match &expressions[..] {
+            [function] => function,
+            _ => return Err(Error::PArity { 
+                message: format!("The function `{help}` expected 1 argument(s) but {} argument(s) were found!", expressions.len()), 
+                span,
+            }),
+        };
+        let AST::FunctionRef { name } = function.ast else {
+            return Err(Error::PError { 
+                message: format!("The function `{help}` expected the name of a function, but found `{function}`!"), 
+                span: function.span,
+            });
+        };
+        let contents = match (self.function_bodies.get(name), get_function(name)) {
+            (Some((arguments, _)), _) => {
+                let doc = self.function_docs.get(name).map_or("This function has no documentation. Write `///` comments before its declaration to add some", String::as_str);
+                format!("{name}({})\n{doc}", arguments.join(", "))
+            },
+            (None, Some((arity, _))) => format!("{name}({})\nA built in function", arity.placeholders()),
+            (None, None) => return Err(Error::PUnknownFunction { 
+                message: format!("The function `{name}` does not exist!"), 
+                span: function.span,
+            }),
+        };
+        Ok(Rc::new(Tree::new(AST::String { contents }, span)))
+    }
+
     fn expect(&mut self, token_type: TokenType) -> Result<(), Error> {
         if self.token.token_type != token_type {
             return Err(Error::PError { 
-                message: format!("Expected a token of type: `{token_type}` but found token of type: {} @ {}", self.token.token_type, self.token.span), 
+                message: format!("Expected a token of type: `{token_type}` but found token of type: `{}`", self.token.token_type), 
                 span: self.token.span,
             })
         }
         Ok(())
     } 
 
+    /// Whether a name at `start` is the next unit of the last quantity, ie. the `s` of `5 m/s`
+    fn continues_units(&self, start: usize) -> bool {
+        let Some(end) = self.units_end.filter(|end| *end <= start) else {
+            return false;
+        };
+        let between = self.lexer.source[end..start].trim();
+        // Skip the power of the unit before, ie. the `**2` of `5 kg*m**2/s`
+        let between = match between.strip_prefix("**") {
+            Some(power) => power.trim_start().trim_start_matches('-').trim_start_matches(|c: char| c.is_ascii_digit()).trim_start(),
+            None => between,
+        };
+        matches!(between, "/" | "*")
+    }
+
+    /// Attach the units written directly after a number to it, ie. `5 km` or `3 m**2`. Variables take precedence over
+    /// units
+    fn quantity(&mut self, number: Rc<Tree<'a>>) -> Result<Rc<Tree<'a>>, Error> {
+        let name = &self.lexer.source[self.token.span.as_range()];
+        if self.token.token_type != TokenType::Identifier || self.symbols.contains_key(name) || !units::is_unit(name) {
+            return Ok(number);
+        }
+        let unit_start = self.token.span.start;
+        let mut units = Units::default();
+        let end = self.unit_factor(&mut units)?;
+        self.units_end = Some(end);
+        let start = number.span.start;
+        let time = Self::is_time(&units);
+        let quantity = Rc::new(
+            Tree::new(
+                AST::BinaryOp { 
+                    lhs: number, 
+                    rhs: Rc::new(Tree::new(AST::Unit { units }, Span::new(unit_start, end))), 
+                    op: Operator::Multiply 
+                },
+                Span::new(start, end)
+            )
+        );
+        // A duration written in parts, ie. `3h 20min`
+        if time && matches!(self.token.token_type, TokenType::Number { .. }) {
+            return self.duration(quantity);
+        }
+        Ok(quantity)
+    }
+
+    fn is_time(units: &Units) -> bool {
+        units.dimension() == Units::new("s", 1).dimension()
+    }
+
+    /// The rest of a duration after its first part. Each part is turned into a duration before they are added, so
+    /// `3h 20min` is `duration(3 h) + duration(20 min)`, which keeps them in exact seconds
+    fn duration(&mut self, first: Rc<Tree<'a>>) -> Result<Rc<Tree<'a>>, Error> {
+        let rest = self.base()?;
+        let is_duration = |tree: &Tree| match &tree.ast {
+            AST::FunctionCall { name: "duration", .. } => true,
+            AST::BinaryOp { lhs, op: Operator::Plus, .. } => matches!(&lhs.ast, AST::FunctionCall { name: "duration", .. }),
+            _ => false,
+        };
+        let rest = match &rest.ast {
+            _ if is_duration(&rest) => Rc::clone(&rest),
+            AST::BinaryOp { rhs, op: Operator::Multiply, .. } if matches!(&rhs.ast, AST::Unit { units } if Self::is_time(units)) => {
+                Rc::new(Tree::new(AST::FunctionCall { name: "duration", expressions: vec![Rc::clone(&rest)] }, rest.span))
+            },
+            _ => return Err(Error::PError { 
+                message: format!(
+                    "Expected a length of time like `20min` after `{}` in the duration, but found `{}`!", 
+                    &self.lexer.source[first.span.as_range()], &self.lexer.source[rest.span.as_range()]
+                ), 
+                span: rest.span,
+            }),
+        };
+        let span = Span::new(first.span.start, rest.span.end);
+        let first = Rc::new(Tree::new(AST::FunctionCall { name: "duration", expressions: vec![Rc::clone(&first)] }, first.span));
+        Ok(Rc::new(Tree::new(AST::BinaryOp { lhs: first, rhs: rest, op: Operator::Plus }, span)))
+    }
+
+    /// Parse the units a quantity is converted to: `km/h`, `kg*m/s**2`
+    fn unit_expression(&mut self) -> Result<(Units, usize), Error> {
+        let mut units = Units::default();
+        let mut end = self.unit_factor(&mut units)?;
+        while matches!(self.token.token_type, TokenType::Multiply | TokenType::Divide) {
+            let divide = self.token.token_type == TokenType::Divide;
+            self.increment()?;
+            let mut factor = Units::default();
+            let start = self.token.span.start;
+            end = self.unit_factor(&mut factor)?;
+            for (name, power) in factor.units {
+                units.push(&name, if divide { -power } else { power })
+                    .map_err(|message| Error::PError { message, span: Span::new(start, end) })?;
+            }
+        }
+        Ok((units, end))
+    }
+
+    /// Parse a single unit with an optional integer power, `s` or `s**-2`, returning where it ends
+    fn unit_factor(&mut self, units: &mut Units) -> Result<usize, Error> {
+        self.expect(TokenType::Identifier)?;
+        let start = self.token.span.start;
+        let name = &self.lexer.source[self.token.span.as_range()];
+        if !units::is_unit(name) {
+            return Err(Error::PUnknownUnit { 
+                message: format!("Unknown unit `{name}`!"), 
+                span: self.token.span,
+            });
+        }
+        let mut end = self.token.span.end;
+        self.increment()?;
+
+        let mut power = 1;
+        if self.token.token_type == TokenType::Exponent {
+            self.increment()?;
+            let negative = self.token.token_type == TokenType::Subtract;
+            if negative {
+                self.increment()?;
+            }
+            let span = self.token.span;
+            power = match (&self.token.token_type, self.lexer.source[span.as_range()].parse::<i32>()) {
+                (TokenType::Number { number_type: NumberType::Real }, Ok(power)) => power,
+                _ => return Err(Error::PError { 
+                    message: format!("Expected an integer power for the unit `{name}`!"), 
+                    span,
+                }),
+            };
+            if negative {
+                power = -power;
+            }
+            end = span.end;
+            self.increment()?;
+        }
+        units.push(name, power).map_err(|message| Error::PError { message, span: Span::new(start, end) })?;
+        Ok(end)
+    }
+
     fn parse_number(&mut self, span: Span, radix: u32, number_type: &str) -> Result<Rc<Tree<'a>>, Error> {
         let binary_str = &self.lexer.source[(span.start + 2)..span.end];
         let number = 
@@ -666,7 +1202,7 @@ impl<'a> Parser<'a> {
                 // This *should* never trigger, but here it is
                 Err(parse_error) => {
                     return Err(Error::PInternalError { 
-                        message: format!("{number_type} number parse error @ {span}. Message: {parse_error:?}"), 
+                        message: format!("{number_type} number parse error: {parse_error:?}"), 
                         span,
                     });
                 }
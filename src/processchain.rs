@@ -1,84 +1,294 @@
-use crate::{bytecode::Bytecode, lexer::Lexer, parser::Parser, vm::VM};
+use std::fmt::Display;
 
-pub struct ProcessChain { }
+use crate::{ast::AST, bytecode::Bytecode, errors::{CalcError, Error}, instruction::Instruction, utils::Span, format::{NumberFormat, Notation}, instruction::Value, lexer::Lexer, lint::Lint, optimize::OptLevel, parser::Parser, random::Random, simplify::simplify, vm::VM};
 
-impl ProcessChain {
-    pub fn store_bytecode_from_file(path_in: &str, path_out: &str) -> Result<(), ()> {
-        let source = &match std::fs::read_to_string(path_in) {
-            Ok(result) => result,
-            Err(error) => {
-                println!("An error has occured while reading file from path {path_in}: {error}");
-                return Err(());
-            }
+/// A form of the program to print instead of running it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    /// Each statement after algebraic simplification
+    Simplified,
+    /// Every token, with its span and its text
+    Tokens,
+    /// The tree of each statement, one node on each line
+    Ast,
+    /// The trees of every statement as a JSON array, with the span of each node
+    AstJson,
+}
+
+impl Display for Emit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Simplified => "simplified",
+            Self::Tokens => "tokens",
+            Self::Ast => "ast",
+            Self::AstJson => "ast-json",
         };
-        Self::store_bytecode_from_text(source, path_out)?;
-        Ok(())
+        write!(f, "{string}")
     }
+}
 
-    pub fn store_bytecode_from_text(source: &str, path: &str) -> Result<(), ()> {
-        let lexer = Lexer::new(source)?;
-        let parser = Parser::new(lexer);
-        let mut bytecode_gen = Bytecode::new(parser);
-        let instructions = bytecode_gen.generate_bytecode();
-        let bytecode = match bincode::serialize(&instructions) {
-            Ok(result) => result,
-            Err(error) => panic!("An error occured while trying to read from path {path}: {error}"),
-        };
-        match std::fs::write(path, bytecode) {
-            Ok(()) => println!("Wrote bytecode to path: {path}"),
-            Err(error) => panic!("An error occured while trying to read from path {path}: {error}"),
+impl TryFrom<&str> for Emit {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "simplified" => Ok(Self::Simplified),
+            "tokens" => Ok(Self::Tokens),
+            "ast" => Ok(Self::Ast),
+            "ast-json" => Ok(Self::AstJson),
+            _ => Err(format!("Unknown output `{value}`! Expected `simplified`, `tokens`, `ast` or `ast-json`")),
+        }
+    }
+}
+
+/// How errors are printed, chosen with `--error-format`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ErrorFormat {
+    /// With the lines of the source they are about
+    #[default]
+    Human,
+    /// As JSON objects, one on each line, for editors and scripts
+    Json,
+}
+
+impl Display for ErrorFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Human => "human",
+            Self::Json => "json",
         };
-        Ok(())
+        write!(f, "{string}")
     }
+}
 
-    pub fn run_from_text(source: &str) -> Result<(), ()> {
-        let lexer = Lexer::new(source)?;
+impl TryFrom<&str> for ErrorFormat {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown error format `{value}`! Expected `human` or `json`")),
+        }
+    }
+}
+
+/// Settings for a run, from the command line or the repl
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub format: NumberFormat,
+    /// Seed for the random number generator. Runs with the same seed produce the same random numbers
+    pub seed: Option<u64>,
+    /// Print the program in this form instead of running it
+    pub emit: Option<Emit>,
+    /// How much the bytecode is optimised, from `-O0` or `-O1`
+    pub opt_level: OptLevel,
+    /// Tables of extra units and currencies, from `--units`
+    pub units: Vec<String>,
+    pub error_format: ErrorFormat,
+    /// Lints that do not give warnings, from `--allow`
+    pub allowed_lints: Vec<Lint>,
+    /// Treat warnings as errors, so that nothing is run if there are any
+    pub deny_warnings: bool,
+}
+
+/// What a run gave, for the caller to show. Whatever was given before a failure is kept, ie. the results from before
+/// a runtime error
+#[derive(Debug, Default)]
+pub struct RunOutput {
+    /// The warnings about the source, in the format chosen with `--error-format`
+    pub warnings: Vec<String>,
+    /// The program in the form chosen with `--emit`, one line for each statement or token
+    pub emitted: Vec<String>,
+    /// The lines written by `print(...)`
+    pub printed: Vec<String>,
+    /// The results of the statements ending with `:`. `None` if nothing was run, or if a runtime error stopped the run
+    /// before any result
+    pub results: Option<Vec<Value>>,
+}
+
+pub struct ProcessChain { }
+
+impl ProcessChain {
+    fn read(path: &str) -> Result<String, CalcError> {
+        std::fs::read_to_string(path).map_err(|error| CalcError::Io { path: path.to_owned(), error })
+    }
+
+    /// Generate the bytecode of a source, or every error found in it. Warnings are added to `output`
+    fn compile<'a>(source: &'a str, options: &Options, output: &mut RunOutput) -> Result<(Vec<Instruction<'a>>, Vec<Option<Span>>), CalcError> {
+        let lexer = Lexer::new(source);
         let parser = Parser::new(lexer);
         let mut bytecode_gen = Bytecode::new(parser);
-        let instructions = bytecode_gen.generate_bytecode();
-        let mut vm = VM::new(instructions);
-        vm.execute_all();
-        vm.print_output();
-        Ok(())
+        bytecode_gen.set_opt_level(options.opt_level);
+        bytecode_gen.set_allowed_lints(options.allowed_lints.clone());
+        let instructions = bytecode_gen.generate_bytecode()
+            .map_err(|errors| CalcError::Compile { source: source.to_owned(), errors })?;
+        let warnings = bytecode_gen.warnings();
+        if options.deny_warnings && !warnings.is_empty() {
+            return Err(CalcError::Warnings { source: source.to_owned(), warnings });
+        }
+        output.warnings.extend(warnings.iter().map(|warning| match options.error_format {
+            ErrorFormat::Human => warning.render(source),
+            ErrorFormat::Json => warning.to_json(source),
+        }));
+        Ok((instructions, bytecode_gen.spans()))
     }
 
-    pub fn run_from_bytecode(path: &str) -> Result<(), ()>{
-        let bytecode = match std::fs::read(path) {
-            Ok(result) => result,
-            Err(error) => {
-                println!("An error occured while trying to read from path {path}: {error}");
-                return Err(());
+    /// Run bytecode, adding what it prints and its results to `output`. The results from before a runtime error are
+    /// kept too
+    fn execute<'a>(instructions: Vec<Instruction<'a>>, source: &'a str, spans: Vec<Option<Span>>, options: &Options, output: &mut RunOutput) -> Result<(), CalcError> {
+        let mut vm = VM::new(instructions);
+        vm.set_spans(source, spans);
+        vm.set_format(options.format);
+        if let Some(seed) = options.seed {
+            vm.set_random(Random::new(seed));
+        }
+        let result = vm.execute_all();
+        output.printed.append(&mut vm.take_printed());
+        match result {
+            Ok(results) => {
+                output.results = Some(results);
+                Ok(())
             },
-        };
-
-        let instructions = match bincode::deserialize(&bytecode) {
-            Ok(result) => result,
             Err(error) => {
-                println!("An error occured while trying to read from path {path}: {error}");
-                return Err(());
+                let results = vm.take_outputs();
+                if !results.is_empty() {
+                    output.results = Some(results);
+                }
+                Err(error)
             },
-        };
-        let mut vm = VM::new(instructions);
-        vm.execute_all();
-        vm.print_output();
+        }
+    }
+
+    pub fn store_bytecode_from_file(path_in: &str, path_out: &str, options: &Options, output: &mut RunOutput) -> Result<(), CalcError> {
+        let source = Self::read(path_in)?;
+        Self::store_bytecode_from_text(&source, path_out, options, output)
+    }
+
+    pub fn store_bytecode_from_text(source: &str, path: &str, options: &Options, output: &mut RunOutput) -> Result<(), CalcError> {
+        let (instructions, _) = Self::compile(source, options, output)?;
+        let bytecode = bincode::serialize(&instructions)
+            .map_err(|error| CalcError::Bytecode { path: path.to_owned(), message: error.to_string() })?;
+        std::fs::write(path, bytecode).map_err(|error| CalcError::Io { path: path.to_owned(), error })
+    }
+
+    pub fn run_from_text(source: &str, options: &Options, output: &mut RunOutput) -> Result<(), CalcError> {
+        if let Some(emit) = options.emit {
+            return Self::emit(source, emit, output);
+        }
+        let (instructions, spans) = Self::compile(source, options, output)?;
+        Self::execute(instructions, source, spans, options, output)
+    }
+
+    pub fn run_from_bytecode(path: &str, options: &Options, output: &mut RunOutput) -> Result<(), CalcError> {
+        let bytecode = std::fs::read(path).map_err(|error| CalcError::Io { path: path.to_owned(), error })?;
+        let instructions = bincode::deserialize(&bytecode)
+            .map_err(|error| CalcError::Bytecode { path: path.to_owned(), message: error.to_string() })?;
+        // There is no source to show runtime errors with
+        Self::execute(instructions, "", vec![], options, output)
+    }
+
+    pub fn run_from_file(path: &str, options: &Options, output: &mut RunOutput) -> Result<(), CalcError> {
+        let source = Self::read(path)?;
+        Self::run_from_text(&source, options, output)
+    }
+
+    /// Give every statement in the requested form instead of running them. Variables do not need to exist, since
+    /// nothing is run
+    pub fn emit(source: &str, emit: Emit, output: &mut RunOutput) -> Result<(), CalcError> {
+        if emit == Emit::Tokens {
+            return Self::emit_tokens(source, output);
+        }
+        let lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer);
+        parser.symbolic = true;
+        let mut errors = vec![];
+        let mut trees = vec![];
+        loop {
+            match parser.next_expression() {
+                Ok(tree) => match emit {
+                    Emit::Simplified => {
+                        let tree = simplify(&tree);
+                        match &tree.ast {
+                            AST::Output { value } => output.emitted.push(format!("{value}:")),
+                            _ => output.emitted.push(format!("{tree};")),
+                        }
+                    },
+                    Emit::Ast => output.emitted.push(tree.dump()),
+                    // Given at once, since the statements are a single array
+                    Emit::AstJson => trees.push(tree),
+                    Emit::Tokens => unreachable!(),
+                },
+                Err(Error::NoResult) => (),
+                Err(error) => errors.push(error),
+            }
+            if parser.finished() {
+                break;
+            }
+        }
+        if !errors.is_empty() {
+            return Err(CalcError::Compile { source: source.to_owned(), errors });
+        }
+        if emit == Emit::AstJson {
+            output.emitted.push(serde_json::to_string_pretty(&trees).expect("A tree could not be serialized"));
+        }
         Ok(())
     }
 
-    pub fn run_from_file(path: &str) -> Result<(), ()> {
-        let source = &match std::fs::read_to_string(path) {
-            Ok(result) => result,
-            Err(error) => {
-                println!("An error has occured while reading file from path {path}: {error}");
-                return Err(());
+    /// Give every token of the source. The lexer carries on after an invalid token, so every one of them is reported
+    fn emit_tokens(source: &str, output: &mut RunOutput) -> Result<(), CalcError> {
+        let mut lexer = Lexer::new(source);
+        let mut errors = vec![];
+        loop {
+            match lexer.next_token() {
+                Ok(token) => output.emitted.push(format!("{token:?} `{}`", &source[token.span.as_range()])),
+                Err(Error::TEOF) => break,
+                Err(error) => errors.push(error),
             }
-        };
-        let lexer = Lexer::new(source)?;
-        let parser = Parser::new(lexer);
-        let mut bytecode_gen = Bytecode::new(parser);
-        let instructions = bytecode_gen.generate_bytecode();
-        let mut vm = VM::new(instructions);
-        vm.execute_all();
-        vm.print_output();
+        }
+        if !errors.is_empty() {
+            return Err(CalcError::Compile { source: source.to_owned(), errors });
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Take the `--flag value` options out of the arguments, leaving the rest in place
+pub fn parse_options(args: Vec<String>) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options::default();
+    let mut rest = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("Expected a value after `{name}`!"));
+        match arg.as_str() {
+            "--base" => options.format.base = parse_base(&value(&arg)?)?,
+            "--precision" => options.format.precision = Some(parse_count(&value(&arg)?)?),
+            "--digits" => options.format.digits = Some(parse_count(&value(&arg)?)?),
+            "--notation" => options.format.notation = Notation::try_from(value(&arg)?.as_str())?,
+            "--separators" => options.format.separators = true,
+            "--seed" => options.seed = Some(parse_seed(&value(&arg)?)?),
+            "--emit" => options.emit = Some(Emit::try_from(value(&arg)?.as_str())?),
+            "--units" => options.units.push(value(&arg)?),
+            "--error-format" => options.error_format = ErrorFormat::try_from(value(&arg)?.as_str())?,
+            "--allow" => options.allowed_lints.push(Lint::try_from(value(&arg)?.as_str())?),
+            "--deny-warnings" => options.deny_warnings = true,
+            "-O0" => options.opt_level = OptLevel::O0,
+            "-O1" => options.opt_level = OptLevel::O1,
+            _ => rest.push(arg),
+        }
+    }
+    Ok((options, rest))
+}
+
+pub fn parse_base(value: &str) -> Result<u32, String> {
+    let base = value.parse().map_err(|_| format!("Expected a base but found `{value}`!"))?;
+    NumberFormat::check_base(base)
+}
+
+pub fn parse_seed(value: &str) -> Result<u64, String> {
+    value.parse().map_err(|_| format!("Expected a seed (a positive integer) but found `{value}`!"))
+}
+
+pub fn parse_count(value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("Expected a number of digits but found `{value}`!"))
+}
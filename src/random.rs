@@ -0,0 +1,118 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{functions::{expect_integer, expect_number, Arity, Builtin}, instruction::Value, vm::{VMError, VM}};
+
+/// A xoshiro256** pseudo random number generator. The same seed always produces the same numbers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Random {
+    seed: u64,
+    state: [u64; 4],
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // Spread the seed over the whole state with splitmix64, as recommended for xoshiro
+        let mut splitmix = seed;
+        let mut next = || {
+            splitmix = splitmix.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = splitmix;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        };
+        Self { seed, state: [next(), next(), next(), next()] }
+    }
+
+    /// Seed the generator from the current time, for when no seed has been provided
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Self::new(seed)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+        result
+    }
+
+    /// A number in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer in `[low, high]`, without modulo bias
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        let span = high.wrapping_sub(low) as u64;
+        if span == u64::MAX {
+            return self.next_u64() as i64;
+        }
+        let span = span + 1;
+        let zone = u64::MAX - (u64::MAX % span);
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return low.wrapping_add((value % span) as i64);
+            }
+        }
+    }
+
+    /// A normally distributed number with a mean of 0 and a standard deviation of 1, using the Box-Muller transform
+    pub fn standard_normal(&mut self) -> f64 {
+        // 1 - x is in (0, 1], so the logarithm is always finite
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+fn rand(vm: &mut VM, _: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::Number(vm.random.next_f64()))
+}
+
+fn rand_int(vm: &mut VM, values: &[Value]) -> Result<Value, VMError> {
+    let low = expect_integer(&values[0], "rand_int")?;
+    let high = expect_integer(&values[1], "rand_int")?;
+    if low > high {
+        return Err(VMError::OutOfDomain(format!("The function `rand_int` expected a lower bound that is not greater than the upper bound, but found {low} and {high}!")));
+    }
+    Ok(Value::Number(vm.random.range(low, high) as f64))
+}
+
+fn normal(vm: &mut VM, values: &[Value]) -> Result<Value, VMError> {
+    let mean = expect_number(&values[0], "normal")?;
+    let deviation = expect_number(&values[1], "normal")?;
+    if deviation < 0.0 {
+        return Err(VMError::OutOfDomain(format!("The function `normal` expected a standard deviation that is not negative, but found {deviation}!")));
+    }
+    Ok(Value::Number(mean + deviation * vm.random.standard_normal()))
+}
+
+fn choice(vm: &mut VM, values: &[Value]) -> Result<Value, VMError> {
+    match &values[0] {
+        Value::Vector(vector) if !vector.is_empty() => {
+            let index = vm.random.range(0, vector.len() as i64 - 1) as usize;
+            Ok(Value::Number(vector[index]))
+        },
+        Value::Vector(..) => Err(VMError::OutOfDomain("The function `choice` cannot choose from an empty list!".to_owned())),
+        value => Err(VMError::TypeMismatch(format!("The function `choice` expected a list but found a value of type `{}`!", value.type_of()))),
+    }
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 4] = [
+    ("rand", (Arity::exactly(0), Builtin::WithVM(rand))),
+    ("rand_int", (Arity::exactly(2), Builtin::WithVM(rand_int))),
+    ("normal", (Arity::exactly(2), Builtin::WithVM(normal))),
+    ("choice", (Arity::exactly(1), Builtin::WithVM(choice))),
+];
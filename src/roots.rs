@@ -0,0 +1,117 @@
+use crate::{functions::{describe_function, evaluate, expect_function, expect_number, Arity, Builtin}, instruction::Value, linalg, vm::{VMError, VM}};
+
+const MAX_ITERATIONS: usize = 200;
+
+/// How close to the root the bracketing method has to get
+const TOLERANCE: f64 = 1e-15;
+
+/// Newton's method, with the derivative approximated by a central difference
+fn newton(vm: &mut VM, function: &str, guess: f64) -> Result<f64, VMError> {
+    let mut x = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let y = evaluate(vm, function, "solve", x)?;
+        if y == 0.0 {
+            return Ok(x);
+        }
+        let h = 1e-6 * x.abs().max(1.0);
+        let slope = (evaluate(vm, function, "solve", x + h)? - evaluate(vm, function, "solve", x - h)?) / (2.0 * h);
+        if slope == 0.0 || !slope.is_finite() {
+            return Err(VMError::ErrString(format!("The function `solve` found that {} is flat at {x}! Try another guess", describe_function(function))));
+        }
+        let step = y / slope;
+        x -= step;
+        if !x.is_finite() {
+            return Err(VMError::ErrString(format!("The function `solve` diverged while looking for a root of {}! Try another guess", describe_function(function))));
+        }
+        if step.abs() <= 1e-12 * x.abs().max(1.0) {
+            return Ok(x);
+        }
+    }
+    Err(VMError::ErrString(format!("The function `solve` did not find a root of {} after {MAX_ITERATIONS} iterations! Try a guess closer to the root, or `root` with a bracket", describe_function(function))))
+}
+
+/// Brent's method, which combines bisection with the secant method and inverse quadratic interpolation.
+/// It always converges when the root is bracketed by `a` and `b`
+fn brent(vm: &mut VM, function: &str, mut a: f64, mut b: f64) -> Result<f64, VMError> {
+    let mut fa = evaluate(vm, function, "root", a)?;
+    let mut fb = evaluate(vm, function, "root", b)?;
+    if fa == 0.0 {
+        return Ok(a);
+    }
+    if fb == 0.0 {
+        return Ok(b);
+    }
+    if fa.signum() == fb.signum() {
+        return Err(VMError::OutOfDomain(format!("The function `root` expected `{function}` to change sign between {a} and {b}, but found {fa} and {fb}!")));
+    }
+
+    // `b` is the best guess and `c` is on the other side of the root
+    let (mut c, mut fc) = (b, fb);
+    let (mut d, mut e) = (b - a, b - a);
+    for _ in 0..MAX_ITERATIONS {
+        if fb.signum() == fc.signum() {
+            (c, fc) = (a, fa);
+            (d, e) = (b - a, b - a);
+        }
+        if fc.abs() < fb.abs() {
+            (a, fa) = (b, fb);
+            (b, fb) = (c, fc);
+            (c, fc) = (a, fa);
+        }
+        let tolerance = 2.0 * f64::EPSILON * b.abs() + 0.5 * TOLERANCE;
+        let middle = 0.5 * (c - b);
+        if middle.abs() <= tolerance || fb == 0.0 {
+            return Ok(b);
+        }
+
+        if e.abs() >= tolerance && fa.abs() > fb.abs() {
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * middle * s, 1.0 - s)
+            } else {
+                let (q, r) = (fa / fc, fb / fc);
+                (s * (2.0 * middle * q * (q - r) - (b - a) * (r - 1.0)), (q - 1.0) * (r - 1.0) * (s - 1.0))
+            };
+            if p > 0.0 {
+                q = -q;
+            }
+            p = p.abs();
+            // Only interpolate when it is doing better than bisection
+            if 2.0 * p < (3.0 * middle * q - (tolerance * q).abs()).min((e * q).abs()) {
+                (e, d) = (d, p / q);
+            } else {
+                (d, e) = (middle, middle);
+            }
+        } else {
+            (d, e) = (middle, middle);
+        }
+
+        (a, fa) = (b, fb);
+        b += if d.abs() > tolerance { d } else { tolerance.copysign(middle) };
+        fb = evaluate(vm, function, "root", b)?;
+    }
+    Err(VMError::ErrString(format!("The function `root` did not find a root of `{function}` after {MAX_ITERATIONS} iterations!")))
+}
+
+/// `solve(f, guess)` finds a root of a function, and `solve(A, b)` solves a linear system
+fn solve(vm: &mut VM, values: &[Value]) -> Result<Value, VMError> {
+    match &values[0] {
+        Value::Function(function) => {
+            let guess = expect_number(&values[1], "solve")?;
+            Ok(Value::Number(newton(vm, function, guess)?))
+        },
+        _ => linalg::solve(values),
+    }
+}
+
+fn root(vm: &mut VM, values: &[Value]) -> Result<Value, VMError> {
+    let function = expect_function(&values[0], "root")?;
+    let a = expect_number(&values[1], "root")?;
+    let b = expect_number(&values[2], "root")?;
+    Ok(Value::Number(brent(vm, &function, a, b)?))
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 2] = [
+    ("solve", (Arity::exactly(2), Builtin::WithVM(solve))),
+    ("root", (Arity::exactly(3), Builtin::WithVM(root))),
+];
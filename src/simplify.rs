@@ -0,0 +1,264 @@
+use std::rc::Rc;
+
+use crate::{ast::{Operator, Tree, AST}, functions::{get_function, Builtin}, instruction::Value, utils::Span};
+
+pub fn node<'a>(ast: AST<'a>, span: Span) -> Rc<Tree<'a>> {
+    Rc::new(Tree::new(ast, span))
+}
+
+pub fn number<'a>(value: f64, span: Span) -> Rc<Tree<'a>> {
+    node(AST::Number { value }, span)
+}
+
+pub fn as_number(tree: &Tree) -> Option<f64> {
+    match tree.ast {
+        AST::Number { value } => Some(value),
+        _ => None,
+    }
+}
+
+// These build a new node while folding constants and dropping zeroes and ones, ie. `x * 1` or `x + 0`
+
+pub fn add<'a>(lhs: Rc<Tree<'a>>, rhs: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    match (as_number(&lhs), as_number(&rhs)) {
+        (Some(a), Some(b)) => number(a + b, span),
+        (Some(0.0), _) => rhs,
+        (_, Some(0.0)) => lhs,
+        _ => node(AST::BinaryOp { lhs, rhs, op: Operator::Plus }, span),
+    }
+}
+
+pub fn sub<'a>(lhs: Rc<Tree<'a>>, rhs: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    match (as_number(&lhs), as_number(&rhs)) {
+        (Some(a), Some(b)) => number(a - b, span),
+        (Some(0.0), _) => neg(rhs, span),
+        (_, Some(0.0)) => lhs,
+        _ => node(AST::BinaryOp { lhs, rhs, op: Operator::Minus }, span),
+    }
+}
+
+pub fn mul<'a>(lhs: Rc<Tree<'a>>, rhs: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    match (as_number(&lhs), as_number(&rhs)) {
+        (Some(a), Some(b)) => number(a * b, span),
+        (Some(0.0), _) | (_, Some(0.0)) => number(0.0, span),
+        (Some(1.0), _) => rhs,
+        (_, Some(1.0)) => lhs,
+        (Some(-1.0), _) => neg(rhs, span),
+        (_, Some(-1.0)) => neg(lhs, span),
+        _ => node(AST::BinaryOp { lhs, rhs, op: Operator::Multiply }, span),
+    }
+}
+
+pub fn div<'a>(lhs: Rc<Tree<'a>>, rhs: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    match (as_number(&lhs), as_number(&rhs)) {
+        // Dividing by zero is left for the VM to report
+        (Some(a), Some(b)) if b != 0.0 => number(a / b, span),
+        (Some(0.0), _) => number(0.0, span),
+        (_, Some(1.0)) => lhs,
+        _ => node(AST::BinaryOp { lhs, rhs, op: Operator::Divide }, span),
+    }
+}
+
+pub fn pow<'a>(lhs: Rc<Tree<'a>>, rhs: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    match (as_number(&lhs), as_number(&rhs)) {
+        (Some(a), Some(b)) => number(a.powf(b), span),
+        (_, Some(0.0)) => number(1.0, span),
+        (_, Some(1.0)) => lhs,
+        _ => node(AST::BinaryOp { lhs, rhs, op: Operator::Exponent }, span),
+    }
+}
+
+pub fn neg<'a>(rhs: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    match &rhs.ast {
+        AST::Number { value } => number(-value, span),
+        AST::UnaryOp { rhs, op: Operator::Minus } => Rc::clone(rhs),
+        _ => node(AST::UnaryOp { rhs, op: Operator::Minus }, span),
+    }
+}
+
+/// Split a term into its numeric coefficient and the rest of it, ie. `(3, x)` for `3 * x`. Numbers have no rest
+fn split_coefficient<'a>(tree: &Rc<Tree<'a>>) -> (f64, Option<Rc<Tree<'a>>>) {
+    match &tree.ast {
+        AST::Number { value } => (*value, None),
+        AST::UnaryOp { rhs, op: Operator::Minus } => {
+            let (coefficient, term) = split_coefficient(rhs);
+            (-coefficient, term)
+        },
+        AST::BinaryOp { lhs, rhs, op: Operator::Multiply } => match (as_number(lhs), as_number(rhs)) {
+            (Some(coefficient), _) => (coefficient, Some(Rc::clone(rhs))),
+            (_, Some(coefficient)) => (coefficient, Some(Rc::clone(lhs))),
+            _ => (1.0, Some(Rc::clone(tree))),
+        },
+        _ => (1.0, Some(Rc::clone(tree))),
+    }
+}
+
+fn with_coefficient<'a>(coefficient: f64, term: Option<Rc<Tree<'a>>>, span: Span) -> Rc<Tree<'a>> {
+    match term {
+        Some(term) => mul(number(coefficient, span), term, span),
+        None => number(coefficient, span),
+    }
+}
+
+/// Split a factor into its base and exponent, ie. `(x, 2)` for `x ** 2` and `(x, 1)` for `x`
+fn split_exponent<'a>(tree: &Rc<Tree<'a>>) -> (Rc<Tree<'a>>, Rc<Tree<'a>>) {
+    match &tree.ast {
+        AST::BinaryOp { lhs, rhs, op: Operator::Exponent } => (Rc::clone(lhs), Rc::clone(rhs)),
+        _ => (Rc::clone(tree), number(1.0, tree.span)),
+    }
+}
+
+/// Whether two expressions are written the same way, regardless of where they are in the source
+fn same(lhs: &Tree, rhs: &Tree) -> bool {
+    lhs.to_string() == rhs.to_string()
+}
+
+/// Gather the terms of a sum with their signs, adding up the coefficients of like terms and the constants
+fn gather_terms<'a>(tree: &Rc<Tree<'a>>, sign: f64, terms: &mut Vec<(f64, Rc<Tree<'a>>)>, constant: &mut f64) {
+    match &tree.ast {
+        AST::BinaryOp { lhs, rhs, op: op @ (Operator::Plus | Operator::Minus) } => {
+            gather_terms(lhs, sign, terms, constant);
+            gather_terms(rhs, if *op == Operator::Minus { -sign } else { sign }, terms, constant);
+        },
+        _ => match split_coefficient(tree) {
+            (coefficient, None) => *constant += sign * coefficient,
+            (coefficient, Some(term)) => match terms.iter_mut().find(|(_, other)| same(other, &term)) {
+                Some((total, _)) => *total += sign * coefficient,
+                None => terms.push((sign * coefficient, term)),
+            },
+        },
+    }
+}
+
+/// `2*x + 3*x` is `5*x`, and `x + 1 - x` is `1`. Terms keep the order they first appear in, with constants last
+fn collect_terms<'a>(sum: Rc<Tree<'a>>) -> Rc<Tree<'a>> {
+    let span = sum.span;
+    let (mut terms, mut constant) = (vec![], 0.0);
+    gather_terms(&sum, 1.0, &mut terms, &mut constant);
+    // Adding strings joins them, so their order and their count matter
+    if terms.iter().any(|(_, term)| matches!(term.ast, AST::String { .. })) {
+        return sum;
+    }
+
+    let mut result: Option<Rc<Tree<'a>>> = None;
+    for (coefficient, term) in terms {
+        if coefficient == 0.0 {
+            continue;
+        }
+        result = Some(match result {
+            None => with_coefficient(coefficient, Some(term), span),
+            Some(result) if coefficient < 0.0 => sub(result, with_coefficient(-coefficient, Some(term), span), span),
+            Some(result) => add(result, with_coefficient(coefficient, Some(term), span), span),
+        });
+    }
+    match result {
+        None => number(constant, span),
+        Some(result) if constant < 0.0 => sub(result, number(-constant, span), span),
+        Some(result) => add(result, number(constant, span), span),
+    }
+}
+
+/// Move the coefficients of a product to the front, and write `x * x` as `x ** 2`. The other factors keep their order,
+/// since multiplying matrices depends on it
+fn multiply<'a>(lhs: Rc<Tree<'a>>, rhs: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    let (a, lhs) = split_coefficient(&lhs);
+    let (b, rhs) = split_coefficient(&rhs);
+    let product = match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => {
+            let (lhs_base, lhs_exponent) = split_exponent(&lhs);
+            let (rhs_base, rhs_exponent) = split_exponent(&rhs);
+            if same(&lhs_base, &rhs_base) {
+                Some(pow(lhs_base, collect_terms(add(lhs_exponent, rhs_exponent, span)), span))
+            } else {
+                Some(node(AST::BinaryOp { lhs, rhs, op: Operator::Multiply }, span))
+            }
+        },
+        (lhs, rhs) => lhs.or(rhs),
+    };
+    with_coefficient(a * b, product, span)
+}
+
+fn divide<'a>(lhs: Rc<Tree<'a>>, rhs: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    match as_number(&rhs) {
+        Some(divisor) if divisor != 0.0 => {
+            let (coefficient, term) = split_coefficient(&lhs);
+            with_coefficient(coefficient / divisor, term, span)
+        },
+        _ if same(&lhs, &rhs) && as_number(&rhs).is_none() => number(1.0, span),
+        _ => div(lhs, rhs, span),
+    }
+}
+
+/// Evaluate a built in function when its arguments are all numbers, ie. `sqrt(4)`. Functions that use the state of the
+/// VM, like `rand`, are left alone
+fn fold_call(name: &str, expressions: &[Rc<Tree>]) -> Option<f64> {
+    let arguments = expressions.iter().map(|expression| as_number(expression).map(Value::Number)).collect::<Option<Vec<_>>>()?;
+    match get_function(name) {
+        Some((arity, Builtin::Pure(function))) if arity.accepts(arguments.len()) => match function(&arguments) {
+            Ok(Value::Number(value)) if value.is_finite() => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn simplify_all<'a>(trees: &[Rc<Tree<'a>>]) -> Vec<Rc<Tree<'a>>> {
+    trees.iter().map(simplify).collect()
+}
+
+/// Rewrite an expression algebraically: fold constants, drop identities like `x * 1` and `x + 0`, and collect like terms.
+/// Division by zero is left for the VM to report
+pub fn simplify<'a>(tree: &Rc<Tree<'a>>) -> Rc<Tree<'a>> {
+    let span = tree.span;
+    match &tree.ast {
+        AST::BinaryOp { lhs, rhs, op } => {
+            let (lhs, rhs) = (simplify(lhs), simplify(rhs));
+            match op {
+                Operator::Plus | Operator::Minus => collect_terms(node(AST::BinaryOp { lhs, rhs, op: *op }, span)),
+                Operator::Multiply => multiply(lhs, rhs, span),
+                Operator::Divide => divide(lhs, rhs, span),
+                Operator::Exponent => pow(lhs, rhs, span),
+                op => node(AST::BinaryOp { lhs, rhs, op: *op }, span),
+            }
+        },
+
+        AST::UnaryOp { rhs, op: Operator::Minus } => {
+            let (coefficient, term) = split_coefficient(&simplify(rhs));
+            with_coefficient(-coefficient, term, span)
+        },
+        AST::UnaryOp { rhs, op } => match simplify(rhs) {
+            rhs if as_number(&rhs).is_some() => rhs,
+            rhs => node(AST::UnaryOp { rhs, op: *op }, span),
+        },
+
+        AST::FunctionCall { name, expressions } => {
+            let expressions = simplify_all(expressions);
+            match fold_call(name, &expressions) {
+                Some(value) => number(value, span),
+                None => node(AST::FunctionCall { name, expressions }, span),
+            }
+        },
+
+        AST::Output { value } => node(AST::Output { value: simplify(value) }, span),
+        AST::DeclareAssign { identifier, identifier_span, value } => {
+            node(AST::DeclareAssign { identifier, identifier_span: *identifier_span, value: simplify(value) }, span)
+        },
+        AST::Assign { identifier, identifier_span, value } => {
+            node(AST::Assign { identifier, identifier_span: *identifier_span, value: simplify(value) }, span)
+        },
+        AST::AssignOp { identifier, identifier_span, value, operator } => {
+            node(AST::AssignOp { identifier, identifier_span: *identifier_span, value: simplify(value), operator: *operator }, span)
+        },
+        AST::FunctionDecl { name, arguments, body } => {
+            node(AST::FunctionDecl { name, arguments: arguments.clone(), body: simplify(body) }, span)
+        },
+        AST::Print { expressions } => node(AST::Print { expressions: simplify_all(expressions) }, span),
+        AST::Vector { elements } => node(AST::Vector { elements: simplify_all(elements) }, span),
+        AST::Convert { value, units } => node(AST::Convert { value: simplify(value), units: units.clone() }, span),
+        AST::Series { operator, index, from, to, body } => {
+            node(AST::Series { operator: *operator, index, from: simplify(from), to: simplify(to), body: simplify(body) }, span)
+        },
+
+        _ => Rc::clone(tree),
+    }
+}
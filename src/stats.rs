@@ -0,0 +1,157 @@
+use crate::{functions::{expect_number, Arity, Builtin}, instruction::Value, vm::VMError};
+
+/// Take a list of numbers with at least `minimum` elements
+fn expect_list(value: &Value, function: &str, minimum: usize) -> Result<Vec<f64>, VMError> {
+    let list = match value {
+        Value::Vector(list) => list.clone(),
+        value => return Err(VMError::TypeMismatch(format!("The function `{function}` expected a list but found a value of type `{}`!", value.type_of()))),
+    };
+    if list.len() < minimum {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` expected a list of at least {minimum} number(s) but found {} number(s)!", list.len())));
+    }
+    if list.iter().any(|value| value.is_nan()) {
+        return Err(VMError::OutOfDomain(format!("The function `{function}` cannot be used with a list containing NaN!")));
+    }
+    Ok(list)
+}
+
+/// Take two lists of the same length, ie. the `x` and `y` of data points
+fn expect_pairs(values: &[Value], function: &str) -> Result<(Vec<f64>, Vec<f64>), VMError> {
+    let xs = expect_list(&values[0], function, 2)?;
+    let ys = expect_list(&values[1], function, 2)?;
+    if xs.len() != ys.len() {
+        return Err(VMError::ShapeMismatch(format!("The function `{function}` expected lists of the same length but found lists of length {} and {}!", xs.len(), ys.len())));
+    }
+    Ok((xs, ys))
+}
+
+fn sort(mut list: Vec<f64>) -> Vec<f64> {
+    list.sort_by(f64::total_cmp);
+    list
+}
+
+fn average(list: &[f64]) -> f64 {
+    list.iter().sum::<f64>() / list.len() as f64
+}
+
+/// The sum of squared deviations from the mean, divided by `n - ddof`
+fn spread(list: &[f64], ddof: usize) -> f64 {
+    let mean = average(list);
+    list.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (list.len() - ddof) as f64
+}
+
+/// The percentile of a sorted list, interpolating linearly between the closest ranks
+fn percentile_of(sorted: &[f64], percent: f64) -> f64 {
+    let rank = percent / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+fn mean(values: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::Number(average(&expect_list(&values[0], "mean", 1)?)))
+}
+
+fn median(values: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::Number(percentile_of(&sort(expect_list(&values[0], "median", 1)?), 50.0)))
+}
+
+/// The most common value. Ties are broken by the smallest value
+fn mode(values: &[Value]) -> Result<Value, VMError> {
+    let sorted = sort(expect_list(&values[0], "mode", 1)?);
+    let (mut best, mut best_count) = (sorted[0], 0);
+    let mut i = 0;
+    while i < sorted.len() {
+        let count = sorted[i..].iter().take_while(|value| **value == sorted[i]).count();
+        if count > best_count {
+            (best, best_count) = (sorted[i], count);
+        }
+        i += count;
+    }
+    Ok(Value::Number(best))
+}
+
+macro_rules! decl_spread_fn {
+    ($($name: ident => $ddof: expr, $root: expr,)*) => {
+        $(fn $name(values: &[Value]) -> Result<Value, VMError> {
+            let list = expect_list(&values[0], stringify!($name), $ddof + 1)?;
+            let variance = spread(&list, $ddof);
+            Ok(Value::Number(if $root { variance.sqrt() } else { variance }))
+        })*
+    };
+}
+
+// The sample versions divide by `n - 1` (Bessel's correction), the population versions by `n`
+decl_spread_fn!(
+    variance => 1, false,
+    stddev => 1, true,
+    pvariance => 0, false,
+    pstddev => 0, true,
+);
+
+fn percentile(values: &[Value]) -> Result<Value, VMError> {
+    let sorted = sort(expect_list(&values[0], "percentile", 1)?);
+    let percent = expect_number(&values[1], "percentile")?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(VMError::OutOfDomain(format!("The function `percentile` expected a percentage between 0 and 100 but found {percent}!")));
+    }
+    Ok(Value::Number(percentile_of(&sorted, percent)))
+}
+
+fn min(values: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::Number(sort(expect_list(&values[0], "min", 1)?)[0]))
+}
+
+fn max(values: &[Value]) -> Result<Value, VMError> {
+    let sorted = sort(expect_list(&values[0], "max", 1)?);
+    Ok(Value::Number(sorted[sorted.len() - 1]))
+}
+
+fn sorted(values: &[Value]) -> Result<Value, VMError> {
+    Ok(Value::Vector(sort(expect_list(&values[0], "sorted", 0)?)))
+}
+
+/// Sums of the products of deviations from the means: `(Sxx, Syy, Sxy)`
+fn deviations(xs: &[f64], ys: &[f64]) -> (f64, f64, f64) {
+    let (mean_x, mean_y) = (average(xs), average(ys));
+    xs.iter().zip(ys).fold((0.0, 0.0, 0.0), |(sxx, syy, sxy), (x, y)| {
+        (sxx + (x - mean_x).powi(2), syy + (y - mean_y).powi(2), sxy + (x - mean_x) * (y - mean_y))
+    })
+}
+
+/// Pearson's correlation coefficient
+fn correlation(values: &[Value]) -> Result<Value, VMError> {
+    let (xs, ys) = expect_pairs(values, "correlation")?;
+    let (sxx, syy, sxy) = deviations(&xs, &ys);
+    if sxx == 0.0 || syy == 0.0 {
+        return Err(VMError::OutOfDomain("The function `correlation` is undefined when all the values of a list are the same!".to_owned()));
+    }
+    Ok(Value::Number(sxy / (sxx * syy).sqrt()))
+}
+
+/// A least squares fit of `y = slope * x + intercept`, returned as `[slope, intercept]`
+fn linear_regression(values: &[Value]) -> Result<Value, VMError> {
+    let (xs, ys) = expect_pairs(values, "linear_regression")?;
+    let (sxx, _, sxy) = deviations(&xs, &ys);
+    if sxx == 0.0 {
+        return Err(VMError::OutOfDomain("The function `linear_regression` cannot fit a line when all the `x` values are the same!".to_owned()));
+    }
+    let slope = sxy / sxx;
+    Ok(Value::Vector(vec![slope, average(&ys) - slope * average(&xs)]))
+}
+
+pub const FUNCTIONS: [(&str, (Arity, Builtin)); 13] = [
+    ("mean", (Arity::exactly(1), Builtin::Pure(mean))),
+    ("median", (Arity::exactly(1), Builtin::Pure(median))),
+    ("mode", (Arity::exactly(1), Builtin::Pure(mode))),
+    ("variance", (Arity::exactly(1), Builtin::Pure(variance))),
+    ("stddev", (Arity::exactly(1), Builtin::Pure(stddev))),
+    ("pvariance", (Arity::exactly(1), Builtin::Pure(pvariance))),
+    ("pstddev", (Arity::exactly(1), Builtin::Pure(pstddev))),
+    ("percentile", (Arity::exactly(2), Builtin::Pure(percentile))),
+    ("min", (Arity::exactly(1), Builtin::Pure(min))),
+    ("max", (Arity::exactly(1), Builtin::Pure(max))),
+    ("sorted", (Arity::exactly(1), Builtin::Pure(sorted))),
+    ("correlation", (Arity::exactly(2), Builtin::Pure(correlation))),
+    ("linear_regression", (Arity::exactly(2), Builtin::Pure(linear_regression))),
+];
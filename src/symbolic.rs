@@ -0,0 +1,169 @@
+use std::{collections::HashMap, f64::consts::E, rc::Rc};
+
+use crate::{ast::{Operator, Tree, AST}, simplify::{add, div, mul, neg, node, number, pow, simplify, sub}, utils::Span};
+
+/// The arguments and the body of a user function, kept by the parser after the function has been compiled
+pub type FunctionBody<'a> = (Vec<&'a str>, Rc<Tree<'a>>);
+
+fn call<'a>(name: &'a str, argument: Rc<Tree<'a>>, span: Span) -> Rc<Tree<'a>> {
+    node(AST::FunctionCall { name, expressions: vec![argument] }, span)
+}
+
+/// Whether an expression depends on the variable
+fn contains(tree: &Tree, variable: &str) -> bool {
+    match &tree.ast {
+        AST::Identifier { name } => *name == variable,
+        AST::BinaryOp { lhs, rhs, .. } => contains(lhs, variable) || contains(rhs, variable),
+        AST::UnaryOp { rhs, .. } => contains(rhs, variable),
+        AST::FunctionCall { expressions, .. } | AST::Vector { elements: expressions } => {
+            expressions.iter().any(|expression| contains(expression, variable))
+        },
+        AST::Convert { value, .. } => contains(value, variable),
+        // The index of a series is a different variable inside its body
+        AST::Series { index, from, to, body, .. } => {
+            contains(from, variable) || contains(to, variable) || (*index != variable && contains(body, variable))
+        },
+        _ => false,
+    }
+}
+
+/// Replace the arguments of a function with the expressions it was called with
+fn substitute<'a>(tree: &Rc<Tree<'a>>, arguments: &HashMap<&str, Rc<Tree<'a>>>) -> Rc<Tree<'a>> {
+    let span = tree.span;
+    match &tree.ast {
+        AST::Identifier { name } => match arguments.get(name) {
+            Some(argument) => Rc::clone(argument),
+            None => Rc::clone(tree),
+        },
+        AST::BinaryOp { lhs, rhs, op } => node(AST::BinaryOp { lhs: substitute(lhs, arguments), rhs: substitute(rhs, arguments), op: *op }, span),
+        AST::UnaryOp { rhs, op } => node(AST::UnaryOp { rhs: substitute(rhs, arguments), op: *op }, span),
+        AST::FunctionCall { name, expressions } => {
+            let expressions = expressions.iter().map(|expression| substitute(expression, arguments)).collect();
+            node(AST::FunctionCall { name, expressions }, span)
+        },
+        AST::Series { operator, index, from, to, body } => {
+            let (from, to) = (substitute(from, arguments), substitute(to, arguments));
+            let body = match arguments.contains_key(index) {
+                true => {
+                    let mut arguments = arguments.clone();
+                    arguments.remove(index);
+                    substitute(body, &arguments)
+                },
+                false => substitute(body, arguments),
+            };
+            node(AST::Series { operator: *operator, index, from, to, body }, span)
+        },
+        _ => Rc::clone(tree),
+    }
+}
+
+/// The derivative of a built in function of a single argument, at `argument`
+fn builtin_derivative<'a>(name: &str, argument: &Rc<Tree<'a>>, span: Span) -> Option<Rc<Tree<'a>>> {
+    let g = || Rc::clone(argument);
+    let one = || number(1.0, span);
+    let square = |tree| pow(tree, number(2.0, span), span);
+    let derivative = match name {
+        "sin" => call("cos", g(), span),
+        "cos" => neg(call("sin", g(), span), span),
+        "tan" => div(one(), square(call("cos", g(), span)), span),
+        "asin" => div(one(), call("sqrt", sub(one(), square(g()), span), span), span),
+        "acos" => neg(div(one(), call("sqrt", sub(one(), square(g()), span), span), span), span),
+        "atan" => div(one(), add(one(), square(g()), span), span),
+        // There is no built in `sinh`, so it is written out in terms of `e`
+        "cosh" => div(sub(pow(number(E, span), g(), span), pow(number(E, span), neg(g(), span), span), span), number(2.0, span), span),
+        "asinh" => div(one(), call("sqrt", add(square(g()), one(), span), span), span),
+        "acosh" => div(one(), call("sqrt", sub(square(g()), one(), span), span), span),
+        "atanh" => div(one(), sub(one(), square(g()), span), span),
+        "sqrt" => div(one(), mul(number(2.0, span), call("sqrt", g(), span), span), span),
+        "cbrt" => div(one(), mul(number(3.0, span), square(call("cbrt", g(), span)), span), span),
+        "ln" => div(one(), g(), span),
+        "log10" => div(one(), mul(g(), number(10f64.ln(), span), span), span),
+        "log2" => div(one(), mul(g(), number(2f64.ln(), span), span), span),
+        "abs" => div(g(), call("abs", g(), span), span),
+        "to_radians" => number(1f64.to_radians(), span),
+        "to_degrees" => number(1f64.to_degrees(), span),
+        // Steps, which are flat everywhere else
+        "floor" | "ceil" | "round" => number(0.0, span),
+        _ => return None,
+    };
+    Some(derivative)
+}
+
+/// Calls to user functions are differentiated through their bodies. `expanding` holds the user functions whose bodies
+/// are being differentiated, so that a function calling itself is refused instead of being expanded forever
+fn derivative_of<'a>(tree: &Rc<Tree<'a>>, variable: &str, functions: &HashMap<&'a str, FunctionBody<'a>>, expanding: &mut Vec<&'a str>) -> Result<Rc<Tree<'a>>, String> {
+    let span = tree.span;
+    if !contains(tree, variable) && !matches!(tree.ast, AST::FunctionCall { .. } | AST::String { .. } | AST::Vector { .. }) {
+        return Ok(number(0.0, span));
+    }
+    let derivative = match &tree.ast {
+        AST::Identifier { .. } => number(1.0, span),
+
+        AST::UnaryOp { rhs, op: Operator::Minus } => neg(derivative_of(rhs, variable, functions, expanding)?, span),
+        AST::UnaryOp { rhs, .. } => derivative_of(rhs, variable, functions, expanding)?,
+
+        AST::BinaryOp { lhs, rhs, op } => {
+            let (f, g) = (Rc::clone(lhs), Rc::clone(rhs));
+            let df = derivative_of(lhs, variable, functions, expanding)?;
+            let dg = derivative_of(rhs, variable, functions, expanding)?;
+            match op {
+                Operator::Plus => add(df, dg, span),
+                Operator::Minus => sub(df, dg, span),
+                // The product rule
+                Operator::Multiply => add(mul(df, g, span), mul(f, dg, span), span),
+                // The quotient rule
+                Operator::Divide => div(sub(mul(df, Rc::clone(&g), span), mul(f, dg, span), span), pow(g, number(2.0, span), span), span),
+                // The power rule
+                Operator::Exponent if !contains(rhs, variable) => {
+                    mul(mul(Rc::clone(&g), pow(f, sub(g, number(1.0, span), span), span), span), df, span)
+                },
+                // `f**g = e**(g * ln(f))`
+                Operator::Exponent => {
+                    let ln_f = call("ln", Rc::clone(&f), span);
+                    let inner = add(mul(dg, ln_f, span), div(mul(Rc::clone(&g), df, span), Rc::clone(&f), span), span);
+                    mul(pow(f, g, span), inner, span)
+                },
+                op => return Err(format!("Cannot differentiate the operator `{op}` in `{tree}`!")),
+            }
+        },
+
+        AST::FunctionCall { name, expressions } => {
+            if let Some((arguments, body)) = functions.get(name) {
+                if expanding.contains(name) {
+                    return Err(format!("Cannot differentiate the function `{name}` since it calls itself, directly or through other functions!"));
+                }
+                let arguments = arguments.iter().copied().zip(expressions.iter().cloned()).collect();
+                expanding.push(name);
+                let derivative = derivative_of(&substitute(body, &arguments), variable, functions, expanding);
+                expanding.pop();
+                return derivative;
+            }
+            let argument = match &expressions[..] {
+                [argument] => argument,
+                _ => return Err(format!("Cannot differentiate the function `{name}` in `{tree}`!")),
+            };
+            if !contains(argument, variable) {
+                return Ok(number(0.0, span));
+            }
+            // The chain rule
+            match builtin_derivative(name, argument, span) {
+                Some(outer) => mul(outer, derivative_of(argument, variable, functions, expanding)?, span),
+                None => return Err(format!("Cannot differentiate the function `{name}` in `{tree}`!")),
+            }
+        },
+
+        // A sum is differentiated term by term, as long as its bounds do not depend on the variable
+        AST::Series { operator: Operator::Plus, index, from, to, body } if *index != variable && !contains(from, variable) && !contains(to, variable) => {
+            let body = derivative_of(body, variable, functions, expanding)?;
+            node(AST::Series { operator: Operator::Plus, index, from: Rc::clone(from), to: Rc::clone(to), body }, span)
+        },
+
+        _ => return Err(format!("Cannot differentiate `{tree}`!")),
+    };
+    Ok(derivative)
+}
+
+/// Differentiate the function `name`, whose body is `tree`, with respect to `variable`, and simplify the result
+pub fn differentiate<'a>(name: &'a str, tree: &Rc<Tree<'a>>, variable: &str, functions: &HashMap<&'a str, FunctionBody<'a>>) -> Result<Rc<Tree<'a>>, String> {
+    Ok(simplify(&derivative_of(tree, variable, functions, &mut vec![name])?))
+}
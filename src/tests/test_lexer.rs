@@ -8,59 +8,59 @@ mod tests {
 
     #[test]
     fn test_numbers() -> Result<(), ()> {
-        let mut lexer = lexer::Lexer::new("1 2.4 08 5. 0o 0b 0x 0b110 0o01234567 0x0123456789ABCDEFabcdef .1")?;
+        let mut lexer = lexer::Lexer::new("1 2.4 08 5. 0o 0b 0x 0b110 0o01234567 0x0123456789ABCDEFabcdef .1");
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Number { number_type: NumberType::Real }, Span::new(0, 1)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Number { number_type: NumberType::Real }, Span::new(2, 5)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Number { number_type: NumberType::Real }, Span::new(6, 8)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Number { number_type: NumberType::Real }, Span::new(9, 11)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Err(Error::TNumberExpected { location: 14 }),
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Err(Error::TNumberExpected { location: 17 }),
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Err(Error::TNumberExpected { location: 20 }),
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Number { number_type: NumberType::Binary }, Span::new(21, 26)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Number { number_type: NumberType::Octal }, Span::new(27, 37)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Number { number_type: NumberType::Hex }, Span::new(38, 62)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Number { number_type: NumberType::Real }, Span::new(63, 65)))
         );
 
@@ -69,24 +69,24 @@ mod tests {
 
     #[test]
     fn test_identifiers() -> Result<(), ()>{
-        let mut lexer = lexer::Lexer::new("a ab abc a~b")?;
+        let mut lexer = lexer::Lexer::new("a ab abc a~b");
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Identifier, Span::new(0, 1)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Identifier, Span::new(2, 4)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Identifier, Span::new(5, 8)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::Identifier, Span::new(9, 12)))
         );
         Ok(())
@@ -94,14 +94,14 @@ mod tests {
 
     #[test]
     fn test_strings() -> Result<(), ()>{
-        let mut lexer = lexer::Lexer::new("\"Hello\n\" \"ABC\\\"DEF\"")?;
+        let mut lexer = lexer::Lexer::new("\"Hello\n\" \"ABC\\\"DEF\"");
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::String, Span::new(0, 8)))
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Ok(Token::new(TokenType::String, Span::new(9, 19)))
         );
         Ok(())
@@ -110,7 +110,7 @@ mod tests {
 
     #[test]
     fn test_operators_and_delimiters() -> Result<(), ()>{
-        let mut lexer = lexer::Lexer::new("* *= ** **= + += - -= = [ ( ) ] ; / /= << <<= >> >>= & &= | |= ^ ^=")?;
+        let mut lexer = lexer::Lexer::new("* *= ** **= + += - -= = [ ( ) ] ; / /= << <<= >> >>= & &= | |= ^ ^=");
         use TokenType::*;
         let list = 
             [
@@ -123,10 +123,10 @@ mod tests {
                 (Subtract, 1),
                 (SubtractEqual, 2),
                 (Equal, 1),
+                (OpeningSquareBracket, 1),
                 (OpeningBracket, 1),
-                (OpeningBracket, 1),
-                (ClosingBracket, 1),
                 (ClosingBracket, 1),
+                (ClosingSquareBracket, 1),
                 (Semicolon, 1),
                 (Divide, 1),
                 (DivideEqual, 2),
@@ -144,7 +144,7 @@ mod tests {
         let mut idx = 0;
         for (item, len) in list {
             assert_eq!(
-                lexer.next(), 
+                lexer.next_token(), 
                 Ok(Token::new(item, Span::new(idx, idx + len)))
             );
             idx += len + 1;
@@ -154,32 +154,80 @@ mod tests {
 
     #[test]
     fn test_invalid() -> Result<(), ()>{
-        let mut lexer = lexer::Lexer::new("<\\!>")?;
+        let mut lexer = lexer::Lexer::new("<\\!>");
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Err(Error::TInvalidCharacter { location: 0 })
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Err(Error::TInvalidCharacter { location: 1 })
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Err(Error::TInvalidCharacter { location: 2 })
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Err(Error::TInvalidCharacter { location: 3 })
         );
 
         assert_eq!(
-            lexer.next(), 
+            lexer.next_token(), 
             Err(Error::TEOF)
         );
         Ok(())
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_non_ascii() -> Result<(), ()> {
+        // Spans are in bytes, so they can be used to slice the source
+        let source = "\"°C\" µ + x̃ 变量 °";
+        let mut lexer = lexer::Lexer::new(source);
+        let expected = [
+            (TokenType::String, "\"°C\""),
+            (TokenType::Identifier, "µ"),
+            (TokenType::Add, "+"),
+            (TokenType::Identifier, "x̃"),
+            (TokenType::Identifier, "变量"),
+        ];
+        for (token_type, text) in expected {
+            let token = lexer.next_token().map_err(|_| ())?;
+            assert_eq!(token.token_type, token_type);
+            assert_eq!(&source[token.span.as_range()], text);
+        }
+
+        // `°` is not a letter, so it cannot start an identifier
+        assert_eq!(
+            lexer.next_token(), 
+            Err(Error::TInvalidCharacter { location: source.len() - '°'.len_utf8() })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_comments() -> Result<(), ()> {
+        let source = "/* a /* nested */ comment */ 1 /// doc\n//// not a doc\nf /* never closed";
+        let mut lexer = lexer::Lexer::new(source);
+        assert_eq!(
+            lexer.next_token(), 
+            Ok(Token::new(TokenType::Number { number_type: NumberType::Real }, Span::new(29, 30)))
+        );
+
+        let start = source.find('f').unwrap();
+        assert_eq!(
+            lexer.next_token(), 
+            Ok(Token::new(TokenType::Identifier, Span::new(start, start + 1)))
+        );
+        assert_eq!(lexer.doc(start), Some("doc"));
+
+        assert_eq!(
+            lexer.next_token(), 
+            Err(Error::TIncompleteComment { span: Span::new(start + 2, start + 4) })
+        );
+        Ok(())
+    }
+}
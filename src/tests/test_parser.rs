@@ -4,10 +4,10 @@
 // If not, this place is gonna need renovation.
 mod tests {
     use std::{ops::Range, rc::Rc};
-    use crate::{ast::{Tree, AST}, errors::Error, lexer::Lexer, parser::Parser, utils::Span};
+    use crate::{ast::{Tree, AST}, bytecode::Bytecode, errors::{CalcError, Error}, format::NumberFormat, lexer::Lexer, parser::Parser, utils::Span, vm::VM};
 
     fn generate_tree(input: &str) -> Vec<Result<Rc<Tree<'_>>, Error>> {
-        let lexer = Lexer::new(input).unwrap();
+        let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         parser.generate_expressions()
     }
@@ -28,6 +28,24 @@ mod tests {
         assert!(matches!(value, Err(..)));
     }
 
+    /// Run a program, giving the results it shows
+    fn run(input: &str) -> Result<Vec<String>, CalcError> {
+        let mut bytecode = Bytecode::new(Parser::new(Lexer::new(input)));
+        let instructions = bytecode.generate_bytecode()
+            .map_err(|errors| CalcError::Compile { source: input.to_owned(), errors })?;
+        let mut vm = VM::new(instructions);
+        let outputs = vm.execute_all()?;
+        Ok(outputs.iter().map(|value| value.format(&NumberFormat::default())).collect())
+    }
+
+    /// Run a program that fails, giving its error
+    fn run_error(input: &str) -> String {
+        match run(input) {
+            Ok(results) => panic!("`{input}` should fail, but gave {results:?}"),
+            Err(error) => error.to_string(),
+        }
+    }
+
     #[test] 
     fn number() {
         let tree = generate_tree("6;0.5;.9;");
@@ -106,6 +124,15 @@ mod tests {
         generate_and_test("let a;a=5;a+=7;a-=7;a*=7;a/=7;a**=7;a&=7;a|=7;a^=7;a<<=7;a>>=7;", &tests)
     }
 
+    #[test]
+    fn failed_operator_keeps_value() {
+        let mut bytecode = Bytecode::new(Parser::new(Lexer::new("let a = 5; a /= 0;")));
+        let mut vm = VM::new(bytecode.generate_bytecode().unwrap());
+        assert!(vm.execute_all().is_err());
+        let (symbols, _) = vm.get_symbols();
+        assert_eq!(symbols["a"].format(&NumberFormat::default()), "5");
+    }
+
     
     #[test]
     fn functions() {
@@ -125,4 +152,609 @@ mod tests {
         ];
         generate_and_test("let a;delete a;", &tests)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn units() {
+        let tests = [
+            "((5 * km) / (20 * min))",
+            "((60 * mph) to m/s)",
+            "(3 * m**2)",
+            "((9.81 * m) / (s ** 2))",
+        ];
+        generate_and_test("5 km / 20 min;60 mph to m/s;3 m**2;9.81 m/s**2;", &tests);
+        let tree = generate_tree("5 to parsec;");
+        expect_error(&tree[0]);
+
+        let results = run("60 mph to m/s:9.81 m/s**2 * 70 kg to N:2 km + 300 m:1 h to s:").unwrap();
+        assert_eq!(results, ["26.8224 m/s", "686.7 N", "2.3 km", "3600 s"]);
+        assert!(run_error("3 m + 2 s:").contains("mismatched dimensions: lhs `m` (length) and rhs `s` (time)"));
+        assert!(run_error("5 kg to m:").contains("Cannot convert `kg` (mass) to `m` (length)"));
+    }
+
+    #[test]
+    fn unit_powers() {
+        assert_eq!(run("1 N**100:").unwrap(), ["1 N**100"]);
+        assert_eq!(run("(1 m**100) * (1 m**100):").unwrap(), ["1 m**200"]);
+        assert!(run_error("(1 m**600) * (1 m**600):").contains("too large"));
+        assert!(run_error("(2 m) ** (10**30):").contains("too large"));
+        assert!(run_error("1 m**2000:").contains("too large"));
+    }
+
+    #[test]
+    fn vectors() {
+        let tests = [
+            "[1, 2, 3]",
+            "[[1, 2], [3, (4 + 5)]]",
+            "[]",
+            "det([[1, 0], [0, 1]])",
+        ];
+        generate_and_test("[1, 2, 3];[[1, 2], [3, 4 + 5]];[];det([[1, 0], [0, 1]]);", &tests);
+        let tree = generate_tree("[1, 2;");
+        expect_error(&tree[0]);
+
+        let source = "[1, 2, 3] + [4, 5, 6]:[[1, 2], [3, 4]] * [[5, 6], [7, 8]]:[[1, 2], [3, 4]] * [1, 1]:2 * [1, 2]:";
+        assert_eq!(run(source).unwrap(), ["[5, 7, 9]", "[[19, 22], [43, 50]]", "[3, 7]", "[2, 4]"]);
+        let source = "det([[1, 2], [3, 4]]):dot([1, 2, 3], [4, 5, 6]):cross([1, 0, 0], [0, 1, 0]):transpose([[1, 2, 3], [4, 5, 6]]):";
+        assert_eq!(run(source).unwrap(), ["-2", "32", "[0, 0, 1]", "[[1, 4], [2, 5], [3, 6]]"]);
+        assert!(run_error("[1, 2] + [1, 2, 3]:").contains("vector of length 2 and vector of length 3"));
+        assert!(run_error("[[1, 2], [3, 4]] * [[1, 2, 3]]:").contains("mismatched shapes 2x2 and 1x3"));
+        assert!(run_error("inv([[1, 2], [2, 4]]):").contains("singular"));
+    }
+
+    #[test]
+    fn matrix_powers() {
+        assert_eq!(run("[[1, 1], [1, 0]] ** 10:").unwrap(), ["[[89, 55], [55, 34]]"]);
+        assert_eq!(run("[[2, 0], [0, 1]] ** -3:").unwrap(), ["[[0.125, 0], [0, 1]]"]);
+        assert_eq!(run("[[1, 0], [0, 1]] ** (10**15):").unwrap(), ["[[1, 0], [0, 1]]"]);
+        assert!(run_error("identity(10**12):").contains("up to 4096"));
+    }
+
+    #[test]
+    fn equations() {
+        let tests = [
+            "(let f x = ((x ** 2) - 2))",
+            "root(f, 0, 2)",
+            "(let <solve#1> x = ((x ** 3) - 8))",
+            "solve(<solve#1>, 2)",
+        ];
+        generate_and_test("let f x = x**2 - 2;root(f, 0, 2);solve(\"x**3 = 8\", x, 2);", &tests);
+        let tree = generate_tree("solve(\"x**3\", x);");
+        expect_error(&tree[0]);
+
+        let close = |source: &str, expected: &[f64]| {
+            let results = run(source).unwrap();
+            assert_eq!(results.len(), expected.len(), "`{source}` gave {results:?}");
+            for (result, expected) in results.iter().zip(expected) {
+                let result = result.parse::<f64>().unwrap();
+                assert!((result - expected).abs() < 1e-9, "`{source}` gave {result}, but expected {expected}");
+            }
+        };
+        close("solve(\"x**2 = 2\", x):solve(\"x**2 = 2\", x, -1):", &[std::f64::consts::SQRT_2, -std::f64::consts::SQRT_2]);
+        close("let f x = x**2 - 2;solve(f, 1):root(f, 0, 2):root(cos, 1, 2):", &[std::f64::consts::SQRT_2, std::f64::consts::SQRT_2, std::f64::consts::FRAC_PI_2]);
+        // Two equations in one statement, and one inside a function, each get their own function
+        close("solve(\"x**2 = 4\", x) + solve(\"x**3 = 27\", x):", &[5.0]);
+        close("let g a = solve(\"x**2 = a\", x);solve(\"x = 5\", x):g(9):", &[5.0, 3.0]);
+        assert_eq!(run("solve([[2, 1], [1, 3]], [3, 5]):").unwrap(), ["[0.8, 1.4]"]);
+
+        assert!(run_error("solve(\"x**2 = -1\", x):").contains("did not find a root of the equation"));
+        assert!(run_error("let f x = x**2 + 1;root(f, -1, 1):").contains("expected `f` to change sign between -1 and 1"));
+    }
+
+    #[test]
+    fn derivatives() {
+        let tests = [
+            "(let f x = ((x ** 2) + sin(x)))",
+            "(let f' x = ((2 * x) + cos(x)))",
+            "f'",
+            "f'(1)",
+        ];
+        generate_and_test("let f x = x**2 + sin(x);diff(f);f'(1);", &tests);
+        assert!(run_error("diff(sin);").contains("can only differentiate user functions, but `sin` is a built in function"));
+        assert!(run_error("let f = 2;diff(f);").contains("expected the name of a function"));
+        assert!(run_error("let f x = x & 2;diff(f);").contains("Cannot differentiate the operator `&`"));
+        // The derivative agrees with the numeric one, including through calls to other user functions
+        let results = run("let g x = x**3;let f x = g(x) + sin(x) * x;diff(f);f'(1.3):derivative(f, 1.3):").unwrap();
+        let (symbolic, numeric) = (results[0].parse::<f64>().unwrap(), results[1].parse::<f64>().unwrap());
+        assert!((symbolic - numeric).abs() < 1e-8, "`f'(1.3)` gave {symbolic}, but `derivative(f, 1.3)` gave {numeric}");
+        // Taking the derivative again gives the same function
+        assert_eq!(run("let f x = x**2;diff(f);diff(f);f'(3):").unwrap(), ["6"]);
+        // Recursive functions are refused instead of being expanded forever
+        assert!(run_error("let f x = x * f(x - 1);diff(f);").contains("Cannot differentiate the function `f` since it calls itself"));
+        assert!(run_error("let h x = x;let g x = h(x);let h x = g(x);diff(h);").contains("Cannot differentiate the function `h` since it calls itself"));
+    }
+
+    #[test]
+    fn simplification() {
+        let tests = [
+            "*\"(5 * x)\"*",
+            "*\"(a + 4)\"*",
+        ];
+        generate_and_test("simplify(2*x + 3*x):simplify(sqrt(16) + a/2 + a/2):", &tests);
+        assert_eq!(run("let a = 3;simplify(2*a + 3*a):").unwrap(), ["(5 * a)"]);
+        assert!(run_error("simplify(1, 2):").contains("The function `simplify` expected a single expression, but found `,`"));
+    }
+
+    #[test]
+    fn series() {
+        let tests = [
+            "sum(i, 1, 10, (i ** 2))",
+            "prod(k, 1, 5, k)",
+        ];
+        generate_and_test("sum(i, 1, 10, i**2);Π(k, 1, 5, k);", &tests);
+        // The index only exists inside the body
+        let tree = generate_tree("sum(i, 1, 3, i);i;");
+        expect_error(&tree[1]);
+
+        let source = "sum(i, 1, 10, i**2):Π(k, 1, 5, k):let n = 4;sum(i, 1, n, i):sum(i, 1, 3, sum(j, 1, i, j)):sum(i, 1, 3, [i, 1]):";
+        assert_eq!(run(source).unwrap(), ["385", "120", "10", "10", "[6, 3]"]);
+        // Empty ranges give the identity of the operation
+        assert_eq!(run("sum(i, 5, 1, i):prod(i, 5, 1, i):").unwrap(), ["0", "1"]);
+        assert!(run_error("sum(i, 1, 10**9, i):").contains("at most 10000000 terms"));
+        assert!(run_error("sum(i, 1.5, 3, i):").contains("expected an integer"));
+    }
+
+    #[test]
+    fn durations() {
+        let tests = [
+            "(3 * h)",
+            "(duration((3 * h)) + duration((20 * min)))",
+        ];
+        generate_and_test("3h;3h 20min;", &tests);
+        let tree = generate_tree("3h 20;");
+        expect_error(&tree[0]);
+
+        let source = "3h 20min:3h 20min to min:3h 20min + 40min:duration(90 min):3h 20min * 2:";
+        assert_eq!(run(source).unwrap(), ["3h 20min", "200 min", "4h", "1h 30min", "6h 40min"]);
+        let source = "date(\"2026-10-17\") + 90 days:date(\"2024-01-31\") + 1 day:date(\"2024-03-01\") - date(\"2024-01-01\"):";
+        assert_eq!(run(source).unwrap(), ["2027-01-15", "2024-02-01", "60d"]);
+        let source = "days_between(date(\"2024-01-01\"), date(\"2024-03-01\")):weekday(date(\"2026-10-18\")):date(\"2024-01-01T12:30:00\") + 3h 20min:";
+        assert_eq!(run(source).unwrap(), ["60", "Sunday", "2024-01-01T15:50:00Z"]);
+        assert!(run_error("date(\"2024-13-01\"):").contains("does not exist"));
+        assert!(run_error("date(\"2024-01-01\") + 3 m:").contains("lhs `{Date}` and rhs `{Quantity}`"));
+    }
+
+    #[test]
+    fn optional_arguments() {
+        let tests = [
+            "pmt(0.05, 10, 1000)",
+            "pmt(0.05, 10, 1000, 0, 1)",
+            "npv(0.1, (-100), 60, 60)",
+        ];
+        generate_and_test("pmt(0.05, 10, 1000);pmt(0.05, 10, 1000, 0, 1);npv(0.1, -100, 60, 60);", &tests);
+        let tree = generate_tree("pmt(0.05, 10);");
+        expect_error(&tree[0]);
+        let tree = generate_tree("pmt(0.05, 10, 1000, 0, 1, 2);");
+        expect_error(&tree[0]);
+
+        // The same results as spreadsheets give
+        let close = |source: &str, expected: f64| {
+            let result = run(source).unwrap()[0].parse::<f64>().unwrap();
+            assert!((result - expected).abs() <= 1e-9 * expected.abs().max(1.0), "`{source}` gave {result}, but expected {expected}");
+        };
+        close("pmt(0.05, 10, 1000):", -129.5045749654566);
+        close("pmt(0.05, 10, 1000, 0, 1):", -123.337690443292);
+        close("pmt(0.05, 10, 1000, 500):", -169.25686244818502);
+        close("pmt(0, 10, 1000):", -100.0);
+        close("npv(0.1, -100, 60, 60):", 3.7565740045078755);
+        close("pv(0.05, 10, -100):", 772.1734929184817);
+        close("fv(0.05, 10, -100):", 1257.789253554884);
+        close("nper(0.05, -100, 1000):", 14.206699082890461);
+        close("irr([-100, 60, 60]):", 0.13066238629180746);
+        close("rate(10, -129.5045749654566, 1000):", 0.05);
+        // Paying back exactly what was borrowed is a rate of 0
+        close("rate(10, -100, 1000):", 0.0);
+        assert!(run_error("pmt(0.05, 10, 1000, 0, 2):").contains("type of payment"));
+    }
+
+    #[test]
+    fn loaded_units() {
+        let path = std::env::temp_dir().join("vm-calc-test-units.toml");
+        std::fs::write(&path, "[units]\nfurlongs = \"201.168 m\"\n\n[currencies]\nbase = \"TST\"\nrates = { XTS = 2.5 }\n").unwrap();
+        assert_eq!(crate::units::load_table(path.to_str().unwrap()), Ok(3));
+        let tests = [
+            "(2 * furlongs)",
+            "(5 * XTS)",
+        ];
+        generate_and_test("2 furlongs;5 XTS;", &tests);
+        // Loading the same table again would redefine its units
+        assert!(crate::units::load_table(path.to_str().unwrap()).is_err());
+
+        // Units can use the ones defined after them
+        let path = std::env::temp_dir().join("vm-calc-test-units-order.toml");
+        std::fs::write(&path, "[units]\naaleague = \"2 zzleague\"\nzzleague = \"3 m\"\n").unwrap();
+        assert_eq!(crate::units::load_table(path.to_str().unwrap()), Ok(2));
+        assert_eq!(run("1 aaleague to m:").unwrap(), ["6 m"]);
+
+        // Nothing from a table with an error is loaded
+        let path = std::env::temp_dir().join("vm-calc-test-units-invalid.toml");
+        std::fs::write(&path, "[units]\ngoodspan = \"3 m\"\nbadspan = \"2 nothing\"\n").unwrap();
+        assert!(crate::units::load_table(path.to_str().unwrap()).is_err());
+        assert!(!crate::units::is_unit("goodspan"));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let source = "let a = 1;\nlet b = (a +\n  2;";
+        let tree = generate_tree(source);
+        let error = tree.iter().find_map(|result| result.clone().err()).unwrap();
+        let expected = [
+            "[PARSE ERROR E0203] 3:4: Expected closing bracket `)`! Found `;`",
+            "  |",
+            "2 | let b = (a +",
+            "  |         - this bracket is never closed",
+            "3 |   2;",
+            "  |    ^",
+        ];
+        assert_eq!(error.render(source), expected.join("\n"));
+    }
+
+    #[test]
+    fn error_recovery() {
+        // Each statement with an error is skipped up to its terminator, and the ones after it are still parsed
+        let tree = generate_tree("1 + (2;3 $ 4:5 * 6;");
+        assert_eq!(tree.len(), 3);
+        expect_error(&tree[0]);
+        expect_error(&tree[1]);
+        assert_eq!(format!("{}", tree[2].clone().unwrap()), "(5 * 6)");
+        // An error at the terminator itself does not hide the statement after it
+        let tree = generate_tree("1 +; 2 +; 3 +; 4 +; 5 +;");
+        assert_eq!(tree.len(), 5);
+        assert!(tree.iter().all(|result| result.is_err()));
+        // A variable whose value has an error is still declared for the statements after it
+        let tree = generate_tree("let x = (1;x + 1;");
+        expect_error(&tree[0]);
+        assert_eq!(format!("{}", tree[1].clone().unwrap()), "(x + 1)");
+    }
+
+    #[test]
+    fn span_table() {
+        let source = "let a = 2;3 + a / a:";
+        let mut bytecode = crate::bytecode::Bytecode::new(Parser::new(Lexer::new(source)));
+        let instructions = bytecode.generate_bytecode().unwrap();
+        let spans = bytecode.spans();
+        assert_eq!(instructions.len(), spans.len());
+        // The addition is the instruction before the output, and spans the whole expression
+        let addition = spans[instructions.len() - 2].unwrap();
+        assert_eq!(&source[addition.as_range()], "3 + a / a");
+    }
+
+    #[test]
+    fn structured_errors() {
+        use crate::{errors::CalcError, processchain::{Options, ProcessChain, RunOutput}};
+        match ProcessChain::run_from_text("1 + (2;3 $ 4:", &Options::default(), &mut RunOutput::default()) {
+            Err(CalcError::Compile { errors, .. }) => assert_eq!(errors.len(), 2),
+            result => panic!("Expected two compile errors but found {result:?}"),
+        }
+        assert!(matches!(ProcessChain::run_from_text("1 / 0:", &Options::default(), &mut RunOutput::default()), Err(CalcError::Runtime { .. })));
+        assert!(matches!(ProcessChain::run_from_file("does-not-exist.calc", &Options::default(), &mut RunOutput::default()), Err(CalcError::Io { .. })));
+
+        // What a run gives is returned rather than printed, including what came before a runtime error
+        let mut output = RunOutput::default();
+        let result = ProcessChain::run_from_text("let unused = 1;print(1, 2);3:1 / 0:4:", &Options::default(), &mut output);
+        assert!(matches!(result, Err(CalcError::Runtime { .. })));
+        assert_eq!(output.warnings.len(), 1);
+        assert_eq!(output.printed, ["1 2"]);
+        assert_eq!(output.results, Some(vec![crate::instruction::Value::Number(3.0)]));
+    }
+
+    #[test]
+    fn suggestions() {
+        use crate::diagnostics::edit_distance;
+        assert_eq!(edit_distance("sine", "sin"), 1);
+        assert_eq!(edit_distance("alhpa", "alpha"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        let tree = generate_tree("sine(1);let value = 3;valeu;y;");
+        let help = |index: usize| tree[index].clone().unwrap_err().diagnostic().help;
+        assert_eq!(help(0), ["did you mean `sin`?"]);
+        assert_eq!(help(2), ["did you mean `value`?"]);
+        // Names this short could be a typo of almost anything
+        assert!(help(3).is_empty());
+    }
+
+    #[test]
+    fn error_codes() {
+        let source = "1 + 0b12;\nsine(1);";
+        let tree = generate_tree(source);
+        let error = tree.iter().find_map(|result| result.clone().err()).unwrap();
+        assert_eq!(error.code(), "E0103");
+        let json: serde_json::Value = serde_json::from_str(&error.diagnostic().to_json(source)).unwrap();
+        assert_eq!(json["code"], "E0103");
+        assert_eq!(json["severity"], "error");
+        assert_eq!((json["line"].as_u64(), json["column"].as_u64()), (Some(1), Some(5)));
+        assert_eq!(json["span"]["start"], 4);
+
+        // The place of an error is in its span, not in its message
+        let tree = generate_tree("diff(sin 2);1 +");
+        let diagnostic = |index: usize| tree[index].clone().unwrap_err().diagnostic();
+        assert_eq!(diagnostic(0).message, "Expected a token of type: `,` but found token of type: `Real Number`");
+        assert_eq!(diagnostic(0).span.map(|span| span.as_range()), Some(9..10));
+        assert_eq!(diagnostic(1).message, "Expected an expression after the `+` operator, but found nothing.");
+        assert_eq!(diagnostic(1).span.map(|span| span.start), Some(15));
+    }
+
+    #[test]
+    fn lints() {
+        use crate::{errors::CalcError, lint::Lint, processchain::{Options, ProcessChain, RunOutput}};
+        let source = "let a = 1;let _b = 2;let f x = x;let f = 3;f + 1;let c = 2.5 | a;c:";
+        let mut bytecode = crate::bytecode::Bytecode::new(Parser::new(Lexer::new(source)));
+        bytecode.generate_bytecode().unwrap();
+        let codes = bytecode.warnings().iter().map(|warning| warning.code.unwrap()).collect::<Vec<_>>();
+        assert_eq!(codes, ["W0002", "W0003", "W0004"]);
+        // `diff` and `help` are replaced by what they give, but declare a function and show its documentation
+        let mut bytecode = crate::bytecode::Bytecode::new(Parser::new(Lexer::new("let f x = x**2;diff(f);help(f);f'(1):f;")));
+        bytecode.generate_bytecode().unwrap();
+        let spans = bytecode.warnings().iter().map(|warning| warning.span.unwrap().start).collect::<Vec<_>>();
+        assert_eq!(spans, [37]);
+        // A compound assignment reads its variable
+        let mut bytecode = crate::bytecode::Bytecode::new(Parser::new(Lexer::new("let a = 5;a += 1;let b = 1;b = 2;")));
+        bytecode.generate_bytecode().unwrap();
+        let messages = bytecode.warnings().iter().map(|warning| warning.message.clone()).collect::<Vec<_>>();
+        assert_eq!(messages, ["The variable `b` is never used"]);
+
+        let options = Options { deny_warnings: true, ..Options::default() };
+        assert!(matches!(ProcessChain::run_from_text("let unused = 1;", &options, &mut RunOutput::default()), Err(CalcError::Warnings { .. })));
+        let options = Options { allowed_lints: vec![Lint::UnusedVariable], ..options };
+        assert!(ProcessChain::run_from_text("let unused = 1;", &options, &mut RunOutput::default()).is_ok());
+    }
+
+    #[test]
+    fn documentation() {
+        let source = "/// Doubles a number\n/// twice over\nlet f x = x * 2;\nlet g x = x;\nhelp(f):\nhelp(g):";
+        let tree = generate_tree(source);
+        let help = tree.iter().filter_map(|result| match &result.as_ref().unwrap().ast {
+            AST::Output { value } => match &value.ast {
+                AST::String { contents } => Some(contents.clone()),
+                _ => None,
+            },
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(help[0], "f(x)\nDoubles a number\ntwice over");
+        assert!(help[1].starts_with("g(x)\nThis function has no documentation"));
+    }
+
+    #[test]
+    fn ast_dumps() {
+        let tree = generate_tree("1 + sin(2):").remove(0).unwrap();
+        assert_eq!(tree.dump(), [
+            "Output [0:10]",
+            "  BinaryOp + [0:10]",
+            "    Number 1 [0:1]",
+            "    FunctionCall sin [4:10]",
+            "      Number 2 [8:9]",
+        ].join("\n"));
+
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(json["ast"]["type"], "Output");
+        assert_eq!(json["ast"]["value"]["ast"]["rhs"]["ast"]["name"], "sin");
+        assert_eq!(json["ast"]["value"]["ast"]["rhs"]["span"], serde_json::json!({ "start": 4, "end": 10 }));
+    }
+
+    #[test]
+    fn arrangements() {
+        assert_eq!(run("nCr(5, 2):nPr(5, 2):nCr(60, 30):").unwrap(), ["10", "20", "118264581564861420"]);
+        assert_eq!(run("nCr(10**15, 10**15 - 1):").unwrap(), ["1000000000000000"]);
+        assert_eq!(run("nCr(10**15, 5 * 10**14):nPr(10**15, 10**15):").unwrap(), ["inf", "inf"]);
+    }
+
+    #[test]
+    fn number_theory() {
+        assert_eq!(run("gcd(12, -18):lcm(4, 6):lcm(0, 5):").unwrap(), ["6", "12", "0"]);
+        assert_eq!(run("is_prime(97):is_prime(1):is_prime(561):next_prime(13):next_prime(-5):").unwrap(), ["1", "0", "0", "17", "2"]);
+        assert_eq!(run("factor(360):factor(600851475143):").unwrap(), ["[2, 2, 2, 3, 3, 5]", "[71, 839, 1471, 6857]"]);
+        assert_eq!(run("mod_pow(4, 13, 497):mod_pow(3, -1, 7):mod_inv(3, 11):").unwrap(), ["445", "5", "4"]);
+        assert_eq!(run("totient(36):totient(1):fib(0):fib(10):").unwrap(), ["12", "1", "0", "55"]);
+
+        // Only integers that can be represented exactly are accepted
+        assert!(run_error("gcd(1.5, 2):").contains("expected an integer but found 1.5"));
+        assert!(run_error("gcd(2**70, 2):").contains("too large to be represented exactly"));
+        assert!(run_error("factor(-4):").contains("not negative"));
+        assert!(run_error("fib(-1):").contains("not negative"));
+        assert!(run_error("factor(0):").contains("cannot factor 0"));
+        assert!(run_error("mod_pow(2, 3, 0):").contains("positive modulus"));
+        assert!(run_error("mod_inv(2, 4):").contains("has no inverse modulo 4"));
+        assert!(run_error("mod_pow(2, -1, 4):").contains("has no inverse modulo 4"));
+    }
+
+    #[test]
+    fn unit_precedence() {
+        assert_eq!(run("5 km / 20 min:").unwrap(), ["15 km/h"]);
+        assert_eq!(run("5 km / 20 min to m/s:3 m / 1 min:").unwrap(), ["4.166666666666667 m/s", "0.05 m/s"]);
+        // Variables take precedence over units
+        let tree = generate_tree("let m = 3;5 m;");
+        expect_error(&tree[1]);
+        // Names are not taken for units, in functions or not, unless they continue the units of a quantity
+        let tree = generate_tree("let f x = x * s;t + 1;");
+        expect_error(&tree[0]);
+        assert_eq!(tree[1].clone().unwrap_err().diagnostic().message, "The variable `t` does not exist! Units are written after a number, ie. `1 t`");
+        assert_eq!(run("let t = 2;t + 1:1 m/s:").unwrap(), ["3", "1 m/s"]);
+        assert_eq!(run("let f t = 9.81 m/s**2 * t;f(2 s):").unwrap(), ["19.62 m/s"]);
+    }
+
+    #[test]
+    fn optimizer() {
+        use crate::{instruction::{Instruction, Value}, optimize::OptLevel};
+        let compile = |source, opt_level| {
+            let mut bytecode = Bytecode::new(Parser::new(Lexer::new(source)));
+            bytecode.set_opt_level(opt_level);
+            bytecode.generate_bytecode().unwrap()
+        };
+        assert_eq!(compile("2 + 3 * 4:", OptLevel::O1), [Instruction::Load { value: Value::Number(14.0) }, Instruction::Output]);
+        assert_eq!(compile("2 + 3 * 4:", OptLevel::O0).len(), 6);
+        // Unused values are dropped
+        assert!(compile("2 + 3;", OptLevel::O1).is_empty());
+
+        // Operations that fail are left to fail at runtime, with the same message
+        assert!(compile("1 / 0:", OptLevel::O1).contains(&Instruction::Binary { operator: crate::ast::Operator::Divide }));
+        let mut vm = VM::new(compile("1 / 0:", OptLevel::O0));
+        assert_eq!(vm.execute_all().unwrap_err().to_string(), run_error("1 / 0:"));
+        assert!(run_error("1 / 0:").contains("Cannot divide a number by zero!"));
+
+        // The body of a series is not on the stack for the instruction after it
+        assert_eq!(run("sum(i, 1, 3, 5) + 2:").unwrap(), ["17"]);
+
+        // In the repl, a declaration is followed by an output, and the end of its body moves when the body is folded
+        let mut bytecode = Bytecode::new(Parser::new(Lexer::new("let f x = x + 2 * 3;")));
+        let (instructions, functions) = bytecode.generate_fn_bytecode(vec![]).unwrap();
+        let Instruction::UData { number: end } = instructions[2] else {
+            panic!("Expected the end of the function, but found {:?}", instructions[2]);
+        };
+        assert_eq!(instructions.len(), end + 4);
+        assert_eq!(instructions[end + 3], Instruction::Output);
+        assert_eq!(functions, instructions);
+    }
+
+    #[test]
+    fn number_format() {
+        let format = |flags: &str, number: f64| {
+            let (options, _) = crate::processchain::parse_options(flags.split(' ').map(str::to_owned).collect()).unwrap();
+            options.format.format(number)
+        };
+        assert_eq!(format("--notation sci", 12345.0), "1.2345e4");
+        assert_eq!(format("--notation eng", 12345.0), "12.345e3");
+        assert_eq!(format("--notation eng --precision 1", 0.00123), "1.2e-3");
+        // Rounding that carries into another digit moves the exponent
+        assert_eq!(format("--notation sci --precision 1", 9.96), "1.0e1");
+        assert_eq!(format("--notation sci --precision 2", -9.999), "-1.00e1");
+        assert_eq!(format("--notation eng --precision 1", 999.96), "1.0e3");
+        assert_eq!(format("--notation eng --precision 1", 0.99996), "1.0e0");
+        assert_eq!(format("--notation sci --precision 1", 9.94), "9.9e0");
+        assert_eq!(format("--base 16", 1234.5), "0x4d2.8");
+        assert_eq!(format("--base 2", -5.5), "-0b101.1");
+        assert_eq!(format("--base 8", 64.0), "0o100");
+        // Numbers too large for the base are shown in decimal
+        assert_eq!(format("--base 16", 1e30), "1000000000000000000000000000000");
+        assert_eq!(format("--precision 2", 1.23456), "1.23");
+        assert_eq!(format("--digits 3", 1234.5), "1230");
+        assert_eq!(format("--separators", -1234567.5), "-1,234,567.5");
+        assert_eq!(format("--separators --base 16", 4294967295.0), "0xffff_ffff");
+        assert!(crate::processchain::parse_options(vec!["--base".to_owned(), "3".to_owned()]).is_err());
+        assert!(crate::processchain::parse_options(vec!["--notation".to_owned(), "roman".to_owned()]).is_err());
+
+        assert_eq!(run("hex(255):bin(5):oct(8):").unwrap(), ["0xff", "0b101", "0o10"]);
+        assert!(run_error("hex(10**30):").contains("below 2**64"));
+        assert!(run_error("bin(-(10**30)):").contains("below 2**64"));
+    }
+
+    #[test]
+    fn seeded_random() {
+        use crate::random::Random;
+        let source = "rand():rand_int(1, 6):normal(0, 1):choice([1, 2, 3]):rand():";
+        let run_seeded = |seed| {
+            let mut bytecode = Bytecode::new(Parser::new(Lexer::new(source)));
+            let mut vm = VM::new(bytecode.generate_bytecode().unwrap());
+            vm.set_random(Random::new(seed));
+            vm.execute_all().unwrap().iter().map(|value| value.format(&NumberFormat::default())).collect::<Vec<_>>()
+        };
+        assert_eq!(run_seeded(42), run_seeded(42));
+        assert_ne!(run_seeded(42), run_seeded(43));
+
+        // The generator itself, which must not change between versions so that seeded runs can be repeated
+        let mut a = Random::new(7);
+        let mut b = Random::new(7);
+        let sequence = (0..100).map(|_| a.next_u64()).collect::<Vec<_>>();
+        assert_eq!(sequence, (0..100).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_eq!(Random::new(0).next_u64(), 0x99EC5F36CB75F2B4);
+        let mut random = Random::new(1);
+        assert!((0..1000).map(|_| random.range(-2, 2)).all(|value| (-2..=2).contains(&value)));
+    }
+
+    #[test]
+    fn statistics() {
+        let data = "[2, 4, 4, 4, 5, 5, 7, 9]";
+        let source = format!("mean({data}):pvariance({data}):pstddev({data}):variance({data}):stddev({data}):");
+        assert_eq!(run(&source).unwrap(), ["5", "4", "2", "4.571428571428571", "2.138089935299395"]);
+        // Percentiles interpolate between the closest ranks
+        let source = "percentile([4, 1, 3, 2], 25):median([4, 1, 3, 2]):percentile([1, 2, 3, 4], 0):percentile([1, 2, 3, 4], 100):";
+        assert_eq!(run(source).unwrap(), ["1.75", "2.5", "1", "4"]);
+        assert!(run_error("percentile([1, 2], 101):").contains("between 0 and 100"));
+        // Ties are broken by the smallest value
+        assert_eq!(run("mode([3, 1, 3, 1, 2]):mode([5]):").unwrap(), ["1", "5"]);
+
+        assert_eq!(run("correlation([1, 2, 3], [2, 4, 6]):correlation([1, 2, 3], [3, 2, 1]):").unwrap(), ["1", "-1"]);
+        assert_eq!(run("linear_regression([1, 2, 3], [3, 5, 7]):").unwrap(), ["[2, 1]"]);
+        assert!(run_error("correlation([1, 1, 1], [1, 2, 3]):").contains("all the values of a list are the same"));
+        assert!(run_error("correlation([1, 2, 3], [5, 5, 5]):").contains("all the values of a list are the same"));
+        assert!(run_error("linear_regression([2, 2, 2], [1, 2, 3]):").contains("all the `x` values are the same"));
+        assert!(run_error("variance([1]):").contains("at least 2 number(s)"));
+    }
+
+    #[test]
+    fn calculus() {
+        let close = |source: &str, expected: f64, tolerance: f64| {
+            let result = run(source).unwrap()[0].parse::<f64>().unwrap();
+            assert!((result - expected).abs() <= tolerance, "`{source}` gave {result}, but expected {expected}");
+        };
+        close("let f x = x**2;integrate(f, 0, 3):", 9.0, 1e-9);
+        close("let f x = x**2;integrate(f, 3, 0):", -9.0, 1e-9);
+        close("let f x = sin(x);integrate(f, 0, 3.141592653589793):", 2.0, 1e-9);
+        close("let f x = 1/x;integrate(f, 1, 2):", std::f64::consts::LN_2, 1e-9);
+        close("let f x = x**2;integrate(f, 1, 1):", 0.0, 0.0);
+
+        close("let f x = x**3;derivative(f, 2):", 12.0, 1e-8);
+        close("let f x = sin(x);derivative(f, 0):", 1.0, 1e-8);
+        close("let f x = ln(x);derivative(f, 2):", 0.5, 1e-8);
+        close("let f x = x**2;derivative(f, 1000):", 2000.0, 1e-6);
+
+        assert!(run_error("let f x = x;integrate(f, 0, 10**400):").contains("finite bounds"));
+        assert!(run_error("let f x = 1/x**2;integrate(f, 0, 1):").contains("divide a number by zero"));
+        // Integrals that do not converge are errors rather than estimates
+        close("let f x = sqrt(x);integrate(f, 0, 1):", 2.0 / 3.0, 1e-9);
+        close("let f x = floor(x);integrate(f, 0, 7):", 21.0, 1e-9);
+        assert!(run_error("let f x = floor(x);integrate(f, 0, 100):").contains("does not converge"));
+        assert!(run_error("let f x = rand();integrate(f, 0, 1):").contains("without converging"));
+    }
+
+
+    #[test]
+    fn common_error_codes() {
+        let code = |input: &str| run(input).unwrap_err().code();
+        assert_eq!(code("1 / 0:"), "E0404");
+        assert_eq!(code("let a = 1;\nlet b = 2 / (a - 1);"), "E0404");
+        assert_eq!(code("[1, 2] / 0:"), "E0404");
+        assert_eq!(code("\"a\" + 1:"), "E0407");
+        assert_eq!(code("3 m + 2 s:"), "E0408");
+        assert_eq!(code("3 m to s:"), "E0408");
+        assert_eq!(code("[1, 2] + [1, 2, 3]:"), "E0409");
+        assert_eq!(code("[[1, 2, 3], [4, 5, 6]] ** 2:"), "E0409");
+        // Assigning with an operator checks the values like the operator does
+        assert_eq!(code("let x = 1;\nx /= 0;"), "E0404");
+        assert_eq!(code("let d = 3 m;\nd += 2 s;"), "E0408");
+        assert_eq!(code("let s = \"a\";\ns += 1;"), "E0407");
+        // The arguments of built in functions
+        assert_eq!(code("sqrt(\"4\"):"), "E0407");
+        assert_eq!(code("dot(1, [1]):"), "E0407");
+        assert_eq!(code("dot([1], [1, 2]):"), "E0409");
+        assert_eq!(code("gcd(1.5, 2):"), "E0410");
+        assert_eq!(code("sum(k, 1, 2.5, k):"), "E0410");
+        assert_eq!(code("fib(-1):"), "E0411");
+        assert_eq!(code("mean([]):"), "E0411");
+        assert_eq!(code("integrate(sin, 0, 10**400):"), "E0411");
+        assert_eq!(code("let f x = x**2 + 1;root(f, -1, 1):"), "E0411");
+        // Errors in the functions passed to built in functions keep their own codes
+        assert_eq!(code("let f x = 1 / 0;derivative(f, 1):"), "E0404");
+        assert_eq!(code("let f x = 1;solve(f, 0):"), "E0403");
+
+        assert_eq!(code("let a = 1;\nb:"), "E0206");
+        assert_eq!(code("sine(1):"), "E0207");
+        assert_eq!(code("sin(1, 2):"), "E0208");
+        assert_eq!(code("1 + 2"), "E0209");
+        assert_eq!(code("5 to parsecish:"), "E0210");
+    }
+
+
+    #[test]
+    fn library() {
+        use crate::instruction::Value;
+        let source = "1 + 2: 3 * 4; 5: 1 / 0: 6:";
+        let mut bytecode = Bytecode::new(Parser::new(Lexer::new(source)));
+        let mut vm = VM::new(bytecode.generate_bytecode().unwrap());
+        let error: crate::CalcError = vm.execute_all().unwrap_err();
+        assert_eq!(error.code(), "E0404");
+        assert_eq!(vm.take_outputs(), [Value::Number(3.0), Value::Number(5.0)]);
+
+        let mut vm = VM::new(Bytecode::new(Parser::new(Lexer::new("1 + 2: 4:"))).generate_bytecode().unwrap());
+        assert_eq!(vm.execute_all().unwrap(), [Value::Number(3.0), Value::Number(4.0)]);
+        assert!(vm.take_outputs().is_empty());
+    }
+}
@@ -34,10 +34,14 @@ pub enum TokenType {
     Colon,
     /// Comma, to seperate expressions
     Comma,
-    /// [, (
+    /// (
     OpeningBracket,
-    /// ], (
+    /// )
     ClosingBracket,
+    /// [, for vectors and matrices
+    OpeningSquareBracket,
+    /// ]
+    ClosingSquareBracket,
 
     // Operators
     /// *
@@ -117,8 +121,10 @@ impl Display for TokenType {
             Self::Semicolon => ";",
             Self::Colon => ":",
             Self::Comma => ",",
-            Self::OpeningBracket => "[ | (",
-            Self::ClosingBracket => "] | )",
+            Self::OpeningBracket => "(",
+            Self::ClosingBracket => ")",
+            Self::OpeningSquareBracket => "[",
+            Self::ClosingSquareBracket => "]",
             Self::Multiply => "*",
             Self::MultiplyEqual => "*=",
             Self::Divide => "/",
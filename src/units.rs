@@ -0,0 +1,573 @@
+use std::{collections::BTreeMap, fmt::Display, sync::{RwLock, RwLockReadGuard}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{functions::get_function, instruction::Value};
+
+/// Exponents of the SI base dimensions, in order: length, mass, time, current, temperature, amount of substance, luminous intensity,
+/// followed by money, which only the currencies loaded with `--units` measure
+pub type Dimension = [i32; 8];
+
+/// The largest power a unit can be raised to, ie. `m**1000`. Powers are kept exact, so they cannot grow without bound
+pub const MAX_POWER: i32 = 1000;
+
+/// The power of a unit, if it is not too large
+fn check_power(name: &str, power: Option<i32>) -> Result<i32, String> {
+    match power {
+        Some(power) if power.unsigned_abs() <= MAX_POWER.unsigned_abs() => Ok(power),
+        _ => Err(format!("The power of the unit `{name}` is too large! Units can be raised to powers up to {MAX_POWER}")),
+    }
+}
+
+const DIMENSIONLESS: Dimension = [0, 0, 0, 0, 0, 0, 0, 0];
+
+const CURRENCY: Dimension = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// A named unit: how many SI base units it is worth, and what it measures
+pub struct UnitDef {
+    pub name: &'static str,
+    pub scale: f64,
+    pub dimension: Dimension,
+    /// Whether SI prefixes (`k`, `m`, `µ`, ...) may be put in front of this unit
+    pub prefixable: bool,
+}
+
+/// Built in units are written with the seven SI exponents, since none of them measure money
+macro_rules! unit {
+    ($name: expr, $scale: expr, [$($dim: expr),*], $prefixable: expr) => {
+        UnitDef { name: $name, scale: $scale, dimension: [$($dim,)* 0], prefixable: $prefixable }
+    };
+}
+
+pub const UNITS: [UnitDef; 59] = [
+    // SI base units. The gram is the base here so that `kg` can be built with a prefix
+    unit!("m", 1.0, [1, 0, 0, 0, 0, 0, 0], true),
+    unit!("g", 1e-3, [0, 1, 0, 0, 0, 0, 0], true),
+    unit!("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+    unit!("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    unit!("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    unit!("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    unit!("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+
+    // Time
+    unit!("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("hr", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("d", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("day", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("days", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("week", 604800.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("weeks", 604800.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("yr", 31557600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("year", 31557600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    unit!("years", 31557600.0, [0, 0, 1, 0, 0, 0, 0], false),
+
+    // Length
+    unit!("in", 0.0254, [1, 0, 0, 0, 0, 0, 0], false),
+    unit!("ft", 0.3048, [1, 0, 0, 0, 0, 0, 0], false),
+    unit!("yd", 0.9144, [1, 0, 0, 0, 0, 0, 0], false),
+    unit!("mi", 1609.344, [1, 0, 0, 0, 0, 0, 0], false),
+    unit!("nmi", 1852.0, [1, 0, 0, 0, 0, 0, 0], false),
+    unit!("au", 149597870700.0, [1, 0, 0, 0, 0, 0, 0], false),
+    unit!("ly", 9460730472580800.0, [1, 0, 0, 0, 0, 0, 0], false),
+
+    // Area and volume
+    unit!("ha", 1e4, [2, 0, 0, 0, 0, 0, 0], false),
+    unit!("acre", 4046.8564224, [2, 0, 0, 0, 0, 0, 0], false),
+    unit!("L", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+    unit!("l", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+    unit!("gal", 3.785411784e-3, [3, 0, 0, 0, 0, 0, 0], false),
+    unit!("qt", 9.46352946e-4, [3, 0, 0, 0, 0, 0, 0], false),
+    unit!("floz", 2.95735295625e-5, [3, 0, 0, 0, 0, 0, 0], false),
+
+    // Mass
+    unit!("t", 1000.0, [0, 1, 0, 0, 0, 0, 0], false),
+    unit!("lb", 0.45359237, [0, 1, 0, 0, 0, 0, 0], false),
+    unit!("oz", 0.028349523125, [0, 1, 0, 0, 0, 0, 0], false),
+    unit!("st", 6.35029318, [0, 1, 0, 0, 0, 0, 0], false),
+
+    // Speed
+    unit!("mph", 0.44704, [1, 0, -1, 0, 0, 0, 0], false),
+    unit!("kph", 1.0 / 3.6, [1, 0, -1, 0, 0, 0, 0], false),
+    unit!("kn", 1852.0 / 3600.0, [1, 0, -1, 0, 0, 0, 0], false),
+
+    // Derived SI units
+    unit!("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    unit!("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    unit!("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0], true),
+    unit!("J", 1.0, [2, 1, -2, 0, 0, 0, 0], true),
+    unit!("W", 1.0, [2, 1, -3, 0, 0, 0, 0], true),
+    unit!("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    unit!("V", 1.0, [2, 1, -3, -1, 0, 0, 0], true),
+    unit!("F", 1.0, [-2, -1, 4, 2, 0, 0, 0], true),
+    unit!("ohm", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    unit!("Ω", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    unit!("T", 1.0, [0, 1, -2, -1, 0, 0, 0], true),
+
+    // Energy, power and pressure outside of SI
+    unit!("eV", 1.602176634e-19, [2, 1, -2, 0, 0, 0, 0], true),
+    unit!("cal", 4.184, [2, 1, -2, 0, 0, 0, 0], true),
+    unit!("Wh", 3600.0, [2, 1, -2, 0, 0, 0, 0], true),
+    unit!("BTU", 1055.05585262, [2, 1, -2, 0, 0, 0, 0], false),
+    unit!("hp", 745.699871582270, [2, 1, -3, 0, 0, 0, 0], false),
+    unit!("bar", 1e5, [-1, 1, -2, 0, 0, 0, 0], true),
+    unit!("atm", 101325.0, [-1, 1, -2, 0, 0, 0, 0], false),
+    unit!("psi", 6894.757293168, [-1, 1, -2, 0, 0, 0, 0], false),
+    unit!("lbf", 4.4482216152605, [1, 1, -2, 0, 0, 0, 0], false),
+];
+
+pub const PREFIXES: [(&str, f64); 21] = [
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("µ", 1e-6),
+    ("μ", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+];
+
+/// Well known dimensions, used to describe quantities in error messages
+const DIMENSION_NAMES: [(Dimension, &str); 16] = [
+    ([1, 0, 0, 0, 0, 0, 0, 0], "length"),
+    ([0, 1, 0, 0, 0, 0, 0, 0], "mass"),
+    ([0, 0, 1, 0, 0, 0, 0, 0], "time"),
+    ([0, 0, 0, 1, 0, 0, 0, 0], "current"),
+    ([0, 0, 0, 0, 1, 0, 0, 0], "temperature"),
+    ([0, 0, 0, 0, 0, 1, 0, 0], "amount of substance"),
+    ([0, 0, 0, 0, 0, 0, 1, 0], "luminous intensity"),
+    ([2, 0, 0, 0, 0, 0, 0, 0], "area"),
+    ([3, 0, 0, 0, 0, 0, 0, 0], "volume"),
+    ([1, 0, -1, 0, 0, 0, 0, 0], "speed"),
+    ([1, 0, -2, 0, 0, 0, 0, 0], "acceleration"),
+    ([1, 1, -2, 0, 0, 0, 0, 0], "force"),
+    ([2, 1, -2, 0, 0, 0, 0, 0], "energy"),
+    ([2, 1, -3, 0, 0, 0, 0, 0], "power"),
+    ([-1, 1, -2, 0, 0, 0, 0, 0], "pressure"),
+    (CURRENCY, "money"),
+];
+
+/// The time unit speeds are usually given in for each length, so that `5 km / 20 min` is shown as `15 km/h`
+const SPEED_TIMES: [(&str, &str); 4] = [("km", "h"), ("mi", "h"), ("nmi", "h"), ("m", "s")];
+
+/// `¤` is the generic currency sign, since the base currency depends on the tables that were loaded
+const BASE_SYMBOLS: [&str; 8] = ["m", "kg", "s", "A", "K", "mol", "cd", "¤"];
+
+/// Find a unit by name, returning its scale (in SI base units) and its dimension.
+/// Exact names are preferred over prefixed ones, so `min` is a minute and not a milli-inch
+pub fn lookup(name: &str) -> Option<(f64, Dimension)> {
+    for unit in &UNITS {
+        if unit.name == name {
+            return Some((unit.scale, unit.dimension));
+        }
+    }
+
+    for (prefix, factor) in PREFIXES {
+        if let Some(rest) = name.strip_prefix(prefix) {
+            for unit in &UNITS {
+                if unit.prefixable && unit.name == rest {
+                    return Some((unit.scale * factor, unit.dimension));
+                }
+            }
+        }
+    }
+    loaded_units().iter().find(|unit| unit.name == name).map(|unit| (unit.scale, unit.dimension))
+}
+
+pub fn is_unit(name: &str) -> bool {
+    lookup(name).is_some()
+}
+
+/// A unit or a currency loaded from a table with `--units`
+#[derive(Debug, Clone)]
+pub struct LoadedUnit {
+    pub name: String,
+    pub scale: f64,
+    pub dimension: Dimension,
+    /// How the unit was written in the table, ie. `201.168 m`
+    pub definition: String,
+    /// The path of the table the unit came from
+    pub source: String,
+}
+
+/// The units loaded at startup. They are never removed, so conversions made earlier stay valid
+static LOADED_UNITS: RwLock<Vec<LoadedUnit>> = RwLock::new(Vec::new());
+
+pub fn loaded_units() -> RwLockReadGuard<'static, Vec<LoadedUnit>> {
+    LOADED_UNITS.read().unwrap_or_else(|error| error.into_inner())
+}
+
+/// The layout of a units table:
+///
+/// ```toml
+/// [units]
+/// furlong = "201.168 m"
+/// fortnight = "14 days"
+///
+/// [currencies]
+/// base = "USD"
+/// rates = { EUR = 1.08, GBP = 1.27 }
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UnitTable {
+    /// Units defined in terms of other units, which can be built in or from this table
+    #[serde(default)]
+    units: BTreeMap<String, String>,
+    currencies: Option<CurrencyTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CurrencyTable {
+    base: String,
+    /// How much of the base currency one of each currency is worth
+    #[serde(default)]
+    rates: BTreeMap<String, f64>,
+}
+
+/// Parse the definition of a unit, ie. `201.168 m` or `1852 m/h`, into its scale and dimension
+fn parse_definition(definition: &str) -> Result<(f64, Dimension), String> {
+    let definition = definition.trim();
+    let (magnitude, units_expression) = match definition.split_once(char::is_whitespace) {
+        Some((magnitude, units)) if magnitude.parse::<f64>().is_ok() => (magnitude.parse().unwrap_or(1.0), units.trim()),
+        _ => match definition.parse::<f64>() {
+            Ok(magnitude) => (magnitude, ""),
+            Err(..) => (1.0, definition),
+        },
+    };
+    let mut units = Units::default();
+    if !units_expression.is_empty() {
+        for (index, part) in units_expression.replace("**", "^").split('/').enumerate() {
+            for factor in part.split('*') {
+                let (name, power) = match factor.trim().split_once('^') {
+                    Some((name, power)) => (name.trim(), power.trim().parse::<i32>().map_err(|_| format!("Expected an integer power in `{definition}`!"))?),
+                    None => (factor.trim(), 1),
+                };
+                if lookup(name).is_none() {
+                    return Err(format!("Unknown unit `{name}` in `{definition}`!"));
+                }
+                units.push(name, if index > 0 { -power } else { power }).map_err(|error| format!("{error} in `{definition}`"))?;
+            }
+        }
+    }
+    if !magnitude.is_finite() || magnitude <= 0.0 {
+        return Err(format!("Expected a positive size for the unit in `{definition}`!"));
+    }
+    let dimension = units.dimension();
+    if dimension.iter().any(|power| power.unsigned_abs() > MAX_POWER.unsigned_abs()) {
+        return Err(format!("The powers of the units in `{definition}` are too large! Units can be raised to powers up to {MAX_POWER}"));
+    }
+    Ok((magnitude * units.scale(), dimension))
+}
+
+/// Check that a new unit can be written in an expression and does not hide anything
+fn check_name(name: &str) -> Result<(), String> {
+    let mut characters = name.chars();
+    let valid = characters.next().is_some_and(|first| first.is_alphabetic() || first == '_')
+        && characters.all(|character| character.is_alphanumeric() || character == '_');
+    if !valid {
+        return Err(format!("`{name}` cannot be the name of a unit!"));
+    }
+    if is_unit(name) {
+        return Err(format!("The unit `{name}` already exists!"));
+    }
+    if get_function(name).is_some() {
+        return Err(format!("The unit `{name}` has the same name as a built in function!"));
+    }
+    Ok(())
+}
+
+fn register(name: &str, scale: f64, dimension: Dimension, definition: String, source: &str) -> Result<(), String> {
+    check_name(name).map_err(|error| format!("Invalid units table `{source}`: {error}"))?;
+    let unit = LoadedUnit { name: name.to_owned(), scale, dimension, definition, source: source.to_owned() };
+    LOADED_UNITS.write().unwrap_or_else(|error| error.into_inner()).push(unit);
+    Ok(())
+}
+
+/// Load the units and currencies of a table, returning how many there were. Units can be defined in terms of each
+/// other in any order. If any of them is invalid, none of the table is loaded
+pub fn load_table(path: &str) -> Result<usize, String> {
+    let loaded = LOADED_UNITS.read().unwrap_or_else(|error| error.into_inner()).len();
+    let result = load_units(path);
+    if result.is_err() {
+        // Units are looked up in the loaded ones while the table is read, so the ones from before the error are
+        // removed again
+        LOADED_UNITS.write().unwrap_or_else(|error| error.into_inner()).truncate(loaded);
+    }
+    result
+}
+
+fn load_units(path: &str) -> Result<usize, String> {
+    let source = std::fs::read_to_string(path).map_err(|error| format!("Failed to read the units table `{path}`: {error}"))?;
+    let table: UnitTable = toml::from_str(&source).map_err(|error| format!("Invalid units table `{path}`: {error}"))?;
+    let mut count = 0;
+
+    if let Some(currencies) = table.currencies {
+        register(&currencies.base, 1.0, CURRENCY, "base currency".to_owned(), path)?;
+        count += 1;
+        for (name, rate) in currencies.rates {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(format!("Expected a positive exchange rate for `{name}` in `{path}` but found {rate}!"));
+            }
+            register(&name, rate, CURRENCY, format!("{rate} {}", currencies.base), path)?;
+            count += 1;
+        }
+    }
+
+    let mut pending = table.units.into_iter().collect::<Vec<_>>();
+    while !pending.is_empty() {
+        let mut errors = vec![];
+        let before = pending.len();
+        for (name, definition) in std::mem::take(&mut pending) {
+            match parse_definition(&definition) {
+                Ok((scale, dimension)) => {
+                    register(&name, scale, dimension, definition, path)?;
+                    count += 1;
+                },
+                Err(error) => {
+                    errors.push(error);
+                    pending.push((name, definition));
+                },
+            }
+        }
+        // Nothing could be defined this time around, so the rest never will be
+        if pending.len() == before {
+            return Err(format!("Invalid units table `{path}`: {}", errors.join(" ")));
+        }
+    }
+    Ok(count)
+}
+
+/// Describe a dimension in words if it is a well known one, otherwise in terms of the SI base units
+pub fn describe_dimension(dimension: &Dimension) -> String {
+    if *dimension == DIMENSIONLESS {
+        return "dimensionless".to_owned();
+    }
+    for (known, name) in DIMENSION_NAMES {
+        if known == *dimension {
+            return name.to_owned();
+        }
+    }
+    let units = Units {
+        units: dimension
+            .iter()
+            .zip(BASE_SYMBOLS)
+            .filter(|(power, _)| **power != 0)
+            .map(|(power, symbol)| (symbol.to_owned(), *power))
+            .collect(),
+    };
+    format!("{units}")
+}
+
+/// A product of named units raised to integer powers, ie. `km/h` is `[("km", 1), ("h", -1)]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Units {
+    pub(crate) units: Vec<(String, i32)>,
+}
+
+impl Units {
+    pub fn new(name: &str, power: i32) -> Self {
+        Self { units: vec![(name.to_owned(), power)] }
+    }
+
+    /// Multiply by a single unit by name, without converting between units of the same dimension
+    pub fn push(&mut self, name: &str, power: i32) -> Result<(), String> {
+        match self.units.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, existing_power)) => *existing_power = check_power(name, existing_power.checked_add(power))?,
+            None => self.units.push((name.to_owned(), check_power(name, Some(power))?)),
+        }
+        self.units.retain(|(_, power)| *power != 0);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+
+    /// The factor to multiply a magnitude in these units by to get it in SI base units
+    pub fn scale(&self) -> f64 {
+        self.units.iter().map(|(name, power)| {
+            let (scale, _) = lookup(name).unwrap_or((1.0, DIMENSIONLESS));
+            scale.powi(*power)
+        }).product()
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        let mut result = DIMENSIONLESS;
+        for (name, power) in &self.units {
+            let (_, dimension) = lookup(name).unwrap_or((1.0, DIMENSIONLESS));
+            // Powers are limited, so this only saturates for tables with thousands of units of different dimensions
+            for i in 0..result.len() {
+                result[i] = result[i].saturating_add(dimension[i].saturating_mul(*power));
+            }
+        }
+        result
+    }
+
+    pub fn powi(&self, exponent: i32) -> Result<Self, String> {
+        let mut units = vec![];
+        for (name, power) in &self.units {
+            units.push((name.clone(), check_power(name, power.checked_mul(exponent))?));
+        }
+        units.retain(|(_, power)| *power != 0);
+        Ok(Self { units })
+    }
+
+    /// Multiply two sets of units, returning the result and the factor the magnitude must be scaled by.
+    /// Units of `other` that measure the same thing as a unit already in `self` are converted to it,
+    /// so that `km * m` becomes `km**2` and `km / m` cancels out
+    pub fn mul(&self, other: &Units) -> Result<(Self, f64), String> {
+        let mut units = self.units.clone();
+        let mut factor = 1.0;
+        for (name, power) in &other.units {
+            let existing = units.iter_mut().find(|(existing, _)| {
+                existing == name || (lookup(existing).map(|unit| unit.1) == lookup(name).map(|unit| unit.1))
+            });
+            match existing {
+                Some((existing, existing_power)) => {
+                    if existing != name {
+                        let (from, _) = lookup(name).unwrap_or((1.0, DIMENSIONLESS));
+                        let (to, _) = lookup(existing).unwrap_or((1.0, DIMENSIONLESS));
+                        factor *= (from / to).powi(*power);
+                    }
+                    *existing_power = check_power(existing, existing_power.checked_add(*power))?;
+                }
+                None => units.push((name.clone(), *power)),
+            }
+        }
+        units.retain(|(_, power)| *power != 0);
+        Ok((Self { units }, factor))
+    }
+
+    pub fn div(&self, other: &Units) -> Result<(Self, f64), String> {
+        self.mul(&other.powi(-1)?)
+    }
+}
+
+impl Display for Units {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_unit = |name: &str, power: i32| {
+            if power == 1 { name.to_owned() } else { format!("{name}**{power}") }
+        };
+        let numerator = self.units.iter()
+            .filter(|(_, power)| *power > 0)
+            .map(|(name, power)| format_unit(name, *power))
+            .collect::<Vec<_>>();
+        let denominator = self.units.iter()
+            .filter(|(_, power)| *power < 0)
+            .map(|(name, power)| format!("/{}", format_unit(name, -power)))
+            .collect::<String>();
+        let numerator = if numerator.is_empty() { "1".to_owned() } else { numerator.join("*") };
+        write!(f, "{numerator}{denominator}")
+    }
+}
+
+/// A number with units attached to it. The magnitude is stored in terms of `units` and not in SI base units,
+/// so that results are shown in the units they were written in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quantity {
+    pub(crate) magnitude: f64,
+    pub(crate) units: Units,
+}
+
+impl Quantity {
+    pub fn new(magnitude: f64, units: Units) -> Self {
+        Self { magnitude, units }
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.units.dimension()
+    }
+
+    /// Collapse quantities whose units have cancelled out into a plain number
+    pub fn into_value(self) -> Value {
+        if self.units.is_empty() {
+            Value::Number(self.magnitude)
+        } else {
+            Value::Quantity(self)
+        }
+    }
+
+    /// Describe the units and the dimension of this quantity for error messages, ie. "`km/h` (speed)"
+    pub fn describe(&self) -> String {
+        format!("`{}` ({})", self.units, describe_dimension(&self.dimension()))
+    }
+
+    pub fn convert(&self, target: &Units) -> Result<Quantity, String> {
+        if self.dimension() != target.dimension() {
+            return Err(
+                format!(
+                    "Cannot convert {} to `{target}` ({}) since their dimensions do not match!",
+                    self.describe(), describe_dimension(&target.dimension())
+                )
+            );
+        }
+        Ok(Quantity::new(self.magnitude * self.units.scale() / target.scale(), target.clone()))
+    }
+
+    /// Add or subtract quantities of the same dimension. The result is in the units of `self`
+    pub fn add(&self, other: &Quantity, negate: bool) -> Result<Quantity, String> {
+        let other = other.convert(&self.units)?;
+        let magnitude = if negate { self.magnitude - other.magnitude } else { self.magnitude + other.magnitude };
+        Ok(Quantity::new(magnitude, self.units.clone()))
+    }
+
+    pub fn mul(&self, other: &Quantity) -> Result<Quantity, String> {
+        let (units, factor) = self.units.mul(&other.units)?;
+        Ok(Quantity::new(self.magnitude * other.magnitude * factor, units))
+    }
+
+    pub fn div(&self, other: &Quantity) -> Result<Quantity, String> {
+        let (units, factor) = self.units.div(&other.units)?;
+        Quantity::new(self.magnitude / other.magnitude * factor, units).usual_speed()
+    }
+
+    /// A length over a time in the time unit usually used with that length, ie. `km/min` becomes `km/h`
+    fn usual_speed(self) -> Result<Quantity, String> {
+        let [(length, 1), (time, -1)] = self.units.units.as_slice() else {
+            return Ok(self);
+        };
+        let usual = SPEED_TIMES.iter().find(|(name, _)| name == length).map(|(_, time)| *time);
+        match usual {
+            Some(usual) if usual != time && lookup(time).map(|unit| unit.1) == lookup(usual).map(|unit| unit.1) => {
+                let mut units = Units::new(length, 1);
+                units.push(usual, -1)?;
+                self.convert(&units)
+            },
+            _ => Ok(self),
+        }
+    }
+
+    pub fn powi(&self, exponent: i32) -> Result<Quantity, String> {
+        Ok(Quantity::new(self.magnitude.powi(exponent), self.units.powi(exponent)?))
+    }
+
+    /// The value of a dimensionless quantity (ie. `km/m`) as a plain number
+    pub fn as_number(&self) -> Option<f64> {
+        if self.dimension() == DIMENSIONLESS {
+            Some(self.magnitude * self.units.scale())
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for Quantity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.magnitude, self.units)
+    }
+}
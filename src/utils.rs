@@ -1,6 +1,8 @@
-use std::{ops::Range, fmt::Display};
+use std::{collections::BTreeSet, ops::Range, fmt::Display, sync::Mutex};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
 pub struct Span {
     pub(crate) start: usize,
     pub(crate) end: usize,
@@ -32,6 +34,19 @@ impl Span {
     }
 }
 
+/// Names made up while parsing, like `f'` for the derivative of `f`, have to live as long as the names in the source.
+/// Each one is allocated once and kept for the rest of the program, so making the same name again allocates nothing
+pub fn intern(name: String) -> &'static str {
+    static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let mut names = NAMES.lock().unwrap_or_else(|error| error.into_inner());
+    if let Some(existing) = names.get(name.as_str()) {
+        return existing;
+    }
+    let name = Box::leak(name.into_boxed_str());
+    names.insert(name);
+    name
+}
+
 impl Display for Span {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.start == self.end {
@@ -1,20 +1,86 @@
-use std::{collections::HashMap, io::Write};
+use std::{collections::HashMap, ops::{Range, RangeInclusive}};
 
-use serde::de::value;
+use crate::{ast::Operator, diagnostics::{did_you_mean, Diagnostic}, errors::CalcError, utils::Span, dates::{self, Duration}, functions::{all_functions, describe_function, expect_integer, get_function, Builtin}, format::NumberFormat, instruction::{Function, Instruction, Value}, linalg::{self, Matrix}, random::Random, units::{Quantity, Units}};
 
-use crate::{ast::Operator, functions::get_function, instruction::{Function, Instruction, Value}};
+/// The most terms a sum or product can have, so that a typo in a bound does not hang the VM
+const MAX_TERMS: i64 = 10_000_000;
 
 pub enum VMError {
-    BinOnNaN,
     InvalidBytecode,
+    DivisionByZero,
+    /// A variable that does not exist, with the message saying which
+    UnknownVariable(String),
+    UnknownFunction(String),
+    /// An operation on values of types it does not accept
+    TypeMismatch(String),
+    /// An operation on quantities that do not measure the same thing
+    DimensionMismatch(String),
+    /// An operation on vectors or matrices of shapes that do not fit together
+    ShapeMismatch(String),
+    /// A fraction given to a function that only accepts integers
+    NotAnInteger(String),
+    /// An argument outside of the values a function is defined for, ie. a negative number given to `fib`
+    OutOfDomain(String),
     ErrString(String)
 }
 
+impl VMError {
+    /// The code of the error, which never changes once given out. `E0401` is no longer used
+    pub fn code(&self) -> &'static str {
+        match self {
+            VMError::InvalidBytecode => "E0402",
+            VMError::ErrString(..) => "E0403",
+            VMError::DivisionByZero => "E0404",
+            VMError::UnknownVariable(..) => "E0405",
+            VMError::UnknownFunction(..) => "E0406",
+            VMError::TypeMismatch(..) => "E0407",
+            VMError::DimensionMismatch(..) => "E0408",
+            VMError::ShapeMismatch(..) => "E0409",
+            VMError::NotAnInteger(..) => "E0410",
+            VMError::OutOfDomain(..) => "E0411",
+        }
+    }
+
+    pub fn message(self) -> String {
+        match self {
+            VMError::InvalidBytecode => "The bytecode provided to the VM appears to be invalid, or containing a bug that causes the program to unexpectedly crash".to_owned(),
+            VMError::DivisionByZero => "Cannot divide a number by zero!".to_owned(),
+            VMError::UnknownVariable(string) | VMError::UnknownFunction(string) | VMError::TypeMismatch(string)
+            | VMError::DimensionMismatch(string) | VMError::ShapeMismatch(string) | VMError::NotAnInteger(string)
+            | VMError::OutOfDomain(string) | VMError::ErrString(string) => string,
+        }
+    }
+}
+
+impl From<String> for VMError {
+    fn from(message: String) -> Self {
+        VMError::ErrString(message)
+    }
+}
+
+/// Where a runtime error happened: the instruction that failed and the calls to user functions it was in, with the
+/// address of each call
+#[derive(Debug, Clone, Default)]
+struct Failure {
+    address: usize,
+    calls: Vec<(String, usize)>,
+}
+
 pub struct VM<'a> {
     instructions: Vec<Instruction<'a>>,
+    /// The span of the source each instruction comes from, if it is known
+    spans: Vec<Option<Span>>,
+    source: &'a str,
+    /// The user functions being run, innermost last, with the address of the instruction that called each of them
+    calls: Vec<(String, usize)>,
+    failure: Option<Failure>,
     stack: Vec<Value>,
     pc: usize,
     pub(crate) outputs: Vec<Value>,
+    /// The lines written by `print(...)`
+    printed: Vec<String>,
+    format: NumberFormat,
+    pub(crate) random: Random,
     symbols: HashMap<&'a str, Value>,
     functions: HashMap<&'a str, Function>,
 }
@@ -25,9 +91,16 @@ impl<'a> VM<'a> {
             pc: 0,
             stack: vec![],
             outputs: vec![],
+            printed: vec![],
+            format: NumberFormat::default(),
+            random: Random::from_time(),
             symbols: HashMap::new(),
             functions: HashMap::new(),
             instructions,
+            spans: vec![],
+            source: "",
+            calls: vec![],
+            failure: None,
         }
     }
 
@@ -36,43 +109,108 @@ impl<'a> VM<'a> {
             pc: 0,
             stack: vec![],
             outputs: vec![],
+            printed: vec![],
+            format: NumberFormat::default(),
+            random: Random::from_time(),
             symbols,
             functions,
             instructions,
+            spans: vec![],
+            source: "",
+            calls: vec![],
+            failure: None,
         }
     }
 
-    pub fn print_output(&self) {
-        if self.outputs.len() > 0 {
-            println!("Results: {}", self.outputs.iter().map(|value| format!("{value}")).collect::<Vec<_>>().join(", "));
-        } else {
-            println!("No results for this expression");
-        }
+    /// Give the source the instructions were generated from and the span table of the instructions, so that runtime
+    /// errors can show where they happened
+    pub fn set_spans(&mut self, source: &'a str, spans: Vec<Option<Span>>) {
+        self.source = source;
+        self.spans = spans;
+    }
+
+    /// Change how numbers are shown by `print` and in the results
+    pub fn set_format(&mut self, format: NumberFormat) {
+        self.format = format;
+    }
+
+    /// Replace the random number generator, ie. with one that has been seeded to make a run reproducible
+    pub fn set_random(&mut self, random: Random) {
+        self.random = random;
+    }
+
+    pub fn get_random(&self) -> Random {
+        self.random.clone()
     }
 
-    pub fn execute_all(&mut self) {
+    /// Take the lines written by `print(...)` so far
+    pub fn take_printed(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.printed)
+    }
+
+    /// Take the results given before a runtime error stopped the run
+    pub fn take_outputs(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.outputs)
+    }
+
+    /// Run every instruction, giving the results of the expressions that end with `:`. Stops at the first runtime
+    /// error, and the results from before it can be taken with `take_outputs`
+    pub fn execute_all(&mut self) -> Result<Vec<Value>, CalcError> {
         // Don't run code that is empty or invalid
         if self.instructions.len() == 0 || self.instructions[0] == Instruction::CompileError {
-            return;
+            return Ok(vec![]);
         }
 
         while self.pc < self.instructions.len() {
-            match self.execute_next() {
-                Ok(_) => (),
-                Err(error) => {
-                    // Stop the vm since a runtime error has occured.
-                    self.pc = self.instructions.len();
-                    match error {
-                        VMError::BinOnNaN => println!("[RUNTIME ERROR]: Binary operation cannot be performed on a value that is not a number"),
-                        VMError::InvalidBytecode => println!("[RUNTIME ERROR]: The bytecode provided to the VM appears to be invalid, or containing a bug that causes the program to unexpectedly crash"),
-                        VMError::ErrString(string) => println!("[RUNTIME ERROR]: {string}"),
-                    }
-                }
-            };
+            if let Err(error) = self.execute_next() {
+                // Stop the vm since a runtime error has occured.
+                self.pc = self.instructions.len();
+                return Err(CalcError::Runtime { source: self.source.to_owned(), diagnostic: Box::new(self.diagnostic(error)) });
+            }
         }
+        Ok(self.take_outputs())
     }
 
+    /// The diagnostic of a runtime error, pointing at the part of the source that failed and the calls it happened in
+    fn diagnostic(&mut self, error: VMError) -> Diagnostic {
+        let code = error.code();
+        let mut diagnostic = Diagnostic::new("RUNTIME ERROR", error.message()).with_code(code);
+        let Some(failure) = self.failure.take() else {
+            return diagnostic;
+        };
+        let span = |address: usize| self.spans.get(address).copied().flatten();
+        // Functions from another line of the repl have no spans, so the innermost call that has one is shown instead
+        let mut places = std::iter::once((None, failure.address))
+            .chain(failure.calls.iter().rev().map(|(name, address)| (Some(name), *address)));
+        for (_, address) in places.by_ref() {
+            if let Some(span) = span(address) {
+                diagnostic = diagnostic.with_span(span);
+                break;
+            }
+        }
+        for (name, address) in places {
+            if let (Some(name), Some(span)) = (name, span(address)) {
+                diagnostic = diagnostic.with_label(span, format!("in this call to {}", describe_function(name)));
+            }
+        }
+        if !failure.calls.is_empty() {
+            let trace = failure.calls.iter().rev().map(|(name, _)| describe_function(name)).collect::<Vec<_>>();
+            diagnostic = diagnostic.with_note(format!("call stack: {}", trace.join(", called from ")));
+        }
+        diagnostic
+    }
+
+    /// Run the next instruction. If it fails, where it failed is kept for the error message
     pub fn execute_next(&mut self) -> Result<(), VMError> {
+        let address = self.pc;
+        let result = self.execute_instruction();
+        if result.is_err() && self.failure.is_none() {
+            self.failure = Some(Failure { address, calls: self.calls.clone() });
+        }
+        result
+    }
+
+    fn execute_instruction(&mut self) -> Result<(), VMError> {
         self.pc += 1;
         match &self.instructions[self.pc - 1] {
             Instruction::Load { value } => self.stack.push(value.clone()),
@@ -80,66 +218,12 @@ impl<'a> VM<'a> {
             Instruction::Binary { operator } => {
                 let rhs = self.stack.pop().unwrap();
                 let lhs = self.stack.pop().unwrap();
-                match (lhs, rhs) {
-                    (Value::Number(a), Value::Number(b)) => {
-                        let res = match operator {
-                            Operator::Plus => a + b,
-                            Operator::Minus => a - b,
-                            Operator::Multiply => a * b,
-                            Operator::Divide => {
-                                if b == 0.0 {
-                                    return Err(VMError::ErrString(format!("Cannot divide a number by zero!")));
-                                }
-                                a / b
-                            },
-                            Operator::Exponent => a.powf(b),
-                            Operator::BitAnd => (a as usize & b as usize) as f64,
-                            Operator::BitOr => (a as usize | b as usize) as f64,
-                            Operator::BitXor => (a as usize ^ b as usize) as f64,
-                            Operator::BitLeftShift => ((a as usize) << (b as usize)) as f64,
-                            Operator::BitRightShift => ((a as usize) >> (b as usize)) as f64,
-                            _ => unimplemented!()
-                        };
-                        self.stack.push(Value::Number(res));
-                    }
-
-                    (Value::String(a), Value::String(b)) => {
-                        let res = match operator {
-                            Operator::Plus => {
-                                let mut base = a;
-                                base.push_str(&b);
-                                base
-                            },
-                            _ => return Err(VMError::ErrString(format!("Cannot perform binary operation `{operator}` on strings!")))
-                        };
-                        self.stack.push(Value::String(res));
-                    }
-
-                    (a, b) => {
-                        return Err(
-                            VMError::ErrString(
-                                format!(
-                                    "Cannot perform binary operation `{operator}` on mismatched types: lhs `{}` and rhs `{}`!", 
-                                    a.type_of(), b.type_of()
-                                )
-                            )
-                        );
-                    }
-                }
+                self.stack.push(Self::binary(*operator, lhs, rhs)?);
             },
 
             Instruction::Unary { operator } => {
-                let rhs = match self.stack.pop().unwrap() {
-                    Value::Number(number) => number,
-                    _ => return Err(VMError::ErrString(format!("Cannot perform unary operations on non numerical values"))),
-                };
-
-                let result = match operator {
-                    Operator::Plus => rhs,
-                    Operator::Minus => -rhs,
-                    _ => return Err(VMError::ErrString(format!("Unable to perform unary operation {operator} on a number!"))),
-                };
-                self.stack.push(Value::Number(result))
+                let rhs = self.stack.pop().unwrap();
+                self.stack.push(Self::unary(*operator, rhs)?)
             }
 
             Instruction::Output => {
@@ -167,7 +251,10 @@ impl<'a> VM<'a> {
             Instruction::CallSymbol { name } => {
                 match self.symbols.get(name) {
                     Some(value) => self.stack.push(value.clone()),
-                    None => return Err(VMError::ErrString(format!("The variable `{name}` does not exist!"))),
+                    None => {
+                        let names = self.symbols.keys().copied();
+                        return Err(VMError::UnknownVariable(format!("The variable `{name}` does not exist!{}", did_you_mean(name, names))));
+                    },
                 }
             },
 
@@ -180,7 +267,7 @@ impl<'a> VM<'a> {
                         };        
                         *value = new_value;
                     },
-                    None => return Err(VMError::ErrString(format!("Cannot assign a value to variable {name} because it does not exist!"))),
+                    None => return Err(VMError::UnknownVariable(format!("Cannot assign a value to variable {name} because it does not exist!"))),
                 }
                 self.stack.push(Value::Null);
             },
@@ -193,115 +280,27 @@ impl<'a> VM<'a> {
 
                 self.pc += 1;
 
+                let new_value = match self.stack.pop() {
+                    Some(res) => res,
+                    None => return Err(VMError::InvalidBytecode), 
+                };
                 match self.symbols.get_mut(name) {
-                    Some(value) => {
-                        let new_value = match self.stack.pop() {
-                            Some(res) => res,
-                            None => return Err(VMError::InvalidBytecode), 
-                        };
-                        match (new_value, value) {
-                            (Value::Number(a), Value::Number(b)) => {
-                                match operator {
-                                    Operator::PlusEqual => *b += a,
-                                    Operator::MinusEqual => *b -= a,
-                                    Operator::DivideEqual => *b /= a,
-                                    Operator::MultiplyEqual => *b *= a,
-                                    Operator::ExponentEqual => *b = f64::powf(*b, a),
-                                    Operator::BitOrEqual => *b = (*b as usize | a as usize) as f64,
-                                    Operator::BitAndEqual => *b = (*b as usize & a as usize) as f64,
-                                    Operator::BitXorEqual => *b = (*b as usize ^ a as usize) as f64,
-                                    Operator::BitLeftShiftEqual => *b = ((*b as usize) << a as usize) as f64,
-                                    Operator::BitRightShiftEqual => *b = (*b as usize >> a as usize) as f64,
-        
-                                    _ => unimplemented!()
-                                };
-                            }
-
-                            (Value::String(a), Value::String(b)) => {
-                                match operator {
-                                    Operator::PlusEqual => b.push_str(a.as_str()),
-        
-                                    _ => return Err(VMError::ErrString(format!("Cannot perform operation `{operator}` on strings!"))),
-                                };
-                            }
-
-                            (new_value, value) => {
-                                return Err(
-                                            VMError::ErrString(
-                                                format!(
-                                                    "Cannot perform operation `{operator}` on mismatched types: lhs `{}` and rhs `{}`!", 
-                                                    value.type_of(), new_value.type_of()
-                                                )
-                                            )
-                                        );
-                            }
-                        }
-                    },
-                    None => return Err(VMError::ErrString(format!("Cannot find variable {name} to change its value!"))),
+                    // The variable keeps its old value if the operation fails, so the repl can still use it
+                    Some(value) => *value = Self::binary(operator.without_assign(), value.clone(), new_value)?,
+                    None => return Err(VMError::UnknownVariable(format!("Cannot find variable {name} to change its value!"))),
                 }
                 self.stack.push(Value::Null);
             },
 
-            // Really slow?
-            Instruction::FunctionCall { name } => {
-                let mut arguments = vec![];
-                match get_function(name) {
-                    Ok((length, function)) => {
-                        for _ in 0..length {
-                            let arg = match self.stack.pop() {
-                                Some(value) => {
-                                    match value {
-                                        Value::Number(num) => num,
-                                        _ => return Err(VMError::ErrString(format!("Functions that do not deal with values other than numbers are not yet supported!"))),
-                                    }
-                                },
-                                None => return Err(VMError::ErrString(format!("Failed to get arguments to function {name} (Likely an internal error)!"))),
-                            };
-                            arguments.push(arg);
-                        }
-                        self.stack.push(Value::Number(function(arguments.as_slice())))        
-                    },
-
-                    // Look for function in function symbols
-                    Err(..) => {
-                        if let Some(function) = self.functions.get(name) {
-                            let fn_args_address = function.instructions.start - function.arguments;
-                            let fn_body_address = function.instructions.start;
-                            let fn_body_end = function.instructions.end;
-
-                            let orig_pc = self.pc;
-                            let orig_symbols = self.symbols.clone();
-                            self.pc = fn_args_address;
-                            let args_len = fn_body_address - fn_args_address;
-                            for i in (0..args_len).rev() {
-                                let arg = &self.instructions[self.pc + i];
-                                match arg {
-                                    Instruction::ArgumentName { name } => {
-                                        let value = match self.stack.pop() {
-                                            Some(value) => value,
-                                            None => return Err(VMError::InvalidBytecode),
-                                        };
-                                        self.symbols.insert(name, value);
-                                    }
-
-                                    _ => return Err(VMError::InvalidBytecode),
-                                }
-                            }
-                            
-                            self.pc = fn_body_address;
-                            
-                            for _ in 0..(fn_body_end - fn_body_address) {
-                                self.execute_next()?;
-                            }
-
-                            self.pc = orig_pc;
-                            self.symbols = orig_symbols;
-
-                        } else {
-                            return Err(VMError::ErrString(format!("The function `{name}` does not exist!")));
-                        }
-                    }
-                };
+            Instruction::FunctionCall { name, arguments } => {
+                let (name, length) = (*name, *arguments);
+                if length > self.stack.len() {
+                    return Err(VMError::ErrString(format!("Failed to get arguments to function {name} (Likely an internal error)!")));
+                }
+                let end = self.stack.len();
+                let arguments = self.stack.split_off(end - length);
+                let result = self.call_function(name, arguments)?;
+                self.stack.push(result);
             }
 
             Instruction::Null => self.stack.push(Value::Null),
@@ -311,7 +310,7 @@ impl<'a> VM<'a> {
                 self.symbols.remove(name);
                 self.functions.remove(name);
 
-                if let Ok(..) = get_function(name) {
+                if get_function(name).is_some() {
                     return Err(VMError::ErrString(format!("Cannot delete builtin function `{name}`")));
                 }
                 self.stack.push(Value::Null);
@@ -338,14 +337,38 @@ impl<'a> VM<'a> {
                 self.stack.push(Value::Null);
             }
 
+            Instruction::Vector { length } => {
+                let end = self.stack.len();
+                if *length > end {
+                    return Err(VMError::InvalidBytecode);
+                }
+                let elements = self.stack.drain((end - length)..end).collect();
+                self.stack.push(linalg::build(elements)?);
+            }
+
+            Instruction::Convert { units } => {
+                let value = match self.stack.pop() {
+                    Some(value) => value,
+                    None => return Err(VMError::InvalidBytecode),
+                };
+                self.stack.push(Self::convert(value, units)?);
+            }
+
+            Instruction::Series { index, operator, length } => {
+                let (index, operator, length) = (*index, *operator, *length);
+                let (to, from) = match (self.stack.pop(), self.stack.pop()) {
+                    (Some(to), Some(from)) => (to, from),
+                    _ => return Err(VMError::InvalidBytecode),
+                };
+                let result = self.series(index, operator, length, from, to)?;
+                self.stack.push(result);
+            }
+
             Instruction::Print { depth } => {
                 let end = self.stack.len();
                 let drained = self.stack.drain((end - depth)..(end));
-                std::io::stdout().flush().ok();
-                for value in drained {
-                    print!("{value} ");
-                }
-                println!();
+                let line = drained.map(|value| value.format(&self.format)).collect::<Vec<_>>().join(" ");
+                self.printed.push(line);
                 self.stack.push(Value::Null);
             }
 
@@ -363,6 +386,265 @@ impl<'a> VM<'a> {
         Ok(())
     }
 
+    /// Call a built in or user function with arguments that have already been evaluated, ie. from a built in
+    /// function like `root` that takes another function as an argument
+    pub fn call_function(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, VMError> {
+        if let Some((arity, function)) = get_function(name) {
+            if !arity.accepts(arguments.len()) {
+                return Err(VMError::ErrString(format!("The function `{name}` expected {arity} argument(s) but {} argument(s) were found!", arguments.len())));
+            }
+            let result = match function {
+                Builtin::Pure(function) => function(arguments.as_slice()),
+                Builtin::WithVM(function) => function(self, arguments.as_slice()),
+            };
+            return result;
+        }
+
+        let (arguments_address, body) = match self.functions.get(name) {
+            Some(function) => (function.instructions.start - function.arguments, function.instructions.clone()),
+            None => {
+                let functions = all_functions().map(|(name, _)| *name).chain(self.functions.keys().copied());
+                return Err(VMError::UnknownFunction(format!("The function `{name}` does not exist!{}", did_you_mean(name, functions))));
+            },
+        };
+        if arguments.len() != body.start - arguments_address {
+            return Err(VMError::ErrString(format!("The function `{name}` expected {} argument(s) but {} argument(s) were found!", body.start - arguments_address, arguments.len())));
+        }
+
+        let orig_pc = self.pc;
+        let orig_symbols = self.symbols.clone();
+        let orig_stack = self.stack.len();
+        for (address, value) in (arguments_address..body.start).zip(arguments) {
+            match self.instructions[address] {
+                Instruction::ArgumentName { name } => { self.symbols.insert(name, value); },
+                _ => return Err(VMError::InvalidBytecode),
+            }
+        }
+
+        // Run the body until it ends, rather than for a number of instructions, since some instructions skip others
+        self.calls.push((name.to_owned(), orig_pc.saturating_sub(1)));
+        self.pc = body.start;
+        let mut result = Ok(());
+        while self.pc < body.end && result.is_ok() {
+            result = self.execute_next();
+        }
+        let result = result.and_then(|_| self.stack.pop().ok_or(VMError::InvalidBytecode));
+
+        self.pc = orig_pc;
+        self.symbols = orig_symbols;
+        self.stack.truncate(orig_stack);
+        self.calls.pop();
+        result
+    }
+
+    /// Run the body of a sum or product, which is the `length` instructions after the current one, for every integer
+    /// from `from` to `to`. The index goes back to its previous value afterwards
+    fn series(&mut self, index: &'a str, operator: Operator, length: usize, from: Value, to: Value) -> Result<Value, VMError> {
+        let name = if operator == Operator::Multiply { "prod" } else { "sum" };
+        let from = expect_integer(&from, name)?;
+        let to = expect_integer(&to, name)?;
+        if to.saturating_sub(from) >= MAX_TERMS {
+            return Err(VMError::ErrString(format!("The function `{name}` can have at most {MAX_TERMS} terms, but {from} to {to} was found!")));
+        }
+
+        let body = self.pc..(self.pc + length);
+        let orig_index = self.symbols.get(index).cloned();
+        let orig_stack = self.stack.len();
+        let result = self.series_terms(index, operator, body.clone(), from..=to);
+
+        self.pc = body.end;
+        self.stack.truncate(orig_stack);
+        match orig_index {
+            Some(value) => self.symbols.insert(index, value),
+            None => self.symbols.remove(index),
+        };
+        result
+    }
+
+    fn series_terms(&mut self, index: &'a str, operator: Operator, body: Range<usize>, range: RangeInclusive<i64>) -> Result<Value, VMError> {
+        let mut total = None;
+        for term in range {
+            self.symbols.insert(index, Value::Number(term as f64));
+            // Run the body until it ends, like the body of a function
+            self.pc = body.start;
+            while self.pc < body.end {
+                self.execute_next()?;
+            }
+            let term = self.stack.pop().ok_or(VMError::InvalidBytecode)?;
+            total = Some(match total {
+                Some(total) => Self::binary(operator, total, term)?,
+                None => term,
+            });
+        }
+        // An empty range is the identity of the operator
+        Ok(total.unwrap_or(Value::Number(if operator == Operator::Multiply { 1.0 } else { 0.0 })))
+    }
+
+    /// Negate a value, or leave it as is for a unary plus
+    pub(crate) fn unary(operator: Operator, rhs: Value) -> Result<Value, VMError> {
+        if !matches!(operator, Operator::Plus | Operator::Minus) {
+            return Err(VMError::ErrString(format!("Unable to perform unary operation {operator} on a number!")));
+        }
+        let result = match (rhs, operator) {
+            (rhs, Operator::Plus) if !matches!(rhs, Value::String(..) | Value::Function(..) | Value::Null) => rhs,
+            (Value::Number(number), _) => Value::Number(-number),
+            (Value::Quantity(quantity), _) => Value::Quantity(Quantity::new(-quantity.magnitude, quantity.units)),
+            (Value::Vector(vector), _) => Value::Vector(vector.iter().map(|value| -value).collect()),
+            (Value::Matrix(matrix), _) => Value::Matrix(Matrix::new(matrix.rows, matrix.cols, matrix.data.iter().map(|value| -value).collect())),
+            (Value::Duration(duration), _) => Value::Duration(Duration::new(-duration.seconds)),
+            _ => return Err(VMError::TypeMismatch("Cannot perform unary operations on non numerical values".to_owned())),
+        };
+        Ok(result)
+    }
+
+    /// Convert a quantity, or a plain number, to other units
+    pub(crate) fn convert(value: Value, units: &Units) -> Result<Value, VMError> {
+        let result = match value {
+            Value::Quantity(quantity) => quantity.convert(units).map_err(VMError::DimensionMismatch)?,
+            Value::Number(number) => Quantity::new(number, Units::default()).convert(units).map_err(VMError::DimensionMismatch)?,
+            Value::Duration(duration) => Quantity::new(duration.seconds, Units::new("s", 1)).convert(units).map_err(VMError::DimensionMismatch)?,
+            value => return Err(VMError::TypeMismatch(format!("Cannot convert a value of type `{}` to `{units}`!", value.type_of()))),
+        };
+        Ok(Value::Quantity(result))
+    }
+
+    /// Perform a binary operation on two values, checking that the types and the dimensions of quantities match
+    pub(crate) fn binary(operator: Operator, lhs: Value, rhs: Value) -> Result<Value, VMError> {
+        match (lhs, rhs) {
+            (Value::Number(a), Value::Number(b)) => {
+                let res = match operator {
+                    Operator::Plus => a + b,
+                    Operator::Minus => a - b,
+                    Operator::Multiply => a * b,
+                    Operator::Divide => {
+                        if b == 0.0 {
+                            return Err(VMError::DivisionByZero);
+                        }
+                        a / b
+                    },
+                    Operator::Exponent => a.powf(b),
+                    Operator::BitAnd => (a as usize & b as usize) as f64,
+                    Operator::BitOr => (a as usize | b as usize) as f64,
+                    Operator::BitXor => (a as usize ^ b as usize) as f64,
+                    Operator::BitLeftShift => ((a as usize) << (b as usize)) as f64,
+                    Operator::BitRightShift => ((a as usize) >> (b as usize)) as f64,
+                    _ => unimplemented!()
+                };
+                Ok(Value::Number(res))
+            }
+
+            (Value::String(a), Value::String(b)) => {
+                let res = match operator {
+                    Operator::Plus => {
+                        let mut base = a;
+                        base.push_str(&b);
+                        base
+                    },
+                    _ => return Err(VMError::TypeMismatch(format!("Cannot perform binary operation `{operator}` on strings!")))
+                };
+                Ok(Value::String(res))
+            }
+
+            (Value::Quantity(a), Value::Quantity(b)) => {
+                let res = match operator {
+                    Operator::Plus | Operator::Minus => {
+                        if a.dimension() != b.dimension() {
+                            return Err(
+                                VMError::DimensionMismatch(
+                                    format!("Cannot perform binary operation `{operator}` on quantities with mismatched dimensions: lhs {} and rhs {}!", a.describe(), b.describe())
+                                )
+                            );
+                        }
+                        a.add(&b, operator == Operator::Minus).map_err(VMError::DimensionMismatch)?
+                    },
+                    Operator::Multiply => a.mul(&b).map_err(VMError::ErrString)?,
+                    Operator::Divide => {
+                        if b.magnitude == 0.0 {
+                            return Err(VMError::DivisionByZero);
+                        }
+                        a.div(&b).map_err(VMError::ErrString)?
+                    },
+                    Operator::Exponent => match b.as_number() {
+                        Some(exponent) => return Self::binary(operator, Value::Quantity(a), Value::Number(exponent)),
+                        None => return Err(VMError::DimensionMismatch(format!("Cannot raise a quantity to the power of a quantity with units {}!", b.describe()))),
+                    },
+                    _ => return Err(VMError::ErrString(format!("Cannot perform binary operation `{operator}` on quantities!"))),
+                };
+                Ok(res.into_value())
+            }
+
+            (Value::Quantity(a), Value::Number(b)) => {
+                let res = match operator {
+                    Operator::Multiply => Quantity::new(a.magnitude * b, a.units),
+                    Operator::Divide => {
+                        if b == 0.0 {
+                            return Err(VMError::DivisionByZero);
+                        }
+                        Quantity::new(a.magnitude / b, a.units)
+                    },
+                    Operator::Exponent => {
+                        if b.fract() != 0.0 {
+                            return Err(VMError::ErrString(format!("Quantities can only be raised to integer powers, but the power provided was {b}!")));
+                        }
+                        // Powers that do not fit are saturated, and then rejected as too large
+                        a.powi(b as i32).map_err(VMError::ErrString)?
+                    },
+                    Operator::Plus | Operator::Minus => match a.as_number() {
+                        Some(a) => return Self::binary(operator, Value::Number(a), Value::Number(b)),
+                        None => return Err(
+                            VMError::DimensionMismatch(
+                                format!("Cannot perform binary operation `{operator}` on mismatched dimensions: lhs {} and a dimensionless rhs!", a.describe())
+                            )
+                        ),
+                    },
+                    _ => return Err(VMError::ErrString(format!("Cannot perform binary operation `{operator}` on quantities!"))),
+                };
+                Ok(res.into_value())
+            }
+
+            (Value::Number(a), Value::Quantity(b)) => {
+                let res = match operator {
+                    Operator::Multiply => Quantity::new(a * b.magnitude, b.units),
+                    Operator::Divide => {
+                        if b.magnitude == 0.0 {
+                            return Err(VMError::DivisionByZero);
+                        }
+                        Quantity::new(a / b.magnitude, b.units.powi(-1).map_err(VMError::ErrString)?)
+                    },
+                    Operator::Plus | Operator::Minus | Operator::Exponent => match b.as_number() {
+                        Some(b) => return Self::binary(operator, Value::Number(a), Value::Number(b)),
+                        None => return Err(
+                            VMError::DimensionMismatch(
+                                format!("Cannot perform binary operation `{operator}` on mismatched dimensions: a dimensionless lhs and rhs {}!", b.describe())
+                            )
+                        ),
+                    },
+                    _ => return Err(VMError::ErrString(format!("Cannot perform binary operation `{operator}` on quantities!"))),
+                };
+                Ok(res.into_value())
+            }
+
+            (a @ (Value::Date(..) | Value::Duration(..)), b) | (a, b @ (Value::Date(..) | Value::Duration(..))) => {
+                dates::binary(operator, a, b)
+            }
+
+            (a @ (Value::Vector(..) | Value::Matrix(..)), b) | (a, b @ (Value::Vector(..) | Value::Matrix(..))) => {
+                linalg::binary(operator, a, b)
+            }
+
+            (a, b) => {
+                Err(
+                    VMError::TypeMismatch(
+                        format!(
+                            "Cannot perform binary operation `{operator}` on mismatched types: lhs `{}` and rhs `{}`!", 
+                            a.type_of(), b.type_of()
+                        )
+                    )
+                )
+            }
+        }
+    }
+
     pub fn get_symbols(self) -> (HashMap<&'a str, Value>, HashMap<&'a str, Function>) {
         (self.symbols, self.functions)
     }